@@ -1,4 +1,9 @@
 use clap::Parser;
+use rust_decimal::Decimal;
+use teloxide::types::ChatId;
+use crate::enums::chart_backend::ChartBackend;
+use crate::enums::state_store::StateStoreKind;
+use crate::enums::update_mode::UpdateMode;
 
 #[derive(Parser)]
 pub struct AppConfig {
@@ -9,10 +14,73 @@ pub struct AppConfig {
     #[clap(env)]
     pub tbank_url: String,
 
+    //Only needed when STATE_STORE is redis
     #[clap(env)]
-    pub redis_url: String,
+    pub redis_url: Option<String>,
 
+    #[clap(env, value_enum, default_value_t = StateStoreKind::Redis)]
+    pub state_store: StateStoreKind,
+
+    #[clap(env, value_enum, default_value_t = ChartBackend::Local)]
+    pub chart_backend: ChartBackend,
+
+    //Only needed when CHART_BACKEND is remote
+    #[clap(env)]
+    pub chart_generator_url: Option<String>,
+
+    //TrueType font for chart labels, local charts are drawn without text when it cannot be read
+    #[clap(env, default_value = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf")]
+    pub chart_font_path: String,
+
+    //Comma separated key_id:base64_key pairs, the first one seals new credentials
+    #[clap(env)]
+    pub credential_keys: String,
+
+    //Seconds a login lasts no matter how active the chat is
+    #[clap(env, default_value_t = 28800)]
+    pub session_absolute_ttl_secs: u64,
+
+    //Seconds a login lasts without any authenticated action
+    #[clap(env, default_value_t = 900)]
+    pub session_idle_ttl_secs: u64,
+
+    //Transfers above this amount need a fresh OTP before they go through
+    #[clap(env, default_value_t = Decimal::ONE_THOUSAND)]
+    pub step_up_threshold: Decimal,
+
+    //Comma separated chat IDs allowed to use admin commands
+    #[clap(env, default_value = "")]
+    pub admin_chat_ids: String,
+
+    #[clap(env, value_enum, default_value_t = UpdateMode::Polling)]
+    pub update_mode: UpdateMode,
+
+    //Public URL Telegram posts updates to, ending in /telegram/webhook. Only needed when UPDATE_MODE is webhook
+    #[clap(env)]
+    pub webhook_url: Option<String>,
+
+    //Sent back by Telegram in X-Telegram-Bot-Api-Secret-Token, 1-256 of A-Z a-z 0-9 _ -
+    #[clap(env)]
+    pub webhook_secret: Option<String>,
+
+    //Teller login used for cash deposits and withdrawals
+    #[clap(env, default_value = "")]
+    pub teller_user_id: String,
+
+    #[clap(env, default_value = "")]
+    pub teller_pin: String,
+
+    //Bearer token for the /admin HTTP routes, which stay disabled without it
     #[clap(env)]
-    pub chart_generator_url: String,
+    pub admin_api_token: Option<String>,
+
+}
 
+impl AppConfig {
+    pub fn is_admin(&self, chat_id: ChatId) -> bool {
+        self.admin_chat_ids
+            .split(',')
+            .filter_map(|id| id.trim().parse::<i64>().ok())
+            .any(|id| id == chat_id.0)
+    }
 }
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use crate::config::AppConfig;
+use anyhow::Context;
+use crate::enums::chart_backend::ChartBackend;
+use crate::enums::state_store::StateStoreKind;
+use crate::repositories::memory_repository::MemoryRepository;
+use crate::repositories::redis_repository::RedisRepository;
+use crate::repositories::state_store::StateStore;
+use crate::repositories::tbank_repository::TBankRepository;
+use crate::services::cash_service::CashService;
+use crate::services::chart_renderer::ChartRenderer;
+use crate::services::credential_keyring::CredentialKeyring;
+use crate::services::local_chart_renderer::LocalChartRenderer;
+use crate::services::remote_chart_renderer::RemoteChartRenderer;
+use crate::services::session_service::{SessionPolicy, SessionService};
+use crate::services::statement_service::StatementService;
+
+/// Everything the handlers share, built once at startup.
+#[derive(Clone)]
+pub struct AppContext {
+    pub config: Arc<AppConfig>,
+    pub http_client: reqwest::Client,
+    pub state_store: Arc<dyn StateStore>,
+    pub tbank_repository: TBankRepository,
+    pub session_service: SessionService,
+    pub statement_service: StatementService,
+    pub cash_service: CashService,
+    pub chart_renderer: Arc<dyn ChartRenderer>,
+}
+
+impl AppContext {
+    pub async fn new(config: Arc<AppConfig>) -> anyhow::Result<Self> {
+        let http_client = reqwest::Client::new();
+        let state_store: Arc<dyn StateStore> = match config.state_store {
+            StateStoreKind::Redis => {
+                let redis_url = config.redis_url.clone().context("REDIS_URL is required for the redis state store")?;
+                Arc::new(RedisRepository::new(redis_url).await?)
+            }
+            StateStoreKind::Memory => Arc::new(MemoryRepository::new()),
+        };
+        let tbank_repository = TBankRepository::new(
+            http_client.clone(),
+            config.tbank_url.clone(),
+            config.chart_generator_url.clone().unwrap_or_default()
+        );
+        let keyring = CredentialKeyring::parse(&config.credential_keys)?;
+        let session_policy = SessionPolicy {
+            absolute_ttl_secs: config.session_absolute_ttl_secs,
+            idle_ttl_secs: config.session_idle_ttl_secs,
+        };
+        let session_service = SessionService::new(state_store.clone(), keyring, session_policy);
+        let statement_service = StatementService::new(tbank_repository.clone());
+        let cash_service = CashService::new(tbank_repository.clone(), &config.teller_user_id, &config.teller_pin);
+        let chart_renderer: Arc<dyn ChartRenderer> = match config.chart_backend {
+            ChartBackend::Remote => {
+                config.chart_generator_url.as_ref().context("CHART_GENERATOR_URL is required for the remote chart backend")?;
+                Arc::new(RemoteChartRenderer::new(tbank_repository.clone()))
+            }
+            ChartBackend::Local => Arc::new(LocalChartRenderer::new(&config.chart_font_path)),
+        };
+        Ok(Self {
+            config,
+            http_client,
+            state_store,
+            tbank_repository,
+            session_service,
+            statement_service,
+            cash_service,
+            chart_renderer,
+        })
+    }
+}
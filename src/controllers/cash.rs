@@ -0,0 +1,216 @@
+use axum::{Json, Router, routing::post, extract::{Path, State}, http::{header::AUTHORIZATION, HeaderMap, StatusCode}};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::controllers::telegram_webhook::constant_time_eq;
+use crate::enums::cash::CashMovement;
+use crate::errors::tbank_error::TBankError;
+use crate::models::money::{Currency, Money, HOME_CURRENCY};
+use crate::models::transaction::CashOrder;
+use crate::services::register_service::ServiceRegister;
+
+pub fn router() -> Router<ServiceRegister> {
+    Router::new()
+        .route("/admin/accounts/:account_id/deposit", post(deposit))
+        .route("/admin/accounts/:account_id/withdraw", post(withdraw))
+}
+
+#[derive(Deserialize)]
+pub struct CashBody {
+    //A JSON number, or a string such as "1,234.50"
+    pub amount: Value,
+    pub narrative: Option<String>,
+    //The account's currency, which the amount is counted in. SGD when left out
+    #[serde(default = "home_currency")]
+    pub currency: Currency,
+}
+
+fn home_currency() -> Currency {
+    HOME_CURRENCY
+}
+
+#[derive(Serialize)]
+pub struct CashReceipt {
+    pub account_id: String,
+    pub amount: String,
+    pub currency: Currency,
+    pub transaction_id: Option<String>,
+    pub balance_before: Option<Decimal>,
+    pub balance_after: Option<Decimal>,
+}
+
+type ApiResult = Result<Json<CashReceipt>, (StatusCode, Json<Value>)>;
+
+async fn deposit(State(services): State<ServiceRegister>, headers: HeaderMap, Path(account_id): Path<String>, Json(body): Json<CashBody>) -> ApiResult {
+    move_cash(services, headers, CashMovement::Deposit, account_id, body).await
+}
+
+async fn withdraw(State(services): State<ServiceRegister>, headers: HeaderMap, Path(account_id): Path<String>, Json(body): Json<CashBody>) -> ApiResult {
+    move_cash(services, headers, CashMovement::Withdrawal, account_id, body).await
+}
+
+async fn move_cash(services: ServiceRegister, headers: HeaderMap, movement: CashMovement, account_id: String, body: CashBody) -> ApiResult {
+    let Some(token) = &services.context.config.admin_api_token else {
+        return Err(failure(StatusCode::NOT_FOUND, "Not found"));
+    };
+    let given = headers.get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !constant_time_eq(given.as_bytes(), token.as_bytes()) {
+        warn!("Rejected a cash {} request with a bad token", movement.noun());
+        return Err(failure(StatusCode::UNAUTHORIZED, "Unauthorized"));
+    }
+    let cash_service = &services.context.cash_service;
+    if !cash_service.is_configured() {
+        return Err(failure(StatusCode::SERVICE_UNAVAILABLE, "Cash services are not set up"));
+    }
+    let amount = match &body.amount {
+        Value::Number(amount) => amount.to_string(),
+        Value::String(amount) => amount.clone(),
+        _ => return Err(failure(StatusCode::BAD_REQUEST, "The amount must be a number")),
+    };
+    let amount = Money::parse(&amount, body.currency);
+    let amount = amount.map_err(|e| failure(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let Some(order) = CashOrder::new(movement, &account_id, amount, body.narrative) else {
+        return Err(failure(StatusCode::BAD_REQUEST, "The amount must be a positive number"));
+    };
+
+    let (amount, currency) = (order.amount.amount_text(), order.amount.currency);
+    match cash_service.execute(order, "admin api").await {
+        Ok(response) => Ok(Json(CashReceipt {
+            account_id,
+            amount,
+            currency,
+            transaction_id: response.transaction_id,
+            balance_before: response.balance_before,
+            balance_after: response.balance_after,
+        })),
+        Err(e) => {
+            warn!("Cash {} for account {} failed : {}", movement.noun(), account_id, e);
+            Err(failure(status_of(&e), &e.to_string()))
+        }
+    }
+}
+
+fn status_of(e: &TBankError) -> StatusCode {
+    match e {
+        TBankError::InsufficientFunds | TBankError::UnsupportedConversion { .. } | TBankError::Rejected { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        TBankError::UnknownAccount => StatusCode::NOT_FOUND,
+        TBankError::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        // A bad teller PIN is our misconfiguration, not the caller's
+        TBankError::InvalidPin | TBankError::ExpiredOtp | TBankError::Transport(_) | TBankError::MalformedResponse(_) => StatusCode::BAD_GATEWAY,
+    }
+}
+
+fn failure(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{Body, HttpBody};
+    use axum::http::Request;
+    use tower::ServiceExt;
+    use crate::services::telegram_service::TelegramService;
+    use crate::testing::context::{test_config, test_context};
+    use crate::testing::mock_tbank::MockTBank;
+    use super::*;
+
+    const TOKEN: &str = "admin-token";
+
+    async fn app(mock: &MockTBank, token: Option<&str>) -> Router {
+        let mut config = test_config(&format!("http://{}/", mock.spawn()));
+        config.admin_api_token = token.map(str::to_owned);
+        let context = test_context(config).await;
+        let services = ServiceRegister::new(context.clone(), TelegramService::new(context), None).await;
+        router().with_state(services)
+    }
+
+    fn request(path: &str, token: Option<&str>, body: Value) -> Request<Body> {
+        let mut request = Request::post(path).header("content-type", "application/json");
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        request.body(Body::from(body.to_string())).unwrap()
+    }
+
+    async fn body(response: axum::response::Response) -> Value {
+        let mut body = response.into_body();
+        let mut bytes = vec![];
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn deposits_and_withdrawals_report_both_balances() {
+        let mock = MockTBank::new().customer("alice", "123456").account("alice", "100", "500.00").teller("teller", "4321");
+        let app = app(&mock, Some(TOKEN)).await;
+
+        let response = app.clone().oneshot(request("/admin/accounts/100/deposit", Some(TOKEN), json!({ "amount": 50 }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let receipt = body(response).await;
+        assert_eq!(receipt["balance_before"], "500.00");
+        assert_eq!(receipt["balance_after"], "550.00");
+        assert_eq!(receipt["amount"], "50.00");
+
+        let response = app.clone().oneshot(request("/admin/accounts/100/withdraw", Some(TOKEN), json!({ "amount": 600 }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = app.clone().oneshot(request("/admin/accounts/999/deposit", Some(TOKEN), json!({ "amount": 5 }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = app.oneshot(request("/admin/accounts/100/withdraw", Some(TOKEN), json!({ "amount": -5 }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(mock.balance("100"), Some(Decimal::new(55000, 2)));
+    }
+
+    #[tokio::test]
+    async fn amounts_may_be_typed_as_text() {
+        let mock = MockTBank::new().customer("alice", "123456").account("alice", "100", "500.00").teller("teller", "4321");
+        let app = app(&mock, Some(TOKEN)).await;
+
+        let response = app.clone().oneshot(request("/admin/accounts/100/deposit", Some(TOKEN), json!({ "amount": "$1,234.50" }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await["amount"], "1234.50");
+        for amount in [json!(0.125), json!("12,34"), json!(null)] {
+            let response = app.clone().oneshot(request("/admin/accounts/100/deposit", Some(TOKEN), json!({ "amount": amount }))).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", amount);
+        }
+        assert_eq!(mock.balance("100"), Some(Decimal::new(173450, 2)));
+    }
+
+    #[tokio::test]
+    async fn amounts_are_in_the_account_currency() {
+        let mock = MockTBank::new().customer("alice", "123456").account_in("alice", "102", "0", "JPY").teller("teller", "4321");
+        let app = app(&mock, Some(TOKEN)).await;
+
+        let response = app.clone().oneshot(request("/admin/accounts/102/deposit", Some(TOKEN), json!({ "amount": 1500, "currency": "JPY" }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let receipt = body(response).await;
+        assert_eq!(receipt["amount"], "1500");
+        assert_eq!(receipt["currency"], "JPY");
+        // Yen have no smaller unit
+        let response = app.clone().oneshot(request("/admin/accounts/102/deposit", Some(TOKEN), json!({ "amount": "12.50", "currency": "JPY" }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = app.oneshot(request("/admin/accounts/102/deposit", Some(TOKEN), json!({ "amount": 1500, "currency": "dollars" }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(mock.balance("102"), Some(Decimal::new(1500, 0)));
+    }
+
+    #[tokio::test]
+    async fn requests_need_the_admin_token() {
+        let mock = MockTBank::new().customer("alice", "123456").account("alice", "100", "500.00").teller("teller", "4321");
+        for token in [None, Some("wrong")] {
+            let response = app(&mock, Some(TOKEN)).await
+                .oneshot(request("/admin/accounts/100/deposit", token, json!({ "amount": 50 }))).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = app(&mock, None).await
+            .oneshot(request("/admin/accounts/100/deposit", Some(TOKEN), json!({ "amount": 50 }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(mock.balance("100"), Some(Decimal::new(50000, 2)));
+    }
+}
@@ -1,2 +1,4 @@
 pub mod server;
-pub mod health;
\ No newline at end of file
+pub mod health;
+pub mod telegram_webhook;
+pub mod cash;
\ No newline at end of file
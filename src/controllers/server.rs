@@ -1,5 +1,4 @@
 use std::net::SocketAddr;
-use std::sync::Arc;
 use anyhow::Context;
 use axum::http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, COOKIE};
 use axum::http::Method;
@@ -7,24 +6,35 @@ use axum::Router;
 use tower::ServiceBuilder;
 use tower_http::cors;
 use tower_http::cors::CorsLayer;
+use teloxide::types::Update;
+use tokio::sync::mpsc::UnboundedSender;
 use tracing::info;
-use crate::config::AppConfig;
-use crate::controllers::health;
+use crate::context::AppContext;
+use crate::controllers::{cash, health, telegram_webhook};
 use crate::services::register_service::ServiceRegister;
 use crate::services::telegram_service::TelegramService;
 
 pub async fn serve(
-    config: Arc<AppConfig>,
-    telegram_service: TelegramService
+    context: AppContext,
+    telegram_service: TelegramService,
+    telegram_updates: Option<UnboundedSender<Update>>,
 ) -> anyhow::Result<()> {
+    let webhook_enabled = telegram_updates.is_some();
     // Register Services to be used in handlers
     let services = ServiceRegister::new(
-        config.clone(),
-        telegram_service
+        context,
+        telegram_service,
+        telegram_updates,
     ).await;
 
-    let app = Router::new()
+    let mut routes = Router::new()
         .nest("/", health::router())
+        .merge(cash::router());
+    if webhook_enabled {
+        routes = routes.merge(telegram_webhook::router());
+    }
+
+    let app = routes
         .with_state(services) // Inject services into handlers as state
         .layer(
             ServiceBuilder::new().layer(
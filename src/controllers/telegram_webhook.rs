@@ -0,0 +1,121 @@
+use axum::{Router, routing::post, extract::State, http::{HeaderMap, StatusCode}};
+use teloxide::types::Update;
+use tracing::{error, warn};
+
+use crate::services::register_service::ServiceRegister;
+
+pub const WEBHOOK_PATH: &str = "/telegram/webhook";
+const SECRET_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+pub fn router() -> Router<ServiceRegister> {
+    Router::new().route(WEBHOOK_PATH, post(receive_update))
+}
+
+async fn receive_update(
+    State(services): State<ServiceRegister>,
+    headers: HeaderMap,
+    body: String,
+) -> StatusCode {
+    let (Some(updates), Some(secret)) = (&services.telegram_updates, &services.context.config.webhook_secret) else {
+        return StatusCode::NOT_FOUND;
+    };
+    let given = headers.get(SECRET_HEADER).map(|value| value.as_bytes()).unwrap_or_default();
+    if !constant_time_eq(given, secret.as_bytes()) {
+        warn!("Rejected a webhook request with a bad secret token");
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    match serde_json::from_str::<Update>(&body) {
+        Ok(update) => {
+            if updates.send(update).is_err() {
+                return StatusCode::SERVICE_UNAVAILABLE;
+            }
+        }
+        // Telegram retries anything but a 2xx, so an update we cannot read is logged and dropped
+        Err(e) => error!("Could not parse a webhook update : {} - {}", e, body),
+    }
+    StatusCode::OK
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::Request;
+    use serde_json::json;
+    use tokio::sync::mpsc::{self, UnboundedReceiver};
+    use tower::ServiceExt;
+    use crate::enums::update_mode::UpdateMode;
+    use crate::services::telegram_service::TelegramService;
+    use crate::testing::context::{test_config, test_context};
+    use super::*;
+
+    const SECRET: &str = "s3cret_token-1";
+
+    async fn app() -> (Router, UnboundedReceiver<Update>) {
+        let mut config = test_config("http://127.0.0.1:9/");
+        config.update_mode = UpdateMode::Webhook;
+        config.webhook_secret = Some(SECRET.to_owned());
+        let context = test_context(config).await;
+        let (sender, updates) = mpsc::unbounded_channel();
+        let services = ServiceRegister::new(context.clone(), TelegramService::new(context), Some(sender)).await;
+        (router().with_state(services), updates)
+    }
+
+    fn update() -> String {
+        json!({
+            "update_id": 7,
+            "message": {
+                "message_id": 1,
+                "date": 0,
+                "chat": { "id": 42, "type": "private", "first_name": "Tester" },
+                "from": { "id": 42, "is_bot": false, "first_name": "Tester" },
+                "text": "/start",
+            },
+        }).to_string()
+    }
+
+    fn request(secret: Option<&str>, body: String) -> Request<Body> {
+        let mut request = Request::post(WEBHOOK_PATH).header("content-type", "application/json");
+        if let Some(secret) = secret {
+            request = request.header(SECRET_HEADER, secret);
+        }
+        request.body(Body::from(body)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn updates_with_the_secret_reach_the_dispatcher() {
+        let (app, mut updates) = app().await;
+        let response = app.oneshot(request(Some(SECRET), update())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(updates.try_recv().unwrap().id, 7);
+    }
+
+    #[tokio::test]
+    async fn updates_without_the_secret_are_rejected() {
+        for secret in [None, Some("wrong"), Some("s3cret_token-")] {
+            let (app, mut updates) = app().await;
+            let response = app.oneshot(request(secret, update())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert!(updates.try_recv().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn unreadable_updates_are_acknowledged_and_dropped() {
+        let (app, mut updates) = app().await;
+        let response = app.oneshot(request(Some(SECRET), "{}".to_owned())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(updates.try_recv().is_err());
+    }
+
+    #[test]
+    fn secrets_compare_by_content() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+}
@@ -1,13 +1,15 @@
+use std::fmt;
+
 pub enum BeneficiaryEnum {
     OWN,
     OTHER
 }
 
-impl BeneficiaryEnum {
-    pub fn to_string(&self) -> String {
+impl fmt::Display for BeneficiaryEnum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
-            BeneficiaryEnum::OWN => "OWN".to_string(),
-            BeneficiaryEnum::OTHER => "OTHER".to_string()
+            BeneficiaryEnum::OWN => write!(f, "OWN"),
+            BeneficiaryEnum::OTHER => write!(f, "OTHER")
         }
     }
 }
@@ -0,0 +1,335 @@
+use thiserror::Error;
+use crate::models::chart::CHART_WINDOWS;
+
+/// Bumped whenever the encoding changes, so buttons on old messages are rejected instead of misrouted.
+pub const CALLBACK_VERSION: &str = "1";
+/// Telegram rejects `callback_data` longer than this many bytes.
+pub const MAX_CALLBACK_DATA_LEN: usize = 64;
+const SEPARATOR: char = ':';
+
+/// What an inline keyboard button does, independent of the text shown on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallbackAction {
+    Login,
+    Register,
+    Cancel,
+    Logout,
+    Back,
+    CheckBalance,
+    BalanceHistory { account_id: String },
+    /// `months` is one of `CHART_WINDOWS`.
+    ChartWindow { account_id: String, months: u8 },
+    CompareAccounts { months: u8 },
+    /// `page` starts at 1.
+    RecentTransactions { account_id: String, page: u32 },
+    DownloadStatement { account_id: String },
+    Transfer,
+    TransferOwn,
+    TransferOther,
+    TransferTo { account_id: String },
+    TransferFrom { account_id: String },
+    AddBeneficiary,
+    ManagePayees,
+    ViewPayee { beneficiary_id: String },
+    RenamePayee { beneficiary_id: String },
+    DeletePayee { beneficiary_id: String },
+    ConfirmDeletePayee { beneficiary_id: String },
+    Confirm,
+    ConfirmCash,
+    ConfirmRegistration,
+    EnableMicroInvest,
+    UpdateMicroInvest,
+    CreateInvestAccount,
+    SelectInvestAccount { account_id: String },
+    ReselectInvestAccount,
+    RemoveInvestAccount,
+    InvestPercentage(u8),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CallbackDataError {
+    #[error("callback data version {0:?} is not supported")]
+    UnsupportedVersion(String),
+    #[error("unknown callback action {0:?}")]
+    UnknownAction(String),
+    #[error("callback action {0} is missing its argument")]
+    MissingArgument(&'static str),
+    #[error("{0:?} is not a valid percentage")]
+    InvalidPercentage(String),
+    #[error("{0:?} is not a valid page")]
+    InvalidPage(String),
+    #[error("{0:?} is not a chart window")]
+    InvalidChartWindow(String),
+    #[error("callback data is {0} bytes, over the {MAX_CALLBACK_DATA_LEN} byte limit")]
+    TooLong(usize),
+}
+
+impl CallbackAction {
+    /// Default English text for the button.
+    pub fn label(&self) -> String {
+        match self {
+            CallbackAction::Login => "Login".to_owned(),
+            CallbackAction::Register => "Register".to_owned(),
+            CallbackAction::Cancel => "Cancel".to_owned(),
+            CallbackAction::Logout => "Logout".to_owned(),
+            CallbackAction::Back => "Back".to_owned(),
+            CallbackAction::CheckBalance => "Check Balance".to_owned(),
+            CallbackAction::BalanceHistory { account_id } => format!("View Account {} Balance History", account_id),
+            CallbackAction::ChartWindow { months, .. } => format!("{} months", months),
+            CallbackAction::CompareAccounts { .. } => "Compare All Accounts".to_owned(),
+            CallbackAction::RecentTransactions { account_id, .. } => format!("Account {} Recent Transactions", account_id),
+            CallbackAction::DownloadStatement { account_id } => format!("Download Account {} Statement", account_id),
+            CallbackAction::Transfer => "Transfer".to_owned(),
+            CallbackAction::TransferOwn => "Between my accounts".to_owned(),
+            CallbackAction::TransferOther => "To someone else".to_owned(),
+            CallbackAction::TransferTo { account_id } => format!("Transfer To {}", account_id),
+            CallbackAction::TransferFrom { account_id } => format!("Transfer From {}", account_id),
+            CallbackAction::AddBeneficiary => "Add Beneficiary".to_owned(),
+            CallbackAction::ManagePayees => "Manage Payees".to_owned(),
+            CallbackAction::ViewPayee { beneficiary_id } => format!("Payee {}", beneficiary_id),
+            CallbackAction::RenamePayee { .. } => "Rename".to_owned(),
+            CallbackAction::DeletePayee { .. } => "Delete".to_owned(),
+            CallbackAction::ConfirmDeletePayee { .. } => "Yes, delete".to_owned(),
+            CallbackAction::Confirm => "Confirm".to_owned(),
+            CallbackAction::ConfirmCash => "Confirm".to_owned(),
+            CallbackAction::ConfirmRegistration => "Confirm".to_owned(),
+            CallbackAction::EnableMicroInvest => "Enable MicroInvest".to_owned(),
+            CallbackAction::UpdateMicroInvest => "Update MicroInvest".to_owned(),
+            CallbackAction::CreateInvestAccount => "Create".to_owned(),
+            CallbackAction::SelectInvestAccount { account_id } => format!("Account: {}", account_id),
+            CallbackAction::ReselectInvestAccount => "Reselect account".to_owned(),
+            CallbackAction::RemoveInvestAccount => "Remove Account".to_owned(),
+            CallbackAction::InvestPercentage(percentage) => format!("{}%", percentage),
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            CallbackAction::Login => "li",
+            CallbackAction::Register => "rg",
+            CallbackAction::Cancel => "ca",
+            CallbackAction::Logout => "lo",
+            CallbackAction::Back => "bk",
+            CallbackAction::CheckBalance => "cb",
+            CallbackAction::BalanceHistory { .. } => "bh",
+            CallbackAction::ChartWindow { .. } => "cw",
+            CallbackAction::CompareAccounts { .. } => "cm",
+            CallbackAction::RecentTransactions { .. } => "rt",
+            CallbackAction::DownloadStatement { .. } => "ds",
+            CallbackAction::Transfer => "tr",
+            CallbackAction::TransferOwn => "to",
+            CallbackAction::TransferOther => "tx",
+            CallbackAction::TransferTo { .. } => "tt",
+            CallbackAction::TransferFrom { .. } => "tf",
+            CallbackAction::AddBeneficiary => "ab",
+            CallbackAction::ManagePayees => "py",
+            CallbackAction::ViewPayee { .. } => "pv",
+            CallbackAction::RenamePayee { .. } => "pr",
+            CallbackAction::DeletePayee { .. } => "pd",
+            CallbackAction::ConfirmDeletePayee { .. } => "px",
+            CallbackAction::Confirm => "cf",
+            CallbackAction::ConfirmCash => "cc",
+            CallbackAction::ConfirmRegistration => "cr",
+            CallbackAction::EnableMicroInvest => "me",
+            CallbackAction::UpdateMicroInvest => "mu",
+            CallbackAction::CreateInvestAccount => "mc",
+            CallbackAction::SelectInvestAccount { .. } => "ms",
+            CallbackAction::ReselectInvestAccount => "mr",
+            CallbackAction::RemoveInvestAccount => "mx",
+            CallbackAction::InvestPercentage(_) => "mp",
+        }
+    }
+
+    /// Encodes as `<version>:<code>[:<argument>]`. The argument is always last, so it may contain anything.
+    pub fn encode(&self) -> Result<String, CallbackDataError> {
+        let argument = match self {
+            CallbackAction::BalanceHistory { account_id }
+            | CallbackAction::TransferTo { account_id }
+            | CallbackAction::TransferFrom { account_id }
+            | CallbackAction::DownloadStatement { account_id }
+            | CallbackAction::SelectInvestAccount { account_id } => Some(account_id.clone()),
+            CallbackAction::ViewPayee { beneficiary_id }
+            | CallbackAction::RenamePayee { beneficiary_id }
+            | CallbackAction::DeletePayee { beneficiary_id }
+            | CallbackAction::ConfirmDeletePayee { beneficiary_id } => Some(beneficiary_id.clone()),
+            CallbackAction::InvestPercentage(percentage) => Some(percentage.to_string()),
+            CallbackAction::RecentTransactions { account_id, page } => Some(format!("{}{}{}", page, SEPARATOR, account_id)),
+            CallbackAction::ChartWindow { account_id, months } => Some(format!("{}{}{}", months, SEPARATOR, account_id)),
+            CallbackAction::CompareAccounts { months } => Some(months.to_string()),
+            _ => None,
+        };
+        let data = match argument {
+            Some(argument) => format!("{}{}{}{}{}", CALLBACK_VERSION, SEPARATOR, self.code(), SEPARATOR, argument),
+            None => format!("{}{}{}", CALLBACK_VERSION, SEPARATOR, self.code()),
+        };
+        if data.len() > MAX_CALLBACK_DATA_LEN {
+            return Err(CallbackDataError::TooLong(data.len()));
+        }
+        Ok(data)
+    }
+
+    pub fn decode(data: &str) -> Result<Self, CallbackDataError> {
+        let mut parts = data.splitn(3, SEPARATOR);
+        let version = parts.next().unwrap_or_default();
+        if version != CALLBACK_VERSION {
+            return Err(CallbackDataError::UnsupportedVersion(version.to_owned()));
+        }
+        let code = parts.next().unwrap_or_default();
+        let argument = parts.next().filter(|argument| !argument.is_empty());
+        let required = |name: &'static str| {
+            argument.map(str::to_owned).ok_or(CallbackDataError::MissingArgument(name))
+        };
+        let chart_window = |months: &str| match months.parse::<u8>() {
+            Ok(months) if CHART_WINDOWS.contains(&months) => Ok(months),
+            _ => Err(CallbackDataError::InvalidChartWindow(months.to_owned())),
+        };
+
+        let action = match code {
+            "li" => CallbackAction::Login,
+            "rg" => CallbackAction::Register,
+            "ca" => CallbackAction::Cancel,
+            "lo" => CallbackAction::Logout,
+            "bk" => CallbackAction::Back,
+            "cb" => CallbackAction::CheckBalance,
+            "bh" => CallbackAction::BalanceHistory { account_id: required("BalanceHistory")? },
+            "rt" => {
+                let argument = argument.ok_or(CallbackDataError::MissingArgument("RecentTransactions"))?;
+                let (page, account_id) = argument.split_once(SEPARATOR)
+                    .filter(|(_, account_id)| !account_id.is_empty())
+                    .ok_or(CallbackDataError::MissingArgument("RecentTransactions"))?;
+                match page.parse::<u32>() {
+                    Ok(page) if page > 0 => CallbackAction::RecentTransactions { account_id: account_id.to_owned(), page },
+                    _ => return Err(CallbackDataError::InvalidPage(page.to_owned())),
+                }
+            }
+            "cw" => {
+                let argument = argument.ok_or(CallbackDataError::MissingArgument("ChartWindow"))?;
+                let (months, account_id) = argument.split_once(SEPARATOR)
+                    .filter(|(_, account_id)| !account_id.is_empty())
+                    .ok_or(CallbackDataError::MissingArgument("ChartWindow"))?;
+                CallbackAction::ChartWindow { account_id: account_id.to_owned(), months: chart_window(months)? }
+            }
+            "cm" => CallbackAction::CompareAccounts { months: chart_window(&required("CompareAccounts")?)? },
+            "ds" => CallbackAction::DownloadStatement { account_id: required("DownloadStatement")? },
+            "tr" => CallbackAction::Transfer,
+            "to" => CallbackAction::TransferOwn,
+            "tx" => CallbackAction::TransferOther,
+            "tt" => CallbackAction::TransferTo { account_id: required("TransferTo")? },
+            "tf" => CallbackAction::TransferFrom { account_id: required("TransferFrom")? },
+            "ab" => CallbackAction::AddBeneficiary,
+            "py" => CallbackAction::ManagePayees,
+            "pv" => CallbackAction::ViewPayee { beneficiary_id: required("ViewPayee")? },
+            "pr" => CallbackAction::RenamePayee { beneficiary_id: required("RenamePayee")? },
+            "pd" => CallbackAction::DeletePayee { beneficiary_id: required("DeletePayee")? },
+            "px" => CallbackAction::ConfirmDeletePayee { beneficiary_id: required("ConfirmDeletePayee")? },
+            "cf" => CallbackAction::Confirm,
+            "cc" => CallbackAction::ConfirmCash,
+            "cr" => CallbackAction::ConfirmRegistration,
+            "me" => CallbackAction::EnableMicroInvest,
+            "mu" => CallbackAction::UpdateMicroInvest,
+            "mc" => CallbackAction::CreateInvestAccount,
+            "ms" => CallbackAction::SelectInvestAccount { account_id: required("SelectInvestAccount")? },
+            "mr" => CallbackAction::ReselectInvestAccount,
+            "mx" => CallbackAction::RemoveInvestAccount,
+            "mp" => {
+                let percentage = argument.ok_or(CallbackDataError::MissingArgument("InvestPercentage"))?;
+                match percentage.parse::<u8>() {
+                    Ok(value) if value <= 100 => CallbackAction::InvestPercentage(value),
+                    _ => return Err(CallbackDataError::InvalidPercentage(percentage.to_owned())),
+                }
+            }
+            other => return Err(CallbackDataError::UnknownAction(other.to_owned())),
+        };
+        Ok(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(action: CallbackAction) {
+        let data = action.encode().unwrap();
+        assert!(data.len() <= MAX_CALLBACK_DATA_LEN);
+        assert_eq!(CallbackAction::decode(&data).unwrap(), action);
+    }
+
+    #[test]
+    fn every_action_round_trips() {
+        let account_id = || "0000002134".to_owned();
+        for action in [
+            CallbackAction::Login,
+            CallbackAction::Register,
+            CallbackAction::Cancel,
+            CallbackAction::Logout,
+            CallbackAction::Back,
+            CallbackAction::CheckBalance,
+            CallbackAction::BalanceHistory { account_id: account_id() },
+            CallbackAction::ChartWindow { account_id: account_id(), months: 24 },
+            CallbackAction::CompareAccounts { months: 3 },
+            CallbackAction::RecentTransactions { account_id: account_id(), page: 3 },
+            CallbackAction::DownloadStatement { account_id: account_id() },
+            CallbackAction::Transfer,
+            CallbackAction::TransferOwn,
+            CallbackAction::TransferOther,
+            CallbackAction::TransferTo { account_id: account_id() },
+            CallbackAction::TransferFrom { account_id: account_id() },
+            CallbackAction::AddBeneficiary,
+            CallbackAction::ManagePayees,
+            CallbackAction::ViewPayee { beneficiary_id: "12".to_owned() },
+            CallbackAction::RenamePayee { beneficiary_id: "12".to_owned() },
+            CallbackAction::DeletePayee { beneficiary_id: "12".to_owned() },
+            CallbackAction::ConfirmDeletePayee { beneficiary_id: "12".to_owned() },
+            CallbackAction::Confirm,
+            CallbackAction::ConfirmCash,
+            CallbackAction::ConfirmRegistration,
+            CallbackAction::EnableMicroInvest,
+            CallbackAction::UpdateMicroInvest,
+            CallbackAction::CreateInvestAccount,
+            CallbackAction::SelectInvestAccount { account_id: account_id() },
+            CallbackAction::ReselectInvestAccount,
+            CallbackAction::RemoveInvestAccount,
+            CallbackAction::InvestPercentage(5),
+        ] {
+            round_trip(action);
+        }
+    }
+
+    #[test]
+    fn account_ids_with_spaces_and_keywords_are_kept_intact() {
+        round_trip(CallbackAction::TransferTo { account_id: "Account 12 Balance History".to_owned() });
+        round_trip(CallbackAction::TransferFrom { account_id: "a:b:c".to_owned() });
+        round_trip(CallbackAction::RecentTransactions { account_id: "a:b:c".to_owned(), page: 12 });
+    }
+
+    #[test]
+    fn labels_are_not_callback_data() {
+        assert!(CallbackAction::decode("Transfer To Mum 0000002134").is_err());
+        assert!(CallbackAction::decode("5%").is_err());
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        assert_eq!(
+            CallbackAction::decode("0:li"),
+            Err(CallbackDataError::UnsupportedVersion("0".to_owned()))
+        );
+    }
+
+    #[test]
+    fn malformed_data_is_rejected() {
+        assert_eq!(CallbackAction::decode("1:zz"), Err(CallbackDataError::UnknownAction("zz".to_owned())));
+        assert_eq!(CallbackAction::decode("1:tt"), Err(CallbackDataError::MissingArgument("TransferTo")));
+        assert_eq!(CallbackAction::decode("1:mp:250"), Err(CallbackDataError::InvalidPercentage("250".to_owned())));
+        assert_eq!(CallbackAction::decode("1:rt:2"), Err(CallbackDataError::MissingArgument("RecentTransactions")));
+        assert_eq!(CallbackAction::decode("1:rt:0:100"), Err(CallbackDataError::InvalidPage("0".to_owned())));
+        assert_eq!(CallbackAction::decode("1:cw:7:100"), Err(CallbackDataError::InvalidChartWindow("7".to_owned())));
+        assert_eq!(CallbackAction::decode("1:cm:x"), Err(CallbackDataError::InvalidChartWindow("x".to_owned())));
+    }
+
+    #[test]
+    fn oversized_data_is_refused() {
+        let action = CallbackAction::TransferTo { account_id: "9".repeat(MAX_CALLBACK_DATA_LEN) };
+        assert!(matches!(action.encode(), Err(CallbackDataError::TooLong(_))));
+    }
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// Which way cash moves across the counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CashMovement {
+    Deposit,
+    Withdrawal,
+}
+
+impl CashMovement {
+    pub fn service_name(&self) -> &'static str {
+        match self {
+            CashMovement::Deposit => "depositCash",
+            CashMovement::Withdrawal => "withdrawCash",
+        }
+    }
+
+    pub fn noun(&self) -> &'static str {
+        match self {
+            CashMovement::Deposit => "deposit",
+            CashMovement::Withdrawal => "withdrawal",
+        }
+    }
+}
@@ -0,0 +1,10 @@
+use clap::ValueEnum;
+
+/// What draws the balance history charts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChartBackend {
+    /// Drawn in process, so charts keep working without any other service.
+    Local,
+    /// Posted to the chart generator at `CHART_GENERATOR_URL`.
+    Remote,
+}
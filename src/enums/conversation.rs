@@ -0,0 +1,542 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::enums::cash::CashMovement;
+use crate::models::CustomerRequest;
+use crate::models::authentication::RequestOTP;
+use crate::models::money::{Currency, Money};
+use crate::models::registration::{Registration, RegistrationField};
+use crate::models::statement::StatementPeriod;
+use crate::models::transaction::{AddBeneficiaryBody, CashOrder, TransferBody, UpdateBeneficiaryBody};
+
+/// Where a chat is in a multi-step flow. Persisted as one JSON value per chat.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(tag = "state", content = "data")]
+pub enum ConversationState {
+    #[default]
+    Idle,
+    AwaitingUsername,
+    AwaitingPin { request: RequestOTP },
+    AwaitingOtp { request: CustomerRequest },
+    AwaitingAmount { transfer: TransferBody },
+    SelectingSourceAccount { transfer: TransferBody },
+    ConfirmingTransfer { transfer: TransferBody },
+    AwaitingTransferOtp { transfer: TransferBody },
+    AwaitingBeneficiaryAccount,
+    AwaitingBeneficiaryDescription { beneficiary: AddBeneficiaryBody },
+    AwaitingPayeeName { beneficiary_id: String },
+    AwaitingStatementPeriod { account_id: String },
+    AwaitingCashAccount { movement: CashMovement },
+    AwaitingCashCurrency { movement: CashMovement, account_id: String },
+    /// `currency` is the account's, as the teller keyed it in, which the cash is counted in.
+    AwaitingCashAmount { movement: CashMovement, account_id: String, currency: Currency },
+    ConfirmingCash { order: CashOrder },
+    Registering { field: RegistrationField, registration: Registration },
+    ReviewingRegistration { registration: Registration },
+}
+
+/// Something the user did that may move the conversation forward.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConversationEvent {
+    StartLogin,
+    Text(String),
+    /// `currency` is the destination account's, which the amount is keyed in.
+    StartTransfer { account_to: String, currency: Currency, reference: String },
+    SelectSourceAccount { account_from: String },
+    /// `step_up` is set when the transfer is large enough to need a fresh OTP.
+    Confirm { step_up: bool },
+    StartAddBeneficiary,
+    StartRenamePayee { beneficiary_id: String },
+    StartStatement { account_id: String },
+    StartCash { movement: CashMovement },
+    StartRegistration,
+    Reset,
+}
+
+/// Work the handler has to do once the next state is known.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    None,
+    AskPin,
+    RequestOtp(RequestOTP),
+    Login(CustomerRequest),
+    ChooseSourceAccount(TransferBody),
+    ShowSummary(TransferBody),
+    Transfer(TransferBody),
+    RequestTransferOtp(TransferBody),
+    TransferWithOtp { transfer: TransferBody, otp: String },
+    /// Asked again when the account number typed in was not valid.
+    AskBeneficiaryAccount { problem: String },
+    AskBeneficiaryDescription { problem: Option<String> },
+    AddBeneficiary(AddBeneficiaryBody),
+    RenamePayee(UpdateBeneficiaryBody),
+    SendStatement(StatementPeriod),
+    /// TBank has no teller lookup for an account's currency, so the teller is asked for it.
+    AskCashCurrency { account_id: String },
+    AskCashAmount { movement: CashMovement, currency: Currency },
+    ShowCashSummary(CashOrder),
+    MoveCash(CashOrder),
+    /// `problem` explains why the last answer to `field` was not accepted.
+    AskRegistrationField { field: RegistrationField, problem: Option<String> },
+    ShowRegistration(Registration),
+    Onboard(Registration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub next: ConversationState,
+    pub effect: Effect,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum InvalidTransition {
+    #[error("{event} is not expected while {state}")]
+    Unexpected { state: &'static str, event: &'static str },
+    #[error("{0} is not a valid amount")]
+    InvalidAmount(String),
+    #[error("{0} is not a currency code")]
+    InvalidCurrency(String),
+    #[error("{0} is not a valid statement period")]
+    InvalidStatementPeriod(String),
+}
+
+impl ConversationState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConversationState::Idle => "Idle",
+            ConversationState::AwaitingUsername => "AwaitingUsername",
+            ConversationState::AwaitingPin { .. } => "AwaitingPin",
+            ConversationState::AwaitingOtp { .. } => "AwaitingOtp",
+            ConversationState::AwaitingAmount { .. } => "AwaitingAmount",
+            ConversationState::SelectingSourceAccount { .. } => "SelectingSourceAccount",
+            ConversationState::ConfirmingTransfer { .. } => "ConfirmingTransfer",
+            ConversationState::AwaitingTransferOtp { .. } => "AwaitingTransferOtp",
+            ConversationState::AwaitingBeneficiaryAccount => "AwaitingBeneficiaryAccount",
+            ConversationState::AwaitingBeneficiaryDescription { .. } => "AwaitingBeneficiaryDescription",
+            ConversationState::AwaitingPayeeName { .. } => "AwaitingPayeeName",
+            ConversationState::AwaitingStatementPeriod { .. } => "AwaitingStatementPeriod",
+            ConversationState::AwaitingCashAccount { .. } => "AwaitingCashAccount",
+            ConversationState::AwaitingCashCurrency { .. } => "AwaitingCashCurrency",
+            ConversationState::AwaitingCashAmount { .. } => "AwaitingCashAmount",
+            ConversationState::ConfirmingCash { .. } => "ConfirmingCash",
+            ConversationState::Registering { .. } => "Registering",
+            ConversationState::ReviewingRegistration { .. } => "ReviewingRegistration",
+        }
+    }
+
+    /// Login and OTP steps only live for a short while; a pending step-up transfer is dropped with them.
+    /// So is a half filled registration, which holds personal details.
+    pub fn expires(&self) -> bool {
+        matches!(
+            self,
+            ConversationState::AwaitingUsername
+                | ConversationState::AwaitingPin { .. }
+                | ConversationState::AwaitingOtp { .. }
+                | ConversationState::AwaitingTransferOtp { .. }
+                | ConversationState::Registering { .. }
+                | ConversationState::ReviewingRegistration { .. }
+        )
+    }
+
+    pub fn transition(self, event: ConversationEvent) -> Result<Transition, InvalidTransition> {
+        use ConversationEvent as E;
+        use ConversationState as S;
+
+        let (next, effect) = match (self, event) {
+            (_, E::Reset) => (S::Idle, Effect::None),
+            (_, E::StartLogin) => (S::AwaitingUsername, Effect::None),
+            (_, E::StartAddBeneficiary) => (S::AwaitingBeneficiaryAccount, Effect::None),
+            (_, E::StartRenamePayee { beneficiary_id }) => (S::AwaitingPayeeName { beneficiary_id }, Effect::None),
+            (_, E::StartStatement { account_id }) => (S::AwaitingStatementPeriod { account_id }, Effect::None),
+            (_, E::StartCash { movement }) => (S::AwaitingCashAccount { movement }, Effect::None),
+            (_, E::StartRegistration) => {
+                let field = RegistrationField::Name;
+                (S::Registering { field, registration: Registration::default() }, Effect::AskRegistrationField { field, problem: None })
+            }
+            (_, E::StartTransfer { account_to, currency, reference }) => {
+                let transfer = TransferBody {
+                    account_from: "".to_owned(),
+                    account_to,
+                    transaction_amount: Money::zero(currency),
+                    transaction_reference_number: reference,
+                    narrative: "".to_owned(),
+                };
+                (S::AwaitingAmount { transfer }, Effect::None)
+            }
+            (S::AwaitingUsername, E::Text(user_id)) => {
+                let request = RequestOTP {
+                    service_name: "requestOTP".to_owned(),
+                    user_id: user_id.trim().to_owned(),
+                    pin: "".to_owned(),
+                };
+                (S::AwaitingPin { request }, Effect::AskPin)
+            }
+            (S::AwaitingPin { mut request }, E::Text(pin)) => {
+                request.pin = pin.trim().to_owned();
+                // The PIN is held sealed by the session service, never in the conversation
+                let login = CustomerRequest {
+                    service_name: "loginCustomer".to_owned(),
+                    user_id: request.user_id.clone(),
+                    pin: "".to_owned(),
+                    otp: "".to_owned(),
+                };
+                (S::AwaitingOtp { request: login }, Effect::RequestOtp(request))
+            }
+            (S::AwaitingOtp { mut request }, E::Text(otp)) => {
+                request.otp = otp.trim().to_owned();
+                (S::Idle, Effect::Login(request))
+            }
+            (S::AwaitingAmount { mut transfer }, E::Text(text)) => {
+                match Money::parse(&text, transfer.transaction_amount.currency) {
+                    Ok(amount) => {
+                        transfer.transaction_amount = amount.rounded();
+                        (S::SelectingSourceAccount { transfer: transfer.clone() }, Effect::ChooseSourceAccount(transfer))
+                    }
+                    _ => return Err(InvalidTransition::InvalidAmount(text)),
+                }
+            }
+            (S::SelectingSourceAccount { mut transfer }, E::SelectSourceAccount { account_from }) => {
+                transfer.account_from = account_from;
+                transfer.narrative = "Simple Transfer".to_owned();
+                (S::ConfirmingTransfer { transfer: transfer.clone() }, Effect::ShowSummary(transfer))
+            }
+            (S::ConfirmingTransfer { transfer }, E::Confirm { step_up: false }) => (S::Idle, Effect::Transfer(transfer)),
+            (S::ConfirmingTransfer { transfer }, E::Confirm { step_up: true }) => {
+                (S::AwaitingTransferOtp { transfer: transfer.clone() }, Effect::RequestTransferOtp(transfer))
+            }
+            (S::AwaitingTransferOtp { transfer }, E::Text(otp)) => {
+                (S::Idle, Effect::TransferWithOtp { transfer, otp: otp.trim().to_owned() })
+            }
+            (S::AwaitingBeneficiaryAccount, E::Text(text)) => match AddBeneficiaryBody::parse_account_id(&text) {
+                Ok(account_id) => {
+                    let beneficiary = AddBeneficiaryBody {
+                        account_id,
+                        description: "".to_owned(),
+                    };
+                    (S::AwaitingBeneficiaryDescription { beneficiary }, Effect::AskBeneficiaryDescription { problem: None })
+                }
+                Err(e) => (S::AwaitingBeneficiaryAccount, Effect::AskBeneficiaryAccount { problem: e.to_string() }),
+            },
+            (S::AwaitingBeneficiaryDescription { mut beneficiary }, E::Text(text)) => match AddBeneficiaryBody::parse_description(&text) {
+                Ok(description) => {
+                    beneficiary.description = description;
+                    (S::Idle, Effect::AddBeneficiary(beneficiary))
+                }
+                Err(e) => {
+                    let problem = Some(e.to_string());
+                    (S::AwaitingBeneficiaryDescription { beneficiary }, Effect::AskBeneficiaryDescription { problem })
+                }
+            },
+            (S::AwaitingPayeeName { beneficiary_id }, E::Text(text)) => match AddBeneficiaryBody::parse_description(&text) {
+                Ok(description) => (S::Idle, Effect::RenamePayee(UpdateBeneficiaryBody { beneficiary_id, description })),
+                Err(e) => {
+                    let problem = Some(e.to_string());
+                    (S::AwaitingPayeeName { beneficiary_id }, Effect::AskBeneficiaryDescription { problem })
+                }
+            },
+            (S::AwaitingStatementPeriod { account_id }, E::Text(text)) => {
+                match StatementPeriod::parse(&account_id, &text) {
+                    Some(period) => (S::Idle, Effect::SendStatement(period)),
+                    None => return Err(InvalidTransition::InvalidStatementPeriod(text)),
+                }
+            }
+            (S::AwaitingCashAccount { movement }, E::Text(account_id)) => {
+                let account_id = account_id.trim().to_owned();
+                (S::AwaitingCashCurrency { movement, account_id: account_id.clone() }, Effect::AskCashCurrency { account_id })
+            }
+            (S::AwaitingCashCurrency { movement, account_id }, E::Text(code)) => {
+                let currency: Currency = code.parse().map_err(|_| InvalidTransition::InvalidCurrency(code.trim().to_owned()))?;
+                (S::AwaitingCashAmount { movement, account_id, currency }, Effect::AskCashAmount { movement, currency })
+            }
+            (S::AwaitingCashAmount { movement, account_id, currency }, E::Text(text)) => {
+                let order = Money::parse(&text, currency).ok()
+                    .and_then(|amount| CashOrder::new(movement, &account_id, amount, None));
+                match order {
+                    Some(order) => (S::ConfirmingCash { order: order.clone() }, Effect::ShowCashSummary(order)),
+                    None => return Err(InvalidTransition::InvalidAmount(text)),
+                }
+            }
+            (S::ConfirmingCash { order }, E::Confirm { .. }) => (S::Idle, Effect::MoveCash(order)),
+            (S::Registering { field, mut registration }, E::Text(text)) => {
+                // A bad answer asks the same question again instead of abandoning the form
+                match (registration.fill(field, &text), field.next()) {
+                    (Err(e), _) => {
+                        let problem = Some(e.to_string());
+                        (S::Registering { field, registration }, Effect::AskRegistrationField { field, problem })
+                    }
+                    (Ok(()), Some(field)) => (S::Registering { field, registration }, Effect::AskRegistrationField { field, problem: None }),
+                    (Ok(()), None) => (S::ReviewingRegistration { registration: registration.clone() }, Effect::ShowRegistration(registration)),
+                }
+            }
+            (S::ReviewingRegistration { registration }, E::Confirm { .. }) => (S::Idle, Effect::Onboard(registration)),
+            (state, event) => {
+                return Err(InvalidTransition::Unexpected {
+                    state: state.name(),
+                    event: event.name(),
+                })
+            }
+        };
+        Ok(Transition { next, effect })
+    }
+}
+
+impl ConversationEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConversationEvent::StartLogin => "StartLogin",
+            ConversationEvent::Text(_) => "Text",
+            ConversationEvent::StartTransfer { .. } => "StartTransfer",
+            ConversationEvent::SelectSourceAccount { .. } => "SelectSourceAccount",
+            ConversationEvent::Confirm { .. } => "Confirm",
+            ConversationEvent::StartAddBeneficiary => "StartAddBeneficiary",
+            ConversationEvent::StartRenamePayee { .. } => "StartRenamePayee",
+            ConversationEvent::StartStatement { .. } => "StartStatement",
+            ConversationEvent::StartCash { .. } => "StartCash",
+            ConversationEvent::StartRegistration => "StartRegistration",
+            ConversationEvent::Reset => "Reset",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use crate::models::money::HOME_CURRENCY;
+    use super::*;
+
+    fn text(value: &str) -> ConversationEvent {
+        ConversationEvent::Text(value.to_owned())
+    }
+
+    fn step(state: ConversationState, event: ConversationEvent) -> Transition {
+        state.transition(event).unwrap()
+    }
+
+    #[test]
+    fn login_collects_username_pin_and_otp() {
+        let t = step(ConversationState::Idle, ConversationEvent::StartLogin);
+        assert_eq!(t.next, ConversationState::AwaitingUsername);
+
+        let t = step(t.next, text("alice"));
+        assert_eq!(t.effect, Effect::AskPin);
+
+        let t = step(t.next, text("123456"));
+        match &t.effect {
+            Effect::RequestOtp(request) => {
+                assert_eq!(request.user_id, "alice");
+                assert_eq!(request.pin, "123456");
+            }
+            other => panic!("unexpected effect {:?}", other),
+        }
+        assert!(t.next.expires());
+
+        let t = step(t.next, text("999999"));
+        assert_eq!(t.next, ConversationState::Idle);
+        match t.effect {
+            Effect::Login(request) => {
+                assert_eq!(request.user_id, "alice");
+                assert_eq!(request.pin, "");
+                assert_eq!(request.otp, "999999");
+            }
+            other => panic!("unexpected effect {:?}", other),
+        }
+    }
+
+    #[test]
+    fn transfer_collects_amount_and_source_before_confirming() {
+        let t = step(ConversationState::Idle, ConversationEvent::StartTransfer {
+            account_to: "200".to_owned(),
+            currency: HOME_CURRENCY,
+            reference: "1".to_owned(),
+        });
+        let t = step(t.next, text("12.5"));
+        assert!(matches!(t.next, ConversationState::SelectingSourceAccount { .. }));
+
+        let t = step(t.next, ConversationEvent::SelectSourceAccount { account_from: "100".to_owned() });
+        let t = step(t.next, ConversationEvent::Confirm { step_up: false });
+        assert_eq!(t.next, ConversationState::Idle);
+        match t.effect {
+            Effect::Transfer(transfer) => {
+                assert_eq!(transfer.account_from, "100");
+                assert_eq!(transfer.account_to, "200");
+                assert_eq!(transfer.transaction_amount.amount_text(), "12.50");
+            }
+            other => panic!("unexpected effect {:?}", other),
+        }
+    }
+
+    #[test]
+    fn large_transfers_wait_for_a_fresh_otp() {
+        let t = step(ConversationState::Idle, ConversationEvent::StartTransfer {
+            account_to: "200".to_owned(),
+            currency: HOME_CURRENCY,
+            reference: "1".to_owned(),
+        });
+        let t = step(t.next, text("5000"));
+        let t = step(t.next, ConversationEvent::SelectSourceAccount { account_from: "100".to_owned() });
+        let t = step(t.next, ConversationEvent::Confirm { step_up: true });
+        assert!(matches!(t.effect, Effect::RequestTransferOtp(_)));
+        assert!(t.next.expires());
+
+        let t = step(t.next, text(" 123456 "));
+        assert_eq!(t.next, ConversationState::Idle);
+        match t.effect {
+            Effect::TransferWithOtp { transfer, otp } => {
+                assert_eq!(transfer.transaction_amount.amount_text(), "5000.00");
+                assert_eq!(otp, "123456");
+            }
+            other => panic!("unexpected effect {:?}", other),
+        }
+    }
+
+    #[test]
+    fn beneficiary_collects_account_and_description() {
+        let t = step(ConversationState::Idle, ConversationEvent::StartAddBeneficiary);
+        let t = step(t.next, text("30-0"));
+        assert_eq!(t.next, ConversationState::AwaitingBeneficiaryAccount);
+        assert!(matches!(t.effect, Effect::AskBeneficiaryAccount { .. }));
+        let t = step(t.next, text("300"));
+        assert_eq!(t.effect, Effect::AskBeneficiaryDescription { problem: None });
+        let t = step(t.next, text("   "));
+        assert!(matches!(t.effect, Effect::AskBeneficiaryDescription { problem: Some(_) }));
+        let t = step(t.next, text("Mum"));
+        assert_eq!(t.effect, Effect::AddBeneficiary(AddBeneficiaryBody {
+            account_id: "300".to_owned(),
+            description: "Mum".to_owned(),
+        }));
+    }
+
+    #[test]
+    fn renaming_a_payee_collects_a_label() {
+        let t = step(ConversationState::Idle, ConversationEvent::StartRenamePayee { beneficiary_id: "7".to_owned() });
+        let t = step(t.next, text(" Big  Bob "));
+        assert_eq!(t.next, ConversationState::Idle);
+        assert_eq!(t.effect, Effect::RenamePayee(UpdateBeneficiaryBody {
+            beneficiary_id: "7".to_owned(),
+            description: "Big Bob".to_owned(),
+        }));
+    }
+
+    #[test]
+    fn statement_collects_a_period() {
+        let t = step(ConversationState::Idle, ConversationEvent::StartStatement { account_id: "100".to_owned() });
+        assert_eq!(t.next, ConversationState::AwaitingStatementPeriod { account_id: "100".to_owned() });
+        assert_eq!(
+            t.next.clone().transition(text("soon")),
+            Err(InvalidTransition::InvalidStatementPeriod("soon".to_owned()))
+        );
+
+        let t = step(t.next, text("2023-01-01 to 2023-01-31"));
+        assert_eq!(t.next, ConversationState::Idle);
+        match t.effect {
+            Effect::SendStatement(period) => {
+                assert_eq!(period.account_id, "100");
+                assert_eq!(period.file_stem(), "statement-100-20230101-20230131");
+            }
+            other => panic!("unexpected effect {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cash_collects_account_and_amount_before_confirming() {
+        let t = step(ConversationState::Idle, ConversationEvent::StartCash { movement: CashMovement::Withdrawal });
+        let t = step(t.next, text(" 100 "));
+        assert_eq!(t.effect, Effect::AskCashCurrency { account_id: "100".to_owned() });
+        assert_eq!(t.next.clone().transition(text("dollars")), Err(InvalidTransition::InvalidCurrency("dollars".to_owned())));
+
+        // The amount is counted in the currency the teller gave for the account
+        let usd: Currency = "USD".parse().unwrap();
+        let t = step(t.next, text(" usd "));
+        assert_eq!(t.effect, Effect::AskCashAmount { movement: CashMovement::Withdrawal, currency: usd });
+        assert_eq!(t.next.clone().transition(text("0")), Err(InvalidTransition::InvalidAmount("0".to_owned())));
+
+        let t = step(t.next, text("20"));
+        assert!(matches!(t.next, ConversationState::ConfirmingCash { .. }));
+        let t = step(t.next, ConversationEvent::Confirm { step_up: false });
+        assert_eq!(t.next, ConversationState::Idle);
+        assert_eq!(t.effect, Effect::MoveCash(CashOrder {
+            movement: CashMovement::Withdrawal,
+            account_id: "100".to_owned(),
+            amount: Money::new(Decimal::new(2000, 2), usd),
+            narrative: "Cash withdrawal".to_owned(),
+        }));
+    }
+
+    #[test]
+    fn registration_asks_again_until_each_answer_is_valid() {
+        let t = step(ConversationState::Idle, ConversationEvent::StartRegistration);
+        assert_eq!(t.effect, Effect::AskRegistrationField { field: RegistrationField::Name, problem: None });
+        assert!(t.next.expires());
+
+        let t = step(t.next, text("Tan"));
+        assert_eq!(t.effect, Effect::AskRegistrationField {
+            field: RegistrationField::Name,
+            problem: Some("Please key in your family name and given name.".to_owned()),
+        });
+
+        let mut t = step(t.next, text("Tan Wei Ming"));
+        for answer in ["S1234567D", "1990-05-01", "1 Raffles Place", "048616", "91234567"] {
+            assert!(matches!(t.effect, Effect::AskRegistrationField { problem: None, .. }), "{:?}", t.effect);
+            t = step(t.next, text(answer));
+        }
+        let t = step(t.next, text("weiming"));
+        let registration = match t.effect {
+            Effect::ShowRegistration(registration) => registration,
+            other => panic!("unexpected effect {:?}", other),
+        };
+        assert_eq!(registration.given_name, "Wei Ming");
+        assert_eq!(registration.preferred_user_id, "weiming");
+
+        let t = step(t.next, ConversationEvent::Confirm { step_up: false });
+        assert_eq!(t.next, ConversationState::Idle);
+        assert_eq!(t.effect, Effect::Onboard(registration));
+    }
+
+    #[test]
+    fn invalid_amounts_are_rejected() {
+        let state = step(ConversationState::Idle, ConversationEvent::StartTransfer {
+            account_to: "200".to_owned(),
+            currency: HOME_CURRENCY,
+            reference: "1".to_owned(),
+        }).next;
+        assert_eq!(state.clone().transition(text("abc")), Err(InvalidTransition::InvalidAmount("abc".to_owned())));
+        assert_eq!(state.transition(text("-5")), Err(InvalidTransition::InvalidAmount("-5".to_owned())));
+    }
+
+    #[test]
+    fn amounts_are_keyed_in_the_destination_currency() {
+        let yen: Currency = "JPY".parse().unwrap();
+        let state = step(ConversationState::Idle, ConversationEvent::StartTransfer {
+            account_to: "300".to_owned(),
+            currency: yen,
+            reference: "1".to_owned(),
+        }).next;
+        assert_eq!(state.clone().transition(text("1.5")), Err(InvalidTransition::InvalidAmount("1.5".to_owned())));
+        assert_eq!(state.clone().transition(text("SGD 15")), Err(InvalidTransition::InvalidAmount("SGD 15".to_owned())));
+        match step(state, text("1,500")).effect {
+            Effect::ChooseSourceAccount(transfer) => assert_eq!(transfer.transaction_amount, Money::new(Decimal::from(1500), yen)),
+            other => panic!("unexpected effect {:?}", other),
+        }
+    }
+
+    #[test]
+    fn out_of_order_events_are_rejected() {
+        assert!(ConversationState::Idle.transition(ConversationEvent::Confirm { step_up: false }).is_err());
+        assert!(ConversationState::Idle.transition(text("hello")).is_err());
+        assert!(ConversationState::AwaitingUsername.transition(ConversationEvent::SelectSourceAccount {
+            account_from: "100".to_owned(),
+        }).is_err());
+    }
+
+    #[test]
+    fn reset_always_returns_to_idle() {
+        let state = ConversationState::AwaitingBeneficiaryAccount;
+        assert_eq!(step(state, ConversationEvent::Reset).next, ConversationState::Idle);
+    }
+
+    #[test]
+    fn state_round_trips_through_json() {
+        let state = step(ConversationState::AwaitingUsername, text("alice")).next;
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<ConversationState>(&json).unwrap(), state);
+    }
+}
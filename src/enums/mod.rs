@@ -1,2 +1,8 @@
 pub mod telegram;
-pub mod beneficiary;
\ No newline at end of file
+pub mod callback;
+pub mod beneficiary;
+pub mod conversation;
+pub mod state_store;
+pub mod update_mode;
+pub mod cash;
+pub mod chart_backend;
\ No newline at end of file
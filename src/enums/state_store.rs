@@ -0,0 +1,9 @@
+use clap::ValueEnum;
+
+/// Where chat state is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StateStoreKind {
+    Redis,
+    /// Lost on restart and not shared between instances; for development and tests.
+    Memory,
+}
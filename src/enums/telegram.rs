@@ -5,5 +5,11 @@ pub enum Command {
     #[command(description = "Initialise the telegram bot.")]
     Start,
     #[command(description = "Get help from the telegram bot.")]
-    Help
+    Help,
+    #[command(description = "List active sessions (admins only).")]
+    Sessions,
+    #[command(description = "Deposit cash into a customer account (admins only).")]
+    Deposit,
+    #[command(description = "Withdraw cash from a customer account (admins only).")]
+    Withdraw
 }
\ No newline at end of file
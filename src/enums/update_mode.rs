@@ -0,0 +1,10 @@
+use clap::ValueEnum;
+
+/// How Telegram updates reach the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UpdateMode {
+    /// Long polls `getUpdates`; only one instance may run per bot token.
+    Polling,
+    /// Telegram posts updates to `/telegram/webhook` on the HTTP server.
+    Webhook,
+}
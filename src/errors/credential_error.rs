@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CredentialError {
+    #[error("No credential keys were configured.")]
+    NoKeys,
+    #[error("Credential key {0:?} is not a base64 encoded 32 byte key.")]
+    InvalidKey(String),
+    #[error("Credentials were sealed with unknown key {0:?}.")]
+    UnknownKey(String),
+    #[error("Credentials could not be encrypted.")]
+    Encryption,
+    #[error("Stored credentials are malformed.")]
+    Malformed,
+    #[error("Stored credentials could not be decrypted.")]
+    Decryption,
+}
@@ -0,0 +1,3 @@
+pub mod tbank_error;
+pub mod credential_error;
+pub mod session_error;
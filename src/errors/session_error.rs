@@ -0,0 +1,9 @@
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SessionError {
+    #[error("You are not logged in.")]
+    NotLoggedIn,
+    #[error("Your session has expired. Please log in again.")]
+    Expired,
+}
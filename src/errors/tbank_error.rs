@@ -0,0 +1,150 @@
+use thiserror::Error;
+use crate::models::Error;
+use crate::models::money::Currency;
+
+// GlobalErrorID returned by TBank in ServiceRespHeader when a call went through
+const SUCCESS: &str = "010000";
+
+#[derive(Debug, Error)]
+pub enum TBankError {
+    #[error("There are insufficient funds in the account.")]
+    InsufficientFunds,
+    #[error("The PIN you entered is invalid.")]
+    InvalidPin,
+    #[error("The OTP has expired or is invalid.")]
+    ExpiredOtp,
+    #[error("The account could not be found.")]
+    UnknownAccount,
+    #[error("Transfers between {from} and {to} accounts are not supported.")]
+    UnsupportedConversion { from: Currency, to: Currency },
+    #[error("TBank is currently unavailable. Please try again later.")]
+    ServiceUnavailable,
+    #[error("We could not reach TBank. Please try again later.")]
+    Transport(#[from] reqwest::Error),
+    #[error("TBank sent a response we could not understand.")]
+    MalformedResponse(String),
+    #[error("TBank rejected the request: {message}")]
+    Rejected {
+        global_error_id: Option<String>,
+        message: String,
+    },
+}
+
+impl Error {
+    pub fn is_success(&self) -> bool {
+        if self.global_error_id.as_deref() == Some(SUCCESS) {
+            return true;
+        }
+        let text_ok = self.error_text.as_deref()
+            .map(|text| text.to_lowercase().contains("invocation successful"))
+            .unwrap_or(false);
+        let details_ok = self.error_details.as_deref()
+            .map(|details| details.eq_ignore_ascii_case("success"))
+            .unwrap_or(false);
+        text_ok || details_ok
+    }
+
+    /// Turns a ServiceRespHeader into `Ok(())` on success or the matching `TBankError`.
+    pub fn ensure_success(&self) -> Result<(), TBankError> {
+        if self.is_success() {
+            return Ok(());
+        }
+        Err(TBankError::from(self))
+    }
+}
+
+impl From<&Error> for TBankError {
+    fn from(header: &Error) -> Self {
+        let message = header.error_details.clone()
+            .filter(|details| !details.is_empty())
+            .or_else(|| header.error_text.clone())
+            .unwrap_or_default();
+        let kind = header.global_error_id.as_deref()
+            .and_then(kind_of_code)
+            .or_else(|| kind_of_message(&message));
+        match kind {
+            Some(kind) => kind,
+            None => TBankError::Rejected {
+                global_error_id: header.global_error_id.clone(),
+                message,
+            },
+        }
+    }
+}
+
+/// GlobalErrorIDs that stand for one failure whatever the service. TBank's list is not published,
+/// so these are the codes MockTBank answers with; any other code, such as the generic 010041,
+/// is left to the message.
+fn kind_of_code(global_error_id: &str) -> Option<TBankError> {
+    match global_error_id.trim() {
+        "010011" => Some(TBankError::InsufficientFunds),
+        "010012" => Some(TBankError::InvalidPin),
+        "010013" => Some(TBankError::ExpiredOtp),
+        "010014" => Some(TBankError::UnknownAccount),
+        "010015" => Some(TBankError::ServiceUnavailable),
+        _ => None,
+    }
+}
+
+/// Fallback for codes that say nothing on their own. The message is compared word by word,
+/// so "shipping" is not a PIN and "laptop" is not an OTP.
+fn kind_of_message(message: &str) -> Option<TBankError> {
+    let lowered = message.to_lowercase();
+    let words: Vec<&str> = lowered.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect();
+    let has = |phrase: &[&str]| words.windows(phrase.len()).any(|window| window == phrase);
+    if has(&["insufficient"]) {
+        Some(TBankError::InsufficientFunds)
+    } else if has(&["pin"]) {
+        Some(TBankError::InvalidPin)
+    } else if has(&["otp"]) {
+        Some(TBankError::ExpiredOtp)
+    } else if has(&["account"]) && (has(&["not", "found"]) || has(&["invalid"]) || has(&["does", "not", "exist"])) {
+        Some(TBankError::UnknownAccount)
+    } else if has(&["unavailable"]) || has(&["timeout"]) || has(&["timed", "out"]) {
+        Some(TBankError::ServiceUnavailable)
+    } else {
+        None
+    }
+}
+
+impl From<serde_json::Error> for TBankError {
+    fn from(e: serde_json::Error) -> Self {
+        TBankError::MalformedResponse(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(global_error_id: &str, details: &str) -> Error {
+        Error {
+            error_text: Some("invocation failed".to_owned()),
+            error_details: Some(details.to_owned()),
+            global_error_id: Some(global_error_id.to_owned()),
+        }
+    }
+
+    #[test]
+    fn codes_decide_before_the_message() {
+        assert!(matches!(TBankError::from(&header("010012", "Request refused")), TBankError::InvalidPin));
+        assert!(matches!(TBankError::from(&header("010011", "Invalid OTP")), TBankError::InsufficientFunds));
+        assert!(matches!(TBankError::from(&header(" 010014 ", "")), TBankError::UnknownAccount));
+    }
+
+    #[test]
+    fn generic_codes_fall_back_to_whole_words() {
+        assert!(matches!(TBankError::from(&header("010041", "Invalid PIN")), TBankError::InvalidPin));
+        assert!(matches!(TBankError::from(&header("010041", "OTP has expired")), TBankError::ExpiredOtp));
+        assert!(matches!(TBankError::from(&header("010041", "Account not found")), TBankError::UnknownAccount));
+        assert!(matches!(TBankError::from(&header("010041", "Request timed out")), TBankError::ServiceUnavailable));
+    }
+
+    #[test]
+    fn words_inside_other_words_do_not_match() {
+        for details in ["Shipping address missing", "Mapping failed", "Spinning up", "Laptop not registered", "Hotpot", "Accounting period closed"] {
+            let error = TBankError::from(&header("010041", details));
+            assert!(matches!(&error, TBankError::Rejected { message, .. } if message == details), "{}: {:?}", details, error);
+        }
+    }
+}
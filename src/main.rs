@@ -1,45 +1,77 @@
 pub mod config;
+pub mod context;
 pub mod controllers;
 pub mod enums;
+pub mod errors;
 pub mod repositories;
 pub mod services;
 pub mod models;
+#[cfg(test)]
+pub mod testing;
 
 use std::sync::Arc;
 use crate::config::AppConfig;
+use crate::context::AppContext;
 use clap::Parser;
 use crate::services::telegram_service::TelegramService;
+use crate::controllers::telegram_webhook::WEBHOOK_PATH;
+use crate::enums::update_mode::UpdateMode;
+use anyhow::Context;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
     // Initialize environment
-    get_app_config();
-    dotenv::dotenv().ok();
-    let app_config = Arc::new(AppConfig::parse());
+    let app_config = get_app_config();
+    // Shared by every update and request, so pools and clients are only built once
+    let context = AppContext::new(app_config).await?;
 
     let telegram_service = TelegramService::new(
-        &app_config.teloxide_token,
+        context.clone(),
     );
 
     let cloned_telegram_service = telegram_service.clone();
 
-    tokio::spawn(async move {
-        let _ = cloned_telegram_service
-            .listen_and_reply()
-            .await;
-    });
+    let telegram_updates = match context.config.update_mode {
+        UpdateMode::Polling => {
+            tokio::spawn(async move {
+                let _ = cloned_telegram_service
+                    .listen_and_reply()
+                    .await;
+            });
+            None
+        }
+        UpdateMode::Webhook => {
+            let config = &context.config;
+            let url = config.webhook_url.as_deref().context("WEBHOOK_URL is required for webhook mode")?;
+            let url = reqwest::Url::parse(url).context("WEBHOOK_URL is not a valid URL")?;
+            anyhow::ensure!(url.path() == WEBHOOK_PATH, "WEBHOOK_URL must end in {}", WEBHOOK_PATH);
+            let secret = config.webhook_secret.clone().context("WEBHOOK_SECRET is required for webhook mode")?;
+            anyhow::ensure!(is_valid_webhook_secret(&secret), "WEBHOOK_SECRET must be 1-256 of A-Z a-z 0-9 _ -");
+
+            telegram_service.register_webhook(url, secret).await?;
+            let (sender, updates) = tokio::sync::mpsc::unbounded_channel();
+            tokio::spawn(cloned_telegram_service.listen_to_webhook(updates));
+            Some(sender)
+        }
+    };
 
-    let _ = controllers::server::serve(
-        app_config,
-        telegram_service
-    ).await.unwrap();
+    controllers::server::serve(
+        context,
+        telegram_service,
+        telegram_updates,
+    ).await?;
 
     Ok(())
 }
 pub fn get_app_config() -> Arc<AppConfig> {
     dotenv::dotenv().ok();
-    let app_config = Arc::new(AppConfig::parse());
-    app_config
+    Arc::new(AppConfig::parse())
+}
+
+/// Telegram only accepts secret tokens made of these characters.
+fn is_valid_webhook_secret(secret: &str) -> bool {
+    (1..=256).contains(&secret.len())
+        && secret.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
 }
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use crate::models::Error;
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct RequestOTP {
     #[serde(rename = "serviceName")]
     pub service_name: String,
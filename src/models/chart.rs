@@ -1,17 +1,264 @@
-use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use crate::models::Error;
 
-#[derive(Debug, Deserialize, Serialize)]
+/// How many months back a balance history chart may go.
+pub const CHART_WINDOWS: [u8; 4] = [3, 6, 12, 24];
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ChartError {
+    #[error("{0:?} is not a YYYY-MM month")]
+    InvalidMonth(String),
+    #[error("month-end balances are out of order, {later} comes after {earlier}")]
+    OutOfOrder { earlier: YearMonth, later: YearMonth },
+}
+
+/// A calendar month, written `YYYY-MM` like TBank's `Year_Month`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct YearMonth {
+    pub year: i32,
+    // 1-12
+    pub month: u32,
+}
+
+impl FromStr for YearMonth {
+    type Err = ChartError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || ChartError::InvalidMonth(text.to_owned());
+        let (year, month) = text.trim().split_once('-').ok_or_else(invalid)?;
+        if year.len() != 4 || month.len() != 2 {
+            return Err(invalid());
+        }
+        let year: i32 = year.parse().map_err(|_| invalid())?;
+        let month: u32 = month.parse().map_err(|_| invalid())?;
+        if !(1..=12).contains(&month) {
+            return Err(invalid());
+        }
+        Ok(YearMonth { year, month })
+    }
+}
+
+impl fmt::Display for YearMonth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+impl Serialize for YearMonth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for YearMonth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A balance trend, oldest month first. Parsing fails unless every month follows the one before it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "TrendData")]
 pub struct ChartBody {
     #[serde(rename = "MonthEndBalance")]
-    MonthEndBalance: Vec<BalanceRecord>,
+    pub month_end_balance: Vec<BalanceRecord>,
     #[serde(rename = "CurrentMonth")]
-    CurrentMonth: BalanceRecord,
+    pub current_month: BalanceRecord,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct BalanceRecord {
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BalanceRecord {
     #[serde(rename = "Year_Month")]
-    Year_Month: String,
+    pub year_month: YearMonth,
     #[serde(rename = "Balance")]
-    Balance: String,
-}
\ No newline at end of file
+    pub balance: Decimal,
+}
+
+/// `ChartBody` as it arrives, before the months are checked.
+#[derive(Deserialize)]
+struct TrendData {
+    #[serde(rename = "MonthEndBalance")]
+    month_end_balance: Vec<BalanceRecord>,
+    #[serde(rename = "CurrentMonth")]
+    current_month: BalanceRecord,
+}
+
+impl TryFrom<TrendData> for ChartBody {
+    type Error = ChartError;
+
+    fn try_from(data: TrendData) -> Result<Self, Self::Error> {
+        let chart = ChartBody {
+            month_end_balance: data.month_end_balance,
+            current_month: data.current_month,
+        };
+        let records: Vec<&BalanceRecord> = chart.records().collect();
+        for pair in records.windows(2) {
+            if pair[0].year_month >= pair[1].year_month {
+                return Err(ChartError::OutOfOrder { earlier: pair[0].year_month, later: pair[1].year_month });
+            }
+        }
+        Ok(chart)
+    }
+}
+
+impl ChartBody {
+    /// Every month end, then the current month.
+    pub fn records(&self) -> impl Iterator<Item = &BalanceRecord> + Clone {
+        self.month_end_balance.iter().chain([&self.current_month])
+    }
+
+    pub fn statistics(&self) -> TrendStatistics {
+        let first = self.month_end_balance.first().unwrap_or(&self.current_month);
+        let mut lowest = first;
+        let mut highest = first;
+        let mut total = Decimal::ZERO;
+        for record in self.records() {
+            if record.balance < lowest.balance {
+                lowest = record;
+            }
+            if record.balance > highest.balance {
+                highest = record;
+            }
+            total += record.balance;
+        }
+        let count = Decimal::from(self.month_end_balance.len() + 1);
+        let change = self.month_end_balance.last().map(|previous| {
+            let amount = self.current_month.balance - previous.balance;
+            let percent = (!previous.balance.is_zero())
+                .then(|| (amount / previous.balance.abs() * Decimal::ONE_HUNDRED).round_dp_with_strategy(1, RoundingStrategy::MidpointAwayFromZero));
+            MonthlyChange {
+                since: previous.year_month,
+                amount,
+                percent,
+            }
+        });
+        TrendStatistics {
+            average: (total / count).round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero),
+            lowest: lowest.clone(),
+            highest: highest.clone(),
+            change,
+        }
+    }
+}
+
+/// One account's line on a comparison chart.
+#[derive(Debug, Clone)]
+pub struct AccountTrend {
+    pub account_id: String,
+    pub trend: ChartBody,
+}
+
+/// Figures shown beneath a balance chart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrendStatistics {
+    pub average: Decimal,
+    // The earliest month wins a tie
+    pub lowest: BalanceRecord,
+    pub highest: BalanceRecord,
+    /// From the last month end to the current month; `None` without any month end.
+    pub change: Option<MonthlyChange>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonthlyChange {
+    pub since: YearMonth,
+    pub amount: Decimal,
+    /// `None` when the balance it changed from was zero.
+    pub percent: Option<Decimal>,
+}
+
+impl TrendStatistics {
+    pub fn summary(&self) -> String {
+        let mut lines = vec![
+            format!("Average: {:.2}", self.average),
+            format!("Lowest: {:.2} ({})", self.lowest.balance, self.lowest.year_month),
+            format!("Highest: {:.2} ({})", self.highest.balance, self.highest.year_month),
+        ];
+        if let Some(change) = &self.change {
+            let percent = change.percent.map(|percent| format!(" ({:+.1}%)", percent)).unwrap_or_default();
+            lines.push(format!("Change since {}: {:+.2}{}", change.since, change.amount, percent));
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MonthlyBalanceTrend {
+    #[serde(rename = "ServiceRespHeader")]
+    pub service_response_header: Error,
+    #[serde(rename = "TrendData")]
+    pub trend_data: ChartBody,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn month(text: &str) -> YearMonth {
+        text.parse().unwrap()
+    }
+
+    fn chart(balances: &[(&str, &str)]) -> Result<ChartBody, serde_json::Error> {
+        let (current, closed) = balances.split_last().unwrap();
+        let record = |(year_month, balance): &(&str, &str)| json!({"Year_Month": year_month, "Balance": balance});
+        serde_json::from_value(json!({
+            "MonthEndBalance": closed.iter().map(record).collect::<Vec<_>>(),
+            "CurrentMonth": record(current),
+        }))
+    }
+
+    #[test]
+    fn months_are_checked_and_round_trip() {
+        assert_eq!(month("2023-07"), YearMonth { year: 2023, month: 7 });
+        assert_eq!(month("2023-07").to_string(), "2023-07");
+        for bad in ["2023-13", "2023-00", "2023-7", "23-07", "2023/07", "July"] {
+            assert_eq!(bad.parse::<YearMonth>(), Err(ChartError::InvalidMonth(bad.to_owned())));
+        }
+        assert!(month("2022-12") < month("2023-01"));
+    }
+
+    #[test]
+    fn trend_data_must_be_in_order() {
+        let trend = chart(&[("2023-08", "10.50"), ("2023-09", "20"), ("2023-10", "-5.25")]).unwrap();
+        assert_eq!(trend.current_month.balance, Decimal::new(-525, 2));
+        // Balances go back out in the shape the chart generator expects
+        assert_eq!(serde_json::to_value(&trend).unwrap()["MonthEndBalance"][0], json!({"Year_Month": "2023-08", "Balance": "10.50"}));
+
+        let error = chart(&[("2023-09", "1"), ("2023-08", "2"), ("2023-10", "3")]).unwrap_err();
+        assert!(error.to_string().contains("2023-08 comes after 2023-09"), "{}", error);
+        assert!(chart(&[("2023-10", "1"), ("2023-10", "2")]).is_err());
+        assert!(chart(&[("2023-10", "lots")]).is_err());
+        assert!(chart(&[("2023-1", "1")]).is_err());
+    }
+
+    #[test]
+    fn statistics_summarise_the_trend() {
+        let trend = chart(&[("2023-07", "100.00"), ("2023-08", "250.50"), ("2023-09", "-20.00"), ("2023-10", "400")]).unwrap();
+        let statistics = trend.statistics();
+        assert_eq!(statistics.average, Decimal::new(18263, 2));
+        assert_eq!(statistics.lowest.year_month, month("2023-09"));
+        assert_eq!(statistics.highest.year_month, month("2023-10"));
+        assert_eq!(
+            statistics.summary(),
+            "Average: 182.63\nLowest: -20.00 (2023-09)\nHighest: 400.00 (2023-10)\nChange since 2023-09: +420.00 (+2100.0%)"
+        );
+    }
+
+    #[test]
+    fn statistics_handle_short_and_flat_trends() {
+        let statistics = chart(&[("2023-10", "0")]).unwrap().statistics();
+        assert_eq!(statistics.change, None);
+        assert_eq!(statistics.summary(), "Average: 0.00\nLowest: 0.00 (2023-10)\nHighest: 0.00 (2023-10)");
+
+        let statistics = chart(&[("2023-09", "0.00"), ("2023-10", "12.30")]).unwrap().statistics();
+        assert_eq!(statistics.change.unwrap().percent, None);
+        let statistics = chart(&[("2023-09", "50"), ("2023-10", "50")]).unwrap().statistics();
+        assert_eq!(statistics.lowest.year_month, month("2023-09"));
+        assert!(statistics.summary().ends_with("Change since 2023-09: +0.00 (+0.0%)"));
+    }
+}
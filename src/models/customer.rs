@@ -1,6 +1,8 @@
 
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use crate::models::Error;
+use crate::models::{Error, OneOrMany};
+use crate::models::money::{currency_or_home, Currency, Money};
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AccountData {
@@ -8,11 +10,12 @@ pub struct AccountData {
     #[serde(rename = "accountID")]
     pub account_id: String,
     pub parent_account_flag: String,
-    pub balance: String,
+    pub balance: Decimal,
     #[serde(rename = "productID")]
     pub product_id: String,
     pub current_status: String,
-    pub currency: String,
+    #[serde(default = "currency_or_home::home", deserialize_with = "currency_or_home::deserialize")]
+    pub currency: Currency,
     pub home_branch: String,
     pub account_open_date: String,
     pub maintenancehistory: Maintenancehistory,
@@ -20,6 +23,12 @@ pub struct AccountData {
     pub officer_id: String,
 }
 
+impl AccountData {
+    pub fn money(&self) -> Money {
+        Money::new(self.balance, self.currency)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RequestOnboardCustomer {
     #[serde(rename = "serviceName")]
@@ -158,7 +167,8 @@ pub struct Customer {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Account<T> {
-    pub account: T
+    #[serde(default = "OneOrMany::default")]
+    pub account: OneOrMany<T>
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -195,8 +205,12 @@ pub struct OnBoardCustomerData {
 
 }
 
+/// Reply to onboardCustomer. The status sits next to the new customer's details,
+/// though a rejected call may only carry a ServiceRespHeader.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct OnBoardCustomerError {
+pub struct OnBoardCustomerResponse {
+    #[serde(rename = "ServiceRespHeader")]
+    pub service_response_header: Option<Error>,
     #[serde(rename = "ErrorText")]
     pub error_text: Option<String>,
     #[serde(rename = "ErrorDetails")]
@@ -213,6 +227,23 @@ pub struct OnBoardCustomerError {
     pub pin: Option<String>,
 }
 
+impl OnBoardCustomerResponse {
+    pub fn status(&self) -> Error {
+        match &self.service_response_header {
+            Some(header) => Error {
+                error_text: header.error_text.clone(),
+                error_details: header.error_details.clone(),
+                global_error_id: header.global_error_id.clone(),
+            },
+            None => Error {
+                error_text: self.error_text.clone(),
+                error_details: self.error_details.clone(),
+                global_error_id: self.global_error_id.clone(),
+            },
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Maintenancehistory {
@@ -228,3 +259,35 @@ pub struct HistoricalMonthlyBalanceBody {
     #[serde(rename = "numMonths")]
     pub num_months: String,
 }
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OpenDepositAccountBody {
+    #[serde(rename = "productID")]
+    pub product_id: String,
+    #[serde(rename = "openingBalance")]
+    pub opening_balance: String,
+    pub currency: Currency,
+    #[serde(rename = "isRestricted")]
+    pub is_restricted: bool,
+    #[serde(rename = "isServiceChargeWaived")]
+    pub is_service_charge_waived: bool,
+    #[serde(rename = "isMinor")]
+    pub is_minor: bool,
+    #[serde(rename = "makeDefaultAccount")]
+    pub make_default_account: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplyOpenDepositAccount {
+    #[serde(rename = "ServiceRespHeader")]
+    pub service_response_header: Error,
+    #[serde(rename = "accountID")]
+    pub account_id: Option<TextContent>,
+}
+
+/// TBank wraps some scalar values as `{"_content_": "..."}`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TextContent {
+    #[serde(rename = "_content_")]
+    pub content: String,
+}
@@ -1,8 +1,12 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 pub mod transaction;
 pub mod customer;
 pub mod authentication;
 pub mod chart;
+pub mod session;
+pub mod statement;
+pub mod registration;
+pub mod money;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TBankResponse<T> {
@@ -21,7 +25,7 @@ pub struct ServiceResponseHeader<T> {
 }
 
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct CustomerRequest {
     #[serde(rename = "serviceName")]
     pub service_name: String,
@@ -42,4 +46,82 @@ pub struct Error {
     pub error_details: Option<String>,
     #[serde(rename = "GlobalErrorID")]
     pub global_error_id: Option<String>,
-}
\ No newline at end of file
+}
+
+/// A TBank list field. TBank sends a bare object instead of an array when the
+/// list has a single element, and null when it is empty.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(transparent)]
+pub struct OneOrMany<T>(pub Vec<T>);
+
+impl<T> Default for OneOrMany<T> {
+    fn default() -> Self {
+        OneOrMany(Vec::new())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for OneOrMany<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Shape<T> {
+            Many(Vec<T>),
+            One(T),
+        }
+
+        Ok(match Option::<Shape<T>>::deserialize(deserializer)? {
+            Some(Shape::Many(items)) => OneOrMany(items),
+            Some(Shape::One(item)) => OneOrMany(vec![item]),
+            None => OneOrMany(Vec::new()),
+        })
+    }
+}
+
+impl<T> From<OneOrMany<T>> for Vec<T> {
+    fn from(list: OneOrMany<T>) -> Self {
+        list.0
+    }
+}
+
+impl<T> IntoIterator for OneOrMany<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Item {
+        id: u32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Holder {
+        #[serde(default)]
+        items: OneOrMany<Item>,
+    }
+
+    fn parse(value: serde_json::Value) -> Vec<Item> {
+        serde_json::from_value::<Holder>(value).unwrap().items.into()
+    }
+
+    #[test]
+    fn one_or_many_accepts_every_list_shape() {
+        assert_eq!(parse(json!({"items": {"id": 1}})), vec![Item { id: 1 }]);
+        assert_eq!(parse(json!({"items": [{"id": 1}, {"id": 2}]})), vec![Item { id: 1 }, Item { id: 2 }]);
+        assert_eq!(parse(json!({"items": null})), vec![]);
+        assert_eq!(parse(json!({})), vec![]);
+    }
+
+    #[test]
+    fn one_or_many_rejects_malformed_items() {
+        assert!(serde_json::from_value::<Holder>(json!({"items": {"name": "x"}})).is_err());
+    }
+}
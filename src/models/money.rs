@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MoneyError {
+    #[error("{0:?} is not a currency code.")]
+    UnknownCurrency(String),
+    #[error("Please key in an amount like 1,234.50")]
+    InvalidAmount,
+    #[error("The amount has to be more than zero.")]
+    NotPositive,
+    #[error("{currency} amounts have at most {digits} decimal places.")]
+    TooPrecise { currency: Currency, digits: u32 },
+    #[error("That amount is in {found}, but this account is in {expected}.")]
+    WrongCurrency { expected: Currency, found: Currency },
+}
+
+/// An ISO 4217 currency code such as `SGD`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const SGD: Currency = Currency(*b"SGD");
+
+    pub fn code(&self) -> &str {
+        // Only ever built from three ASCII letters
+        std::str::from_utf8(&self.0).unwrap_or("???")
+    }
+
+    /// Digits after the decimal point in the currency's smallest unit.
+    pub fn minor_digits(&self) -> u32 {
+        match self.code() {
+            "JPY" | "KRW" | "VND" | "IDR" | "CLP" | "ISK" => 0,
+            "BHD" | "KWD" | "OMR" | "JOD" | "TND" | "LYD" | "IQD" => 3,
+            _ => 2,
+        }
+    }
+
+    /// Whether `$` on its own may stand for this currency.
+    fn is_dollar(&self) -> bool {
+        self.code().ends_with('D') && !matches!(self.code(), "BHD" | "KWD" | "JOD" | "IQD" | "LYD")
+    }
+}
+
+/// Where TBank leaves the currency out, amounts are in the bank's own currency.
+pub const HOME_CURRENCY: Currency = Currency::SGD;
+
+impl FromStr for Currency {
+    type Err = MoneyError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let code = text.trim().to_ascii_uppercase();
+        match <[u8; 3]>::try_from(code.as_bytes()) {
+            Ok(bytes) if bytes.iter().all(u8::is_ascii_uppercase) => Ok(Currency(bytes)),
+            _ => Err(MoneyError::UnknownCurrency(text.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl fmt::Debug for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// An exact amount of one currency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: Decimal,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount: Decimal, currency: Currency) -> Self {
+        Self {
+            amount,
+            currency,
+        }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Self::new(Decimal::ZERO, currency)
+    }
+
+    /// Reads an amount a customer typed, such as `1,234.50`, `$20` or `SGD 20`.
+    /// A currency in the text has to be `currency`; without one, `currency` is assumed.
+    pub fn parse(text: &str, currency: Currency) -> Result<Self, MoneyError> {
+        let mut text = text.trim();
+        if !text.chars().any(|c| c.is_ascii_digit()) {
+            return Err(MoneyError::InvalidAmount);
+        }
+        if let Some(code) = text.get(..3).filter(|code| code.chars().all(|c| c.is_ascii_alphabetic())) {
+            Self::expect_currency(code, currency)?;
+            text = text[3..].trim_start();
+        } else if let Some(code) = text.get(text.len().saturating_sub(3)..).filter(|code| code.chars().all(|c| c.is_ascii_alphabetic())) {
+            Self::expect_currency(code, currency)?;
+            text = text[..text.len() - 3].trim_end();
+        }
+        if currency.is_dollar() {
+            let symbol = format!("{}$", &currency.code()[..1]);
+            text = text.strip_prefix(symbol.as_str()).or_else(|| text.strip_prefix('$')).unwrap_or(text);
+        }
+        if text.starts_with('-') {
+            return Err(MoneyError::NotPositive);
+        }
+
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        let groups: Vec<&str> = whole.split(',').collect();
+        // Commas may only separate thousands
+        let grouped = groups.len() == 1
+            || (!groups[0].is_empty() && groups[0].len() <= 3 && groups[1..].iter().all(|group| group.len() == 3));
+        let digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if whole.is_empty() || !grouped || !groups.iter().all(|group| digits(group)) || !digits(fraction) || text.ends_with('.') {
+            return Err(MoneyError::InvalidAmount);
+        }
+        if fraction.len() > currency.minor_digits() as usize {
+            return Err(MoneyError::TooPrecise { currency, digits: currency.minor_digits() });
+        }
+        let amount = Decimal::from_str(&format!("{}.{}", groups.concat(), if fraction.is_empty() { "0" } else { fraction }))
+            .map_err(|_| MoneyError::InvalidAmount)?;
+        let money = Self::new(amount, currency).rounded();
+        if !money.is_positive() {
+            return Err(MoneyError::NotPositive);
+        }
+        Ok(money)
+    }
+
+    fn expect_currency(code: &str, expected: Currency) -> Result<(), MoneyError> {
+        let found: Currency = code.parse()?;
+        if found != expected {
+            return Err(MoneyError::WrongCurrency { expected, found });
+        }
+        Ok(())
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.amount > Decimal::ZERO
+    }
+
+    /// Rounded half away from zero to the currency's smallest unit, and padded out to it.
+    pub fn rounded(&self) -> Self {
+        let mut amount = self.amount.round_dp_with_strategy(self.currency.minor_digits(), RoundingStrategy::MidpointAwayFromZero);
+        amount.rescale(self.currency.minor_digits());
+        Self::new(amount, self.currency)
+    }
+
+    /// `percentage` of this amount for a MicroInvest top-up. Rounded down to the smallest unit,
+    /// so a customer never puts away more than the percentage they chose.
+    pub fn share(&self, percentage: u8) -> Self {
+        let share = self.amount * Decimal::from(percentage) / Decimal::ONE_HUNDRED;
+        let mut amount = share.round_dp_with_strategy(self.currency.minor_digits(), RoundingStrategy::ToZero);
+        amount.rescale(self.currency.minor_digits());
+        Self::new(amount, self.currency)
+    }
+
+    /// `None` when the currencies differ.
+    pub fn checked_add(&self, other: Money) -> Option<Self> {
+        (self.currency == other.currency).then(|| Self::new(self.amount + other.amount, self.currency))
+    }
+
+    /// Adds up amounts per currency, in currency code order.
+    pub fn totals(amounts: impl IntoIterator<Item = Money>) -> Vec<Money> {
+        let mut totals: BTreeMap<Currency, Decimal> = BTreeMap::new();
+        for money in amounts {
+            *totals.entry(money.currency).or_default() += money.amount;
+        }
+        totals.into_iter().map(|(currency, amount)| Self::new(amount, currency)).collect()
+    }
+
+    /// The plain figure TBank expects, e.g. `1234.50`.
+    pub fn amount_text(&self) -> String {
+        self.rounded().amount.to_string()
+    }
+}
+
+impl fmt::Display for Money {
+    /// `SGD 1,234.50`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = self.amount_text();
+        let (sign, text) = text.strip_prefix('-').map(|text| ("-", text)).unwrap_or(("", text.as_str()));
+        let (whole, fraction) = text.split_once('.').map(|(whole, fraction)| (whole, Some(fraction))).unwrap_or((text, None));
+        let mut grouped = String::new();
+        for (index, digit) in whole.chars().enumerate() {
+            if index > 0 && (whole.len() - index) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        match fraction {
+            Some(fraction) => write!(f, "{} {}{}.{}", self.currency, sign, grouped, fraction),
+            None => write!(f, "{} {}{}", self.currency, sign, grouped),
+        }
+    }
+}
+
+/// TBank leaves an account's currency blank, or out altogether, when it is `HOME_CURRENCY`.
+/// Use with `#[serde(default = "currency_or_home::home", deserialize_with = "currency_or_home::deserialize")]`.
+pub mod currency_or_home {
+    use super::*;
+
+    pub fn home() -> Currency {
+        HOME_CURRENCY
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Currency, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(code) if !code.trim().is_empty() => code.parse().map_err(serde::de::Error::custom),
+            _ => Ok(HOME_CURRENCY),
+        }
+    }
+}
+
+/// TBank takes amounts as bare figures such as `"12.50"` in the account's own currency, so the
+/// currency stays behind. Use on request content with `#[serde(with = "crate::models::money::tbank_amount")]`;
+/// figures TBank sends back are paired with the account's currency by whoever knows it.
+pub mod tbank_amount {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(money: &Money, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&money.amount_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sgd(text: &str) -> Money {
+        Money::new(text.parse().unwrap(), Currency::SGD)
+    }
+
+    fn currency(code: &str) -> Currency {
+        code.parse().unwrap()
+    }
+
+    #[test]
+    fn typed_amounts_are_read_exactly() {
+        assert_eq!(Money::parse("1,234.50", Currency::SGD), Ok(sgd("1234.50")));
+        assert_eq!(Money::parse(" $20 ", Currency::SGD), Ok(sgd("20.00")));
+        assert_eq!(Money::parse("S$0.10", Currency::SGD), Ok(sgd("0.10")));
+        assert_eq!(Money::parse("sgd 1,000,000", Currency::SGD), Ok(sgd("1000000.00")));
+        assert_eq!(Money::parse("12.5 SGD", Currency::SGD), Ok(sgd("12.50")));
+        assert_eq!(Money::parse("1500", currency("JPY")), Ok(Money::new(Decimal::from(1500), currency("JPY"))));
+    }
+
+    #[test]
+    fn bad_amounts_are_refused() {
+        for bad in ["", "abc", "12,34", "1,2345", ",123", "1.2.3", "12.", "$", "1e5", "12 .50"] {
+            assert_eq!(Money::parse(bad, Currency::SGD), Err(MoneyError::InvalidAmount), "{:?}", bad);
+        }
+        assert_eq!(Money::parse("0", Currency::SGD), Err(MoneyError::NotPositive));
+        assert_eq!(Money::parse("-5", Currency::SGD), Err(MoneyError::NotPositive));
+        assert_eq!(Money::parse("1.005", Currency::SGD), Err(MoneyError::TooPrecise { currency: Currency::SGD, digits: 2 }));
+        assert_eq!(Money::parse("1.5", currency("JPY")), Err(MoneyError::TooPrecise { currency: currency("JPY"), digits: 0 }));
+        assert_eq!(
+            Money::parse("USD 20", Currency::SGD),
+            Err(MoneyError::WrongCurrency { expected: Currency::SGD, found: currency("USD") })
+        );
+        assert_eq!(Money::parse("$20", currency("EUR")), Err(MoneyError::InvalidAmount));
+    }
+
+    #[test]
+    fn micro_invest_shares_round_down() {
+        assert_eq!(sgd("100.00").share(5), sgd("5.00"));
+        assert_eq!(sgd("33.33").share(10), sgd("3.33"));
+        // 0.1 + 0.2 style drift cannot creep in
+        assert_eq!(sgd("0.30").share(50), sgd("0.15"));
+        assert_eq!(sgd("19.99").share(15).amount_text(), "2.99");
+        assert_eq!(sgd("0.01").share(1), sgd("0.00"));
+        assert_eq!(sgd("10.00").checked_add(sgd("0.50")), Some(sgd("10.50")));
+        assert_eq!(sgd("10.00").checked_add(Money::zero(currency("USD"))), None);
+    }
+
+    #[test]
+    fn totals_are_kept_per_currency() {
+        let usd = |text: &str| Money::new(text.parse().unwrap(), currency("USD"));
+        let totals = Money::totals([usd("20"), sgd("500.00"), usd("0.50"), sgd("10")]);
+        assert_eq!(totals, [sgd("510.00"), usd("20.50")]);
+        assert!(Money::totals([]).is_empty());
+    }
+
+    #[test]
+    fn amounts_are_shown_in_the_currency_units() {
+        assert_eq!(sgd("1234567.5").to_string(), "SGD 1,234,567.50");
+        assert_eq!(sgd("-20").to_string(), "SGD -20.00");
+        assert_eq!(sgd("999.994").amount_text(), "999.99");
+        assert_eq!(sgd("999.995").amount_text(), "1000.00");
+        assert_eq!(Money::new(Decimal::new(12345, 1), currency("KWD")).to_string(), "KWD 1,234.500");
+        assert_eq!(Money::new(Decimal::from(1500), currency("JPY")).to_string(), "JPY 1,500");
+    }
+
+    #[test]
+    fn currencies_are_three_letters() {
+        assert_eq!(currency("usd"), currency("USD"));
+        assert_eq!(currency("USD").to_string(), "USD");
+        assert!("US".parse::<Currency>().is_err());
+        assert!("U$D".parse::<Currency>().is_err());
+        assert_eq!(serde_json::to_value(sgd("5")).unwrap(), serde_json::json!({"amount": "5", "currency": "SGD"}));
+    }
+}
@@ -0,0 +1,274 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::models::customer::OnBoardCustomerData;
+
+/// Youngest a customer may be to open an account on their own.
+pub const MIN_AGE_YEARS: i32 = 16;
+const MAX_AGE_YEARS: i32 = 120;
+
+/// One question of the registration form, asked in declaration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegistrationField {
+    Name,
+    IcNumber,
+    DateOfBirth,
+    Address,
+    PostalCode,
+    Mobile,
+    UserId,
+}
+
+impl RegistrationField {
+    pub fn prompt(&self) -> &'static str {
+        match self {
+            RegistrationField::Name => "What is your full name as shown on your IC? Key in your family name first, e.g. Tan Wei Ming",
+            RegistrationField::IcNumber => "What is your NRIC or FIN number?",
+            RegistrationField::DateOfBirth => "What is your date of birth? Key it in as YYYY-MM-DD",
+            RegistrationField::Address => "What is your street address?",
+            RegistrationField::PostalCode => "What is your postal code?",
+            RegistrationField::Mobile => "What is your Singapore mobile number?",
+            RegistrationField::UserId => "Which user ID would you like to log in with? Use 6 to 20 letters, digits or _, starting with a letter",
+        }
+    }
+
+    pub fn next(&self) -> Option<Self> {
+        match self {
+            RegistrationField::Name => Some(RegistrationField::IcNumber),
+            RegistrationField::IcNumber => Some(RegistrationField::DateOfBirth),
+            RegistrationField::DateOfBirth => Some(RegistrationField::Address),
+            RegistrationField::Address => Some(RegistrationField::PostalCode),
+            RegistrationField::PostalCode => Some(RegistrationField::Mobile),
+            RegistrationField::Mobile => Some(RegistrationField::UserId),
+            RegistrationField::UserId => None,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RegistrationError {
+    #[error("Please key in your family name and given name.")]
+    IncompleteName,
+    #[error("Names may only contain letters, spaces and . ' - characters.")]
+    InvalidName,
+    #[error("That is not a valid NRIC or FIN number.")]
+    InvalidIcNumber,
+    #[error("That is not a valid date. Please use YYYY-MM-DD.")]
+    InvalidDate,
+    #[error("You need to be at least {MIN_AGE_YEARS} years old to register.")]
+    TooYoung,
+    #[error("That date of birth is too far in the past.")]
+    TooOld,
+    #[error("Please key in an address between 5 and 100 characters.")]
+    InvalidAddress,
+    #[error("Postal codes have 6 digits.")]
+    InvalidPostalCode,
+    #[error("That is not a Singapore mobile number.")]
+    InvalidMobile,
+    #[error("User IDs have 6 to 20 letters, digits or _, starting with a letter.")]
+    InvalidUserId,
+}
+
+/// What a new customer has told us so far. Fields stay empty until their question is answered.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Registration {
+    pub family_name: String,
+    pub given_name: String,
+    pub ic_number: String,
+    //yyyy-MM-dd
+    pub date_of_birth: String,
+    pub street_address: String,
+    pub postal_code: String,
+    pub mobile_number: String,
+    pub preferred_user_id: String,
+}
+
+impl Registration {
+    /// Checks the answer to `field` and keeps it in its normalised form.
+    pub fn fill(&mut self, field: RegistrationField, text: &str) -> Result<(), RegistrationError> {
+        let text = text.trim();
+        match field {
+            RegistrationField::Name => {
+                let (family_name, given_name) = Self::parse_name(text)?;
+                self.family_name = family_name;
+                self.given_name = given_name;
+            }
+            RegistrationField::IcNumber => self.ic_number = Self::parse_ic_number(text)?,
+            RegistrationField::DateOfBirth => self.date_of_birth = Self::parse_date_of_birth(text, Self::today())?,
+            RegistrationField::Address => {
+                if !(5..=100).contains(&text.chars().count()) {
+                    return Err(RegistrationError::InvalidAddress);
+                }
+                self.street_address = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            }
+            RegistrationField::PostalCode => {
+                if text.len() != 6 || !text.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(RegistrationError::InvalidPostalCode);
+                }
+                self.postal_code = text.to_owned();
+            }
+            RegistrationField::Mobile => self.mobile_number = Self::parse_mobile(text)?,
+            RegistrationField::UserId => {
+                let valid = (6..=20).contains(&text.len())
+                    && text.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    return Err(RegistrationError::InvalidUserId);
+                }
+                self.preferred_user_id = text.to_owned();
+            }
+        }
+        Ok(())
+    }
+
+    /// The review screen shown before anything is sent to TBank.
+    pub fn summary(&self) -> String {
+        format!(
+            "Name: {} {}\nNRIC/FIN: {}\nDate of birth: {}\nAddress: {}, Singapore {}\nMobile: +65 {}\nUser ID: {}",
+            self.family_name,
+            self.given_name,
+            self.ic_number,
+            self.date_of_birth,
+            self.street_address,
+            self.postal_code,
+            self.mobile_number,
+            self.preferred_user_id,
+        )
+    }
+
+    pub fn into_onboard_data(self) -> OnBoardCustomerData {
+        OnBoardCustomerData {
+            service_name: "onboardCustomer".to_owned(),
+            ic_number: self.ic_number,
+            family_name: self.family_name,
+            given_name: self.given_name,
+            date_of_birth: self.date_of_birth,
+            gender: "".to_owned(),
+            occupation: "".to_owned(),
+            street_address: self.street_address,
+            city: "Singapore".to_owned(),
+            state: "".to_owned(),
+            country: "SG".to_owned(),
+            postal_code: self.postal_code,
+            country_code: "65".to_owned(),
+            mobile_number: self.mobile_number,
+            preferred_user_id: self.preferred_user_id,
+            currency: "SGD".to_owned(),
+            bank_id: "1".to_owned(),
+        }
+    }
+
+    /// Splits off the first word as the family name.
+    fn parse_name(text: &str) -> Result<(String, String), RegistrationError> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.len() < 2 {
+            return Err(RegistrationError::IncompleteName);
+        }
+        let allowed = |c: char| c.is_alphabetic() || matches!(c, '.' | '\'' | '-');
+        if text.chars().count() > 70 || !words.iter().all(|word| word.chars().all(allowed)) {
+            return Err(RegistrationError::InvalidName);
+        }
+        Ok((words[0].to_owned(), words[1..].join(" ")))
+    }
+
+    /// A letter for the holder type, seven digits and a check letter.
+    fn parse_ic_number(text: &str) -> Result<String, RegistrationError> {
+        let ic_number = text.to_uppercase();
+        let bytes = ic_number.as_bytes();
+        let valid = bytes.len() == 9
+            && matches!(bytes[0], b'S' | b'T' | b'F' | b'G' | b'M')
+            && bytes[1..8].iter().all(u8::is_ascii_digit)
+            && bytes[8].is_ascii_uppercase();
+        if !valid {
+            return Err(RegistrationError::InvalidIcNumber);
+        }
+        Ok(ic_number)
+    }
+
+    fn parse_date_of_birth(text: &str, today: NaiveDate) -> Result<String, RegistrationError> {
+        let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").map_err(|_| RegistrationError::InvalidDate)?;
+        let mut age = today.year() - date.year();
+        if (today.month(), today.day()) < (date.month(), date.day()) {
+            age -= 1;
+        }
+        if date > today || age < MIN_AGE_YEARS {
+            return Err(RegistrationError::TooYoung);
+        }
+        if age > MAX_AGE_YEARS {
+            return Err(RegistrationError::TooOld);
+        }
+        Ok(date.format("%Y-%m-%d").to_string())
+    }
+
+    /// Eight digits starting with 8 or 9, with or without the +65 country code.
+    fn parse_mobile(text: &str) -> Result<String, RegistrationError> {
+        let digits: String = text.chars().filter(|c| !matches!(c, ' ' | '-')).collect();
+        let digits = digits.strip_prefix("+65").unwrap_or(&digits);
+        let valid = digits.len() == 8
+            && digits.starts_with(['8', '9'])
+            && digits.chars().all(|c| c.is_ascii_digit());
+        if !valid {
+            return Err(RegistrationError::InvalidMobile);
+        }
+        Ok(digits.to_owned())
+    }
+
+    fn today() -> NaiveDate {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        DateTime::from_timestamp(now as i64, 0).unwrap_or_default().date_naive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn names_put_the_family_name_first() {
+        let mut registration = Registration::default();
+        registration.fill(RegistrationField::Name, "  Tan   Wei Ming ").unwrap();
+        assert_eq!(registration.family_name, "Tan");
+        assert_eq!(registration.given_name, "Wei Ming");
+        assert_eq!(registration.fill(RegistrationField::Name, "Tan"), Err(RegistrationError::IncompleteName));
+        assert_eq!(registration.fill(RegistrationField::Name, "Tan W3i"), Err(RegistrationError::InvalidName));
+    }
+
+    #[test]
+    fn ic_numbers_and_mobiles_are_normalised() {
+        let mut registration = Registration::default();
+        registration.fill(RegistrationField::IcNumber, "s1234567d").unwrap();
+        assert_eq!(registration.ic_number, "S1234567D");
+        assert_eq!(registration.fill(RegistrationField::IcNumber, "X1234567D"), Err(RegistrationError::InvalidIcNumber));
+        assert_eq!(registration.fill(RegistrationField::IcNumber, "S123456D"), Err(RegistrationError::InvalidIcNumber));
+
+        registration.fill(RegistrationField::Mobile, "+65 9123-4567").unwrap();
+        assert_eq!(registration.mobile_number, "91234567");
+        assert_eq!(registration.fill(RegistrationField::Mobile, "61234567"), Err(RegistrationError::InvalidMobile));
+    }
+
+    #[test]
+    fn dates_of_birth_need_an_adult() {
+        let today = date("2023-10-15");
+        assert_eq!(Registration::parse_date_of_birth("2007-10-15", today), Ok("2007-10-15".to_owned()));
+        assert_eq!(Registration::parse_date_of_birth("2007-10-16", today), Err(RegistrationError::TooYoung));
+        assert_eq!(Registration::parse_date_of_birth("2030-01-01", today), Err(RegistrationError::TooYoung));
+        assert_eq!(Registration::parse_date_of_birth("1890-01-01", today), Err(RegistrationError::TooOld));
+        assert_eq!(Registration::parse_date_of_birth("15/10/1990", today), Err(RegistrationError::InvalidDate));
+    }
+
+    #[test]
+    fn other_fields_are_checked() {
+        let mut registration = Registration::default();
+        assert_eq!(registration.fill(RegistrationField::PostalCode, "12345"), Err(RegistrationError::InvalidPostalCode));
+        assert_eq!(registration.fill(RegistrationField::Address, "1"), Err(RegistrationError::InvalidAddress));
+        assert_eq!(registration.fill(RegistrationField::UserId, "1alice"), Err(RegistrationError::InvalidUserId));
+        assert_eq!(registration.fill(RegistrationField::UserId, "ali"), Err(RegistrationError::InvalidUserId));
+        registration.fill(RegistrationField::UserId, "alice_01").unwrap();
+        assert_eq!(registration.into_onboard_data().preferred_user_id, "alice_01");
+    }
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// What a chat holds once logged in. The credentials themselves live encrypted under the token.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Session {
+    pub token: String,
+    pub user_id: String,
+    pub chat_id: i64,
+    /// Unix seconds of the login
+    pub created_at: u64,
+    /// Unix seconds of the last authenticated action
+    pub last_seen: u64,
+}
@@ -0,0 +1,76 @@
+use chrono::NaiveDate;
+
+/// Longest period one statement may cover.
+pub const MAX_STATEMENT_DAYS: i64 = 366;
+
+/// An account and the inclusive range of days a statement covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementPeriod {
+    pub account_id: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl StatementPeriod {
+    /// Reads `yyyy-MM-dd to yyyy-MM-dd` (or the two dates separated by spaces).
+    pub fn parse(account_id: &str, text: &str) -> Option<Self> {
+        let text = text.trim().to_lowercase();
+        let (start, end) = text.split_once(" to ").or_else(|| text.split_once(char::is_whitespace))?;
+        let start = NaiveDate::parse_from_str(start.trim(), "%Y-%m-%d").ok()?;
+        let end = NaiveDate::parse_from_str(end.trim(), "%Y-%m-%d").ok()?;
+        let days = (end - start).num_days();
+        if !(0..MAX_STATEMENT_DAYS).contains(&days) {
+            return None;
+        }
+        Some(Self {
+            account_id: account_id.to_owned(),
+            start,
+            end,
+        })
+    }
+
+    /// Start and end in TBank's `yyyy-MM-dd HH:mm:ss`, covering the whole of both days.
+    pub fn tbank_range(&self) -> (String, String) {
+        (
+            format!("{} 00:00:00", self.start.format("%Y-%m-%d")),
+            format!("{} 23:59:59", self.end.format("%Y-%m-%d")),
+        )
+    }
+
+    /// File name for the statement, without an extension.
+    pub fn file_stem(&self) -> String {
+        format!("statement-{}-{}-{}", self.account_id, self.start.format("%Y%m%d"), self.end.format("%Y%m%d"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn periods_are_read_with_or_without_to() {
+        let expected = StatementPeriod { account_id: "100".to_owned(), start: date("2023-01-01"), end: date("2023-03-31") };
+        assert_eq!(StatementPeriod::parse("100", "2023-01-01 to 2023-03-31"), Some(expected.clone()));
+        assert_eq!(StatementPeriod::parse("100", " 2023-01-01  2023-03-31 "), Some(expected.clone()));
+        assert_eq!(StatementPeriod::parse("100", "2023-01-01 TO 2023-03-31"), Some(expected));
+    }
+
+    #[test]
+    fn backwards_long_or_malformed_periods_are_refused() {
+        assert_eq!(StatementPeriod::parse("100", "2023-03-31 to 2023-01-01"), None);
+        assert_eq!(StatementPeriod::parse("100", "2022-01-01 to 2023-03-31"), None);
+        assert_eq!(StatementPeriod::parse("100", "last month"), None);
+        assert_eq!(StatementPeriod::parse("100", "2023-02-30 to 2023-03-31"), None);
+    }
+
+    #[test]
+    fn the_range_covers_both_whole_days() {
+        let period = StatementPeriod::parse("100", "2023-01-01 to 2023-01-01").unwrap();
+        assert_eq!(period.tbank_range(), ("2023-01-01 00:00:00".to_owned(), "2023-01-01 23:59:59".to_owned()));
+        assert_eq!(period.file_stem(), "statement-100-20230101-20230101");
+    }
+}
@@ -1,66 +1,150 @@
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-#[derive(Serialize, Deserialize)]
+use thiserror::Error;
+use crate::enums::cash::CashMovement;
+use crate::models::{CustomerRequest, Error, OneOrMany};
+use crate::models::money::{currency_or_home, tbank_amount, Currency, Money};
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct DepositRequest {
     #[serde(rename = "serviceName")]
-    service_name: String,
+    pub service_name: String,
     #[serde(rename = "userID")]
-    user_id: String,
+    pub user_id: String,
     #[serde(rename = "PIN")]
-    pin: String,
+    pub pin: String,
     //Not required
     #[serde(rename = "OTP")]
-    otp: String,
+    pub otp: String,
     #[serde(rename = "accountID")]
-    account_id: String,
-    amount: String,
-    narrative: String,
+    pub account_id: String,
+    pub amount: Money,
+    pub narrative: String,
 }
 
-#[derive(Serialize, Deserialize)]
+/// The part of a `DepositRequest` TBank expects as Content; the rest goes in the Header.
+#[derive(Debug, Serialize)]
+pub struct DepositContent<'a> {
+    #[serde(rename = "accountID")]
+    pub account_id: &'a str,
+    #[serde(with = "tbank_amount")]
+    pub amount: Money,
+    pub narrative: &'a str,
+}
+
+impl DepositRequest {
+    pub fn header(&self) -> CustomerRequest {
+        CustomerRequest {
+            service_name: self.service_name.clone(),
+            user_id: self.user_id.clone(),
+            pin: self.pin.clone(),
+            otp: self.otp.clone(),
+        }
+    }
+
+    pub fn content(&self) -> DepositContent<'_> {
+        DepositContent {
+            account_id: &self.account_id,
+            amount: self.amount,
+            narrative: &self.narrative,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct DepositResponse {
     //Shows successful messages as well.. strange
     #[serde(rename = "ErrorText")]
-    error_text: String,
+    pub error_text: String,
     #[serde(rename = "GlobalErrorID")]
-    global_error_id: String,
+    pub global_error_id: String,
     #[serde(rename = "ErrorDetails")]
-    error_details: String,
-    #[serde(rename = "BalanceAfter")]
-    balance_after: String,
-    #[serde(rename = "BalanceBefore")]
-    balance_before: String,
-    #[serde(rename = "TransactionID")]
-    transaction_id: String,
+    pub error_details: String,
+    //Only sent when the deposit or withdrawal went through
+    #[serde(rename = "BalanceAfter", default)]
+    pub balance_after: Option<Decimal>,
+    #[serde(rename = "BalanceBefore", default)]
+    pub balance_before: Option<Decimal>,
+    #[serde(rename = "TransactionID", default)]
+    pub transaction_id: Option<String>,
+}
+
+impl DepositResponse {
+    /// The reply carries its own status instead of a ServiceRespHeader.
+    pub fn status(&self) -> Error {
+        Error {
+            error_text: Some(self.error_text.clone()),
+            error_details: Some(self.error_details.clone()),
+            global_error_id: Some(self.global_error_id.clone()),
+        }
+    }
+}
+
+/// A cash deposit or withdrawal a teller is putting together, before any credentials are attached.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CashOrder {
+    pub movement: CashMovement,
+    pub account_id: String,
+    pub amount: Money,
+    pub narrative: String,
+}
+
+impl CashOrder {
+    /// An order for `amount`, or `None` unless it is positive.
+    pub fn new(movement: CashMovement, account_id: &str, amount: Money, narrative: Option<String>) -> Option<Self> {
+        if !amount.is_positive() {
+            return None;
+        }
+        let narrative = narrative
+            .map(|narrative| narrative.trim().to_owned())
+            .filter(|narrative| !narrative.is_empty())
+            .unwrap_or_else(|| format!("Cash {}", movement.noun()));
+        Some(Self {
+            movement,
+            account_id: account_id.trim().to_owned(),
+            amount: amount.rounded(),
+            narrative,
+        })
+    }
+
+    pub fn into_request(self, teller: &CustomerRequest) -> DepositRequest {
+        DepositRequest {
+            service_name: self.movement.service_name().to_owned(),
+            user_id: teller.user_id.clone(),
+            pin: teller.pin.clone(),
+            otp: teller.otp.clone(),
+            account_id: self.account_id,
+            amount: self.amount,
+            narrative: self.narrative,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct BeneficiaryList {
-    //Shows successful messages as well.. strange
-    #[serde(rename = "BeneficiaryList")]
-    pub beneficiary_list: Beneficiaries,
+    #[serde(rename = "BeneficiaryList", default)]
+    pub beneficiary_list: Option<Beneficiaries>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Beneficiaries {
-    //Shows successful messages as well.. strange
-    #[serde(rename = "Beneficiary")]
-    pub beneficiary: Vec<Beneficiary>
+    #[serde(rename = "Beneficiary", default)]
+    pub beneficiary: OneOrMany<Beneficiary>
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Beneficiary {
     //Shows successful messages as well.. strange
     #[serde(rename = "AccountID")]
     pub account_id: String,
     #[serde(rename = "Description")]
     pub description: String,
-    #[serde(rename = "Currency")]
-    pub currency: String,
+    #[serde(rename = "Currency", default = "currency_or_home::home", deserialize_with = "currency_or_home::deserialize")]
+    pub currency: Currency,
     #[serde(rename = "BeneficiaryID")]
     pub beneficiary_id: String
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AddBeneficiaryBody {
     #[serde(rename = "AccountID")]
     pub account_id: String,
@@ -68,20 +152,92 @@ pub struct AddBeneficiaryBody {
     pub description: String
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+// Longest payee label we accept
+const MAX_DESCRIPTION_LEN: usize = 50;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum BeneficiaryError {
+    #[error("Account numbers only contain digits, up to 20 of them.")]
+    InvalidAccountId,
+    #[error("Please key in a label of up to {MAX_DESCRIPTION_LEN} characters.")]
+    InvalidDescription,
+}
+
+impl AddBeneficiaryBody {
+    pub fn parse_account_id(text: &str) -> Result<String, BeneficiaryError> {
+        let account_id = text.trim();
+        if account_id.is_empty() || account_id.len() > 20 || !account_id.chars().all(|c| c.is_ascii_digit()) {
+            return Err(BeneficiaryError::InvalidAccountId);
+        }
+        Ok(account_id.to_owned())
+    }
+
+    pub fn parse_description(text: &str) -> Result<String, BeneficiaryError> {
+        let description = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if description.is_empty() || description.chars().count() > MAX_DESCRIPTION_LEN {
+            return Err(BeneficiaryError::InvalidDescription);
+        }
+        Ok(description)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UpdateBeneficiaryBody {
+    #[serde(rename = "BeneficiaryID")]
+    pub beneficiary_id: String,
+    #[serde(rename = "Description")]
+    pub description: String
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DeleteBeneficiaryBody {
+    #[serde(rename = "BeneficiaryID")]
+    pub beneficiary_id: String,
+}
+
+/// A transfer as the bot keeps it between steps.
+/// `creditTransfer` is only documented within one currency, so both accounts are in `transaction_amount`'s.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct TransferBody {
     #[serde(rename = "accountFrom")]
     pub account_from: String,
     #[serde(rename = "accountTo")]
     pub account_to: String,
     #[serde(rename = "transactionAmount")]
-    pub transaction_amount: String,
+    pub transaction_amount: Money,
     #[serde(rename = "transactionReferenceNumber")]
     pub transaction_reference_number: String,
     #[serde(rename = "narrative")]
     pub narrative: String
 }
 
+/// A `TransferBody` the way `creditTransfer` takes it, with the amount as a bare figure.
+#[derive(Debug, Serialize)]
+pub struct TransferContent<'a> {
+    #[serde(rename = "accountFrom")]
+    pub account_from: &'a str,
+    #[serde(rename = "accountTo")]
+    pub account_to: &'a str,
+    #[serde(rename = "transactionAmount", with = "tbank_amount")]
+    pub transaction_amount: Money,
+    #[serde(rename = "transactionReferenceNumber")]
+    pub transaction_reference_number: &'a str,
+    #[serde(rename = "narrative")]
+    pub narrative: &'a str,
+}
+
+impl TransferBody {
+    pub fn content(&self) -> TransferContent<'_> {
+        TransferContent {
+            account_from: &self.account_from,
+            account_to: &self.account_to,
+            transaction_amount: self.transaction_amount,
+            transaction_reference_number: &self.transaction_reference_number,
+            narrative: &self.narrative,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct TransferResponse {
     pub status_text: String,
@@ -89,4 +245,163 @@ pub struct TransferResponse {
     pub pre_balance: Option<String>,
     pub transaction_amount: String,
     pub transaction_reference_number: Option<String>,
-}
\ No newline at end of file
+}
+#[derive(Serialize, Deserialize)]
+pub struct BeneficiaryListBody {
+    #[serde(rename = "accountGroup")]
+    pub account_group: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TransactionHistoryBody {
+    #[serde(rename = "accountID")]
+    pub account_id: String,
+    //yyyy-MM-dd HH:mm:ss
+    #[serde(rename = "startDate")]
+    pub start_date: String,
+    #[serde(rename = "endDate")]
+    pub end_date: String,
+    #[serde(rename = "numRecordsPerPage")]
+    pub num_records_per_page: String,
+    //Starts at 1
+    #[serde(rename = "pageNo")]
+    pub page_no: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionHistory {
+    #[serde(rename = "ServiceRespHeader")]
+    pub service_response_header: Error,
+    #[serde(rename = "CDMTransactionDetail", default)]
+    pub transaction_detail: Option<TransactionDetails>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionDetails {
+    #[serde(rename = "transaction_Detail", default)]
+    pub transaction_detail: OneOrMany<TransactionDetail>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TransactionDetail {
+    #[serde(rename = "transactionID")]
+    pub transaction_id: String,
+    #[serde(rename = "accountFrom")]
+    pub account_from: String,
+    #[serde(rename = "accountTo")]
+    pub account_to: String,
+    #[serde(rename = "transactionAmount")]
+    pub transaction_amount: String,
+    #[serde(rename = "transactionDate")]
+    pub transaction_date: String,
+    #[serde(default)]
+    pub narrative: Option<String>,
+    //Balances after the transaction
+    #[serde(rename = "accountFromBalance", default)]
+    pub account_from_balance: Option<String>,
+    #[serde(rename = "accountToBalance", default)]
+    pub account_to_balance: Option<String>,
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+impl TransactionDetail {
+    /// The amount as seen from `account_id`: negative when money left it.
+    /// `None` when TBank's figure is not a number.
+    pub fn amount_for(&self, account_id: &str) -> Option<Decimal> {
+        let amount = self.transaction_amount.trim().parse::<Decimal>().ok()?;
+        Some(if self.account_from == account_id { -amount } else { amount })
+    }
+
+    /// The currency TBank gave, or `fallback` when it left it out or sent something else.
+    pub fn currency_or(&self, fallback: Currency) -> Currency {
+        self.currency.as_deref()
+            .and_then(|currency| currency.parse().ok())
+            .unwrap_or(fallback)
+    }
+
+    /// Balance of `account_id` right after this transaction, when TBank reports it.
+    pub fn balance_for(&self, account_id: &str) -> Option<&str> {
+        let balance = if self.account_from == account_id { &self.account_from_balance } else { &self.account_to_balance };
+        balance.as_deref()
+    }
+
+    /// The `yyyy-MM-dd` part of the transaction date.
+    pub fn date(&self) -> &str {
+        self.transaction_date.get(..10).unwrap_or(&self.transaction_date)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::money::HOME_CURRENCY;
+    use super::*;
+
+    #[test]
+    fn cash_orders_need_a_positive_amount() {
+        let sgd = |amount: &str| Money::new(amount.parse().unwrap(), HOME_CURRENCY);
+        assert_eq!(CashOrder::new(CashMovement::Deposit, "100", sgd("0"), None), None);
+        assert_eq!(CashOrder::new(CashMovement::Deposit, "100", sgd("-5"), None), None);
+
+        let order = CashOrder::new(CashMovement::Withdrawal, " 100 ", sgd("12.5"), Some("  ".to_owned())).unwrap();
+        assert_eq!(order.account_id, "100");
+        assert_eq!(order.amount.amount_text(), "12.50");
+        assert_eq!(order.narrative, "Cash withdrawal");
+        // TBank gets the bare figure
+        let content = serde_json::to_value(order.into_request(&CustomerRequest::default()).content()).unwrap();
+        assert_eq!(content["amount"], "12.50");
+    }
+
+    #[test]
+    fn transfers_keep_their_currency_but_send_bare_figures() {
+        let usd: Currency = "USD".parse().unwrap();
+        let transfer = TransferBody {
+            account_from: "100".to_owned(),
+            account_to: "200".to_owned(),
+            transaction_amount: Money::new(Decimal::new(12345, 1), usd),
+            transaction_reference_number: "".to_owned(),
+            narrative: "".to_owned(),
+        };
+        // Saved conversations must not fall back to the home currency
+        let saved: TransferBody = serde_json::from_value(serde_json::to_value(&transfer).unwrap()).unwrap();
+        assert_eq!(saved.transaction_amount.currency, usd);
+        assert_ne!(usd, HOME_CURRENCY);
+        assert_eq!(serde_json::to_value(transfer.content()).unwrap()["transactionAmount"], "1234.50");
+    }
+
+    #[test]
+    fn history_rows_keep_what_tbank_sent() {
+        let mut detail = TransactionDetail {
+            transaction_id: "1".to_owned(),
+            account_from: "100".to_owned(),
+            account_to: "200".to_owned(),
+            transaction_amount: " 12.5 ".to_owned(),
+            transaction_date: "2023-01-05T10:00:00".to_owned(),
+            narrative: None,
+            account_from_balance: None,
+            account_to_balance: None,
+            currency: None,
+        };
+        assert_eq!(detail.amount_for("100"), Some(Decimal::new(-125, 1)));
+        assert_eq!(detail.amount_for("200"), Some(Decimal::new(125, 1)));
+        let usd: Currency = "USD".parse().unwrap();
+        assert_eq!(detail.currency_or(usd), usd);
+        detail.currency = Some("jpy".to_owned());
+        assert_eq!(detail.currency_or(usd).to_string(), "JPY");
+
+        detail.transaction_amount = "12,50".to_owned();
+        detail.currency = Some("".to_owned());
+        assert_eq!(detail.amount_for("100"), None);
+        assert_eq!(detail.currency_or(usd), usd);
+    }
+
+    #[test]
+    fn payee_details_are_checked() {
+        assert_eq!(AddBeneficiaryBody::parse_account_id(" 0000002134 "), Ok("0000002134".to_owned()));
+        assert_eq!(AddBeneficiaryBody::parse_account_id("12-34"), Err(BeneficiaryError::InvalidAccountId));
+        assert_eq!(AddBeneficiaryBody::parse_account_id(""), Err(BeneficiaryError::InvalidAccountId));
+        assert_eq!(AddBeneficiaryBody::parse_description("  Mum's   savings "), Ok("Mum's savings".to_owned()));
+        assert_eq!(AddBeneficiaryBody::parse_description(" "), Err(BeneficiaryError::InvalidDescription));
+        assert_eq!(AddBeneficiaryBody::parse_description(&"x".repeat(51)), Err(BeneficiaryError::InvalidDescription));
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use crate::repositories::state_store::StateStore;
+
+/// Process-local store for running without Redis. Expired keys are dropped when next touched.
+#[derive(Clone, Default)]
+pub struct MemoryRepository {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+struct Entry {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_live(&self, now: Instant) -> bool {
+        self.expires_at.map(|expires_at| expires_at > now).unwrap_or(true)
+    }
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn insert(&self, key: &str, value: String, ttl: Option<Duration>) {
+        let expires_at = ttl.map(|ttl| Instant::now() + ttl);
+        self.entries.lock().unwrap().insert(key.to_owned(), Entry { value, expires_at });
+    }
+
+    fn take_live(&self, key: &str, remove: bool) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        match entries.get(key) {
+            Some(entry) if entry.is_live(now) && !remove => Some(entry.value.clone()),
+            Some(entry) if entry.is_live(now) => entries.remove(key).map(|entry| entry.value),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Glob match supporting only `*`, which is all the bot uses.
+    fn matches(pattern: &str, key: &str) -> bool {
+        match pattern.split_once('*') {
+            None => pattern == key,
+            Some((head, rest)) => {
+                let Some(key) = key.strip_prefix(head) else {
+                    return false;
+                };
+                (0..=key.len())
+                    .filter(|at| key.is_char_boundary(*at))
+                    .any(|at| Self::matches(rest, &key[at..]))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StateStore for MemoryRepository {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.take_live(key, false))
+    }
+
+    async fn set(&self, key: &str, value: String) -> anyhow::Result<()> {
+        self.insert(key, value, None);
+        Ok(())
+    }
+
+    async fn set_with_ttl(&self, key: &str, value: String, ttl_secs: u64) -> anyhow::Result<()> {
+        self.insert(key, value, Some(Duration::from_secs(ttl_secs.max(1))));
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn get_and_delete(&self, key: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.take_live(key, true))
+    }
+
+    async fn expire(&self, key: &str, ttl_secs: u64) -> anyhow::Result<()> {
+        if let Some(value) = self.take_live(key, false) {
+            self.insert(key, value, Some(Duration::from_secs(ttl_secs.max(1))));
+        }
+        Ok(())
+    }
+
+    async fn keys(&self, pattern: &str) -> anyhow::Result<Vec<String>> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| entry.is_live(now));
+        Ok(entries.keys().filter(|key| Self::matches(pattern, key)).cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn values_round_trip_until_deleted() {
+        let store = MemoryRepository::new();
+        assert_eq!(store.get("a").await.unwrap(), None);
+        store.set("a", "1".to_owned()).await.unwrap();
+        assert_eq!(store.get("a").await.unwrap().as_deref(), Some("1"));
+        store.delete("a").await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn expired_values_are_gone() {
+        let store = MemoryRepository::new();
+        store.insert("short", "1".to_owned(), Some(Duration::from_millis(20)));
+        store.set_with_ttl("long", "2".to_owned(), 60).await.unwrap();
+        assert!(store.get("short").await.unwrap().is_some());
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(store.get("short").await.unwrap(), None);
+        assert_eq!(store.get_and_delete("short").await.unwrap(), None);
+        assert_eq!(store.keys("*").await.unwrap(), ["long"]);
+    }
+
+    #[tokio::test]
+    async fn get_and_delete_hands_the_value_out_once() {
+        let store = MemoryRepository::new();
+        store.set("pending", "transfer".to_owned()).await.unwrap();
+        assert_eq!(store.get_and_delete("pending").await.unwrap().as_deref(), Some("transfer"));
+        assert_eq!(store.get_and_delete("pending").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn keys_match_wildcards() {
+        let store = MemoryRepository::new();
+        for key in ["1:LoginCred", "22:LoginCred", "1:conversation"] {
+            store.set(key, "".to_owned()).await.unwrap();
+        }
+        let mut keys = store.keys("*:LoginCred").await.unwrap();
+        keys.sort();
+        assert_eq!(keys, ["1:LoginCred", "22:LoginCred"]);
+        assert_eq!(store.keys("1:conversation").await.unwrap(), ["1:conversation"]);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod tbank_repository;
-pub mod redis_repository;
\ No newline at end of file
+pub mod redis_repository;
+pub mod memory_repository;
+pub mod state_store;
\ No newline at end of file
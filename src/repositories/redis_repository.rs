@@ -1,8 +1,10 @@
 use anyhow::anyhow;
+use async_trait::async_trait;
 use bb8_redis::RedisConnectionManager;
 use bb8_redis::bb8::Pool;
-use bb8_redis::redis::{AsyncCommands, RedisError};
+use bb8_redis::redis::{self, AsyncCommands, AsyncIter, RedisError};
 use tracing::warn;
+use crate::repositories::state_store::StateStore;
 
 const REDIS_PREFIX: &str = "usr";
 #[derive(Clone)]
@@ -11,64 +13,70 @@ pub struct RedisRepository {
 }
 
 impl RedisRepository {
-    pub async fn new(redis_url: String) -> Self {
-        let redis_manager = RedisConnectionManager::new(redis_url.clone()).unwrap();
-        let redis_pool = Pool::builder().build(redis_manager).await.unwrap();
-        Self {
+    pub async fn new(redis_url: String) -> anyhow::Result<Self> {
+        let redis_manager = RedisConnectionManager::new(redis_url.clone())?;
+        let redis_pool = Pool::builder().build(redis_manager).await?;
+        Ok(Self {
             redis_client: redis_pool,
-        }
+        })
+    }
+
+    fn prefixed(key: &str) -> String {
+        format!("{}:{}", REDIS_PREFIX, key)
     }
 
-    //REDIS
-    pub async fn get_data_from_redis(self, key: &String) -> anyhow::Result<String> {
-        let mut redis_conn = self.redis_client.get().await.unwrap();
-        let redis_key = format!("{}:{}", REDIS_PREFIX, key);
-        let res = redis_conn.get(redis_key.clone()).await;
-        return match res {
-            Ok(data) => Ok(data),
-            Err(e) => {
-                warn!("Something went wrong populating redis: {:?}", e);
-                Err(anyhow!("Something went wrong!"))
-            }
-        };
+    fn failed<T>(res: Result<T, RedisError>) -> anyhow::Result<T> {
+        res.map_err(|e| {
+            warn!("Something went wrong populating redis: {:?}", e);
+            anyhow!("Something went wrong!")
+        })
     }
+}
 
-    pub async fn set_data_in_redis(self, key: &String, value: String, to_expire: bool) -> anyhow::Result<()> {
+//REDIS
+#[async_trait]
+impl StateStore for RedisRepository {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
         let mut redis_conn = self.redis_client.get().await?;
-        if to_expire {
-            //2 min TTL
-            let res : Result<(), RedisError> = redis_conn.set_ex(format!("{}:{}", REDIS_PREFIX, key.clone()), value, 120).await;
-            return match res {
-                Ok(_) => Ok(()),
-                Err(e) => {
-                    warn!("Something went wrong populating redis: {:?}", e);
-                    Err(anyhow!("Something went wrong!"))
-                }
-            };
-        }else{
-            // No ttl
-            let res : Result<(), RedisError> = redis_conn.set(format!("{}:{}", REDIS_PREFIX, key.clone()), value).await;
-            return match res {
-                Ok(_) => Ok(()),
-                Err(e) => {
-                    warn!("Something went wrong populating redis: {:?}", e);
-                    Err(anyhow!("Something went wrong!"))
-                }
-            };
-        }
+        Self::failed(redis_conn.get(Self::prefixed(key)).await)
     }
 
+    async fn set(&self, key: &str, value: String) -> anyhow::Result<()> {
+        let mut redis_conn = self.redis_client.get().await?;
+        // No ttl
+        Self::failed(redis_conn.set(Self::prefixed(key), value).await)
+    }
 
-    pub async fn remove_data_in_redis(self, key: &String) -> anyhow::Result<()> {
+    async fn set_with_ttl(&self, key: &str, value: String, ttl_secs: u64) -> anyhow::Result<()> {
         let mut redis_conn = self.redis_client.get().await?;
-        //1 day ttl
-        let res : Result<(), RedisError> = redis_conn.del(format!("{}:{}", REDIS_PREFIX, key.clone())).await;
-        return match res {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                warn!("Something went wrong populating redis: {:?}", e);
-                Err(anyhow!("Something went wrong!"))
-            }
-        };
+        // Redis refuses a zero TTL
+        let ttl = ttl_secs.max(1) as usize;
+        Self::failed(redis_conn.set_ex(Self::prefixed(key), value, ttl).await)
     }
-}
\ No newline at end of file
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let mut redis_conn = self.redis_client.get().await?;
+        Self::failed(redis_conn.del(Self::prefixed(key)).await)
+    }
+
+    async fn get_and_delete(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let mut redis_conn = self.redis_client.get().await?;
+        Self::failed(redis::cmd("GETDEL").arg(Self::prefixed(key)).query_async(&mut *redis_conn).await)
+    }
+
+    async fn expire(&self, key: &str, ttl_secs: u64) -> anyhow::Result<()> {
+        let mut redis_conn = self.redis_client.get().await?;
+        Self::failed(redis_conn.expire(Self::prefixed(key), ttl_secs.max(1) as usize).await)
+    }
+
+    async fn keys(&self, pattern: &str) -> anyhow::Result<Vec<String>> {
+        let mut redis_conn = self.redis_client.get().await?;
+        let prefix = Self::prefixed("");
+        let mut iter: AsyncIter<String> = Self::failed(redis_conn.scan_match(Self::prefixed(pattern)).await)?;
+        let mut keys = vec![];
+        while let Some(key) = iter.next_item().await {
+            keys.push(key.strip_prefix(&prefix).unwrap_or(&key).to_owned());
+        }
+        Ok(keys)
+    }
+}
@@ -0,0 +1,23 @@
+use async_trait::async_trait;
+
+/// Key-value storage for chat state. Keys are namespaced by the implementation.
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    /// `None` when the key is missing or has expired.
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>>;
+
+    async fn set(&self, key: &str, value: String) -> anyhow::Result<()>;
+
+    async fn set_with_ttl(&self, key: &str, value: String, ttl_secs: u64) -> anyhow::Result<()>;
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+
+    /// Reads and removes the key in one step, so only one caller can ever get the value.
+    async fn get_and_delete(&self, key: &str) -> anyhow::Result<Option<String>>;
+
+    /// Resets the TTL of an existing key.
+    async fn expire(&self, key: &str, ttl_secs: u64) -> anyhow::Result<()>;
+
+    /// Keys matching a pattern where `*` matches any run of characters.
+    async fn keys(&self, pattern: &str) -> anyhow::Result<Vec<String>>;
+}
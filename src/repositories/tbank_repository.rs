@@ -1,18 +1,34 @@
-use anyhow::anyhow;
 use axum::body::Bytes;
-use futures_util::task::waker;
 use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderValue};
-use serde_json::Value;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use tracing::{warn, info};
-use crate::models::customer::{AccountData, GetCustomerAccounts, GetCustomerDetails, HistoricalMonthlyBalanceBody, OnBoardCustomerData};
+use crate::models::customer::{AccountData, GetCustomerAccounts, GetCustomerDetails, HistoricalMonthlyBalanceBody, OnBoardCustomerData, OnBoardCustomerResponse, OpenDepositAccountBody, ReplyOpenDepositAccount};
 use crate::models::{TBankResponse, Error, ServiceResponseHeader, CustomerRequest};
-use urlencoding::encode;
 use crate::enums::beneficiary::BeneficiaryEnum;
+use crate::errors::tbank_error::TBankError;
 use crate::models;
 use crate::models::authentication::{RequestOTP, ServiceLoginOtpResponse};
-use crate::models::chart::ChartBody;
-use crate::models::transaction::{AddBeneficiaryBody, Beneficiaries, TransferBody, Beneficiary};
+use crate::models::chart::{ChartBody, MonthlyBalanceTrend};
+use crate::models::money::HOME_CURRENCY;
+use crate::models::transaction::{AddBeneficiaryBody, TransferBody, BeneficiaryList, BeneficiaryListBody, DeleteBeneficiaryBody, UpdateBeneficiaryBody, DepositRequest, DepositResponse, TransactionDetail, TransactionHistory, TransactionHistoryBody};
 
+const RIB_CONSUMER: &str = "RIB";
+const TELLER_CONSUMER: &str = "Teller";
+// For services that only take a Header
+const NO_CONTENT: Option<&()> = None;
+
+/// Content for the services that take it nested under a `Content` key, as `openDepositAccount` always has.
+/// The other services take their content as it is.
+#[derive(Serialize)]
+struct Wrapped<'a, T> {
+    #[serde(rename = "Content")]
+    content: &'a T,
+}
+
+fn wrapped<T>(content: &T) -> Wrapped<'_, T> {
+    Wrapped { content }
+}
 
 #[allow(dead_code)]
 #[derive(Clone)]
@@ -23,8 +39,7 @@ pub struct TBankRepository {
 }
 
 impl TBankRepository {
-    pub fn new(tbank_url: String, chart_url: String) -> Self {
-        let client = reqwest::Client::new();
+    pub fn new(client: reqwest::Client, tbank_url: String, chart_url: String) -> Self {
         Self {
             client,
             tbank_url,
@@ -32,333 +47,655 @@ impl TBankRepository {
         }
     }
 
-    //TBANK
-    pub async fn onboard_customer(self, body: OnBoardCustomerData) -> anyhow::Result<serde_json::Value> {
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let serde_body = serde_json::to_string(&body).unwrap();
-        let encoded_header = encode(r#"{"serviceName":"onboardCustomer","userID":"","PIN":"","OTP":""}"#).to_string();
-        let encoded_content = encode(&serde_body).to_string();
-        let url = format!("{}?Header={}Content={}", self.tbank_url, encoded_header, encoded_content);
-        let req = self.client
-            .post(url)
-            .headers(headers)
-            .send()
-            .await;
-        let res = match req {
-            Ok(res) => {
-                Ok(res.json::<serde_json::Value>().await.unwrap())
-                // Ok(res.json::<TBankResponse<ReplyOnboardCustomer>>().await.unwrap())
-            }
-            Err(e) => {
-                warn!("{}", e);
-                Err(anyhow!("Something went wrong with the OnboardCustomer API."))
-            }
+    /// Calls a TBank service using the `Header=`/`Content=`/`ConsumerID=` query protocol.
+    /// The header's `serviceName` is always replaced by `service_name`, and `content` is sent as it is.
+    pub async fn invoke<Req: Serialize, Resp: DeserializeOwned>(
+        &self,
+        service_name: &str,
+        header: &CustomerRequest,
+        content: Option<&Req>,
+        consumer_id: Option<&str>,
+    ) -> Result<TBankResponse<Resp>, TBankError> {
+        let header = CustomerRequest {
+            service_name: service_name.to_owned(),
+            ..header.clone()
         };
-        res
-    }
+        let mut query = vec![("Header", serde_json::to_string(&header)?)];
+        if let Some(content) = content {
+            query.push(("Content", serde_json::to_string(content)?));
+        }
+        if let Some(consumer_id) = consumer_id {
+            query.push(("ConsumerID", consumer_id.to_owned()));
+        }
 
-    pub async fn request_otp(self, body: RequestOTP) -> anyhow::Result<TBankResponse<ServiceResponseHeader<Error>>> {
         let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let serde_body = serde_json::to_string(&body).unwrap();
-        let consumer_id = encode("RIB").to_string();
-        let encoded_header = encode(&serde_body).to_string();
-        let url = format!("{}?Header={}ConsumerID={}", self.tbank_url, encoded_header, consumer_id);
-        let req = self.client
-            .post(url)
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let res = self.client
+            .post(&self.tbank_url)
+            .query(&query)
             .headers(headers)
             .send()
-            .await;
-        let res = match req {
-            Ok(res) => {                
-                Ok(res.json::<TBankResponse<ServiceResponseHeader<Error>>>().await.unwrap())
-            }
-            Err(e) => {
-                warn!("{}", e);
-                Err(anyhow!("Something went wrong with the RequestOTP API."))
+            .await
+            .map_err(|e| {
+                warn!("{} : {}", service_name, e);
+                TBankError::from(e)
+            })?;
+
+        if res.status().is_server_error() {
+            warn!("{} : TBank responded with {}", service_name, res.status());
+            return Err(TBankError::ServiceUnavailable);
+        }
+        let bytes = res.bytes().await?;
+        serde_json::from_slice::<TBankResponse<Resp>>(&bytes).map_err(|e| {
+            // Rejected calls usually only carry the ServiceRespHeader
+            if let Ok(reply) = serde_json::from_slice::<TBankResponse<ServiceResponseHeader<Error>>>(&bytes) {
+                if let Err(rejected) = reply.content.service_response.service_response_header.ensure_success() {
+                    return rejected;
+                }
             }
+            warn!("{} : could not parse response : {}", service_name, e);
+            TBankError::from(e)
+        })
+    }
+
+    //TBANK
+    pub async fn onboard_customer(&self, body: OnBoardCustomerData) -> Result<OnBoardCustomerResponse, TBankError> {
+        let res = self.invoke::<_, OnBoardCustomerResponse>("onboardCustomer", &CustomerRequest::default(), Some(&body), None).await?;
+        let reply = res.content.service_response;
+        reply.status().ensure_success()?;
+        Ok(reply)
+    }
+
+    pub async fn request_otp(&self, body: RequestOTP) -> Result<TBankResponse<ServiceResponseHeader<Error>>, TBankError> {
+        let header = CustomerRequest {
+            service_name: body.service_name,
+            user_id: body.user_id,
+            pin: body.pin,
+            otp: "".to_owned(),
         };
-        res
+        self.invoke("requestOTP", &header, NO_CONTENT, Some(RIB_CONSUMER)).await
     }
 
-    pub async fn login_customer(self, body: CustomerRequest) -> anyhow::Result<TBankResponse<ServiceLoginOtpResponse>> {
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let serde_body = serde_json::to_string(&body).unwrap();
-        let consumer_id = encode("RIB").to_string();
-        let encoded_header = encode(&serde_body).to_string();
-        let url = format!("{}?Header={}ConsumerID={}", self.tbank_url, encoded_header, consumer_id);
-        let req = self.client
-            .post(url)
-            .headers(headers)
-            .send()
-            .await;
-        let res = match req {
-            Ok(res) => {
-                Ok(res.json::<TBankResponse<ServiceLoginOtpResponse>>().await.unwrap())
-            }
-            Err(e) => {
-                warn!("{}", e);
-                Err(anyhow!("Something went wrong with the RequestOTP API."))
-            }
+    pub async fn login_customer(&self, body: CustomerRequest) -> Result<TBankResponse<ServiceLoginOtpResponse>, TBankError> {
+        self.invoke("loginCustomer", &body, NO_CONTENT, Some(RIB_CONSUMER)).await
+    }
+
+    /// Opens a deposit account in `HOME_CURRENCY`. The bot only opens MicroInvest accounts,
+    /// and letting customers open accounts in other currencies is out of its scope.
+    pub async fn create_account(&self, body: CustomerRequest) -> Result<String, TBankError> {
+        let content = OpenDepositAccountBody {
+            product_id: "101".to_owned(),
+            opening_balance: "0".to_owned(),
+            currency: HOME_CURRENCY,
+            is_restricted: false,
+            is_service_charge_waived: true,
+            is_minor: false,
+            make_default_account: false,
         };
-        res
+        let res = self.invoke::<_, ReplyOpenDepositAccount>("openDepositAccount", &body, Some(&wrapped(&content)), Some(TELLER_CONSUMER)).await?;
+        let reply = res.content.service_response;
+        info!("{:?}", reply);
+        reply.service_response_header.ensure_success()?;
+        match reply.account_id {
+            Some(account_id) => Ok(account_id.content),
+            None => Err(TBankError::MalformedResponse("openDepositAccount did not return an accountID".to_owned())),
+        }
     }
 
-    pub async fn create_account(self, body: CustomerRequest) -> anyhow::Result<String> {
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let header = &serde_json::to_string(&body).unwrap();
-        let encoded_header = encode(header);
-        let encoded_content =
-            r#"{"Content":{"productID":"101","openingBalance":"0","currency":"SGD","isRestricted":false,"isServiceChargeWaived":true,"isMinor":false,"makeDefaultAccount":false}}"#;
-        let url = format!("{}?Header={}&Content={}&ConsumerID={}", self.tbank_url, encoded_header, encoded_content, "Teller");
-        let req = self.client
-            .post(url)
-            .headers(headers)
-            .send()
-            .await;
-        let res = match req {
-            Ok(res) => {
-                let temp = res.json::<Value>().await.unwrap();
-                info!("{:?}", temp);
-                Ok(temp["Content"]["ServiceResponse"]["accountID"]["_content_"].to_string())
-            }
-            Err(e) => {
-                warn!("{}", e);
-                Err(anyhow!("Something went wrong with the RequestOTP API."))
-            }
+    pub async fn get_customer_accounts(&self, body: CustomerRequest) -> Result<Vec<AccountData>, TBankError> {
+        let res = self.invoke::<_, GetCustomerAccounts<AccountData>>("getCustomerAccounts", &body, NO_CONTENT, Some(RIB_CONSUMER)).await?;
+        Ok(res.content.service_response.account_list.account.into())
+    }
+
+    pub async fn get_customer_details(&self, body: CustomerRequest) -> Result<TBankResponse<GetCustomerDetails>, TBankError> {
+        self.invoke("getCustomerDetails", &body, NO_CONTENT, Some(RIB_CONSUMER)).await
+    }
+
+    pub async fn get_beneficiaries(&self, body: CustomerRequest, beneficiary_type: BeneficiaryEnum) -> Result<Vec<models::transaction::Beneficiary>, TBankError> {
+        let content = BeneficiaryListBody {
+            account_group: beneficiary_type.to_string(),
         };
-        res
+        let res = self.invoke::<_, BeneficiaryList>("getBeneficiaryList", &body, Some(&wrapped(&content)), Some(RIB_CONSUMER)).await?;
+        Ok(res.content.service_response.beneficiary_list
+            .map(|list| list.beneficiary.into())
+            .unwrap_or_default())
+    }
+
+    pub async fn add_beneficiary(&self, body: CustomerRequest, content: AddBeneficiaryBody) -> Result<(), TBankError> {
+        let res = self.invoke::<_, ServiceResponseHeader<Error>>("addBeneficiary", &body, Some(&content), None).await?;
+        res.content.service_response.service_response_header.ensure_success()
+    }
+
+    /// Changes the label a payee is listed under.
+    pub async fn update_beneficiary(&self, body: CustomerRequest, content: UpdateBeneficiaryBody) -> Result<(), TBankError> {
+        let res = self.invoke::<_, ServiceResponseHeader<Error>>("updateBeneficiary", &body, Some(&wrapped(&content)), Some(RIB_CONSUMER)).await?;
+        res.content.service_response.service_response_header.ensure_success()
+    }
+
+    pub async fn delete_beneficiary(&self, body: CustomerRequest, content: DeleteBeneficiaryBody) -> Result<(), TBankError> {
+        let res = self.invoke::<_, ServiceResponseHeader<Error>>("deleteBeneficiary", &body, Some(&wrapped(&content)), Some(RIB_CONSUMER)).await?;
+        res.content.service_response.service_response_header.ensure_success()
+    }
+
+    pub async fn transfer(&self, body: CustomerRequest, content: TransferBody) -> Result<(), TBankError> {
+        let res = self.invoke::<_, ServiceResponseHeader<Error>>("creditTransfer", &body, Some(&content.content()), Some(RIB_CONSUMER)).await?;
+        res.content.service_response.service_response_header.ensure_success()
+    }
+
+    /// Teller service: credits cash to the account.
+    pub async fn deposit_cash(&self, body: DepositRequest) -> Result<DepositResponse, TBankError> {
+        self.move_cash("depositCash", body).await
+    }
+
+    /// Teller service: debits cash from the account.
+    pub async fn withdraw_cash(&self, body: DepositRequest) -> Result<DepositResponse, TBankError> {
+        self.move_cash("withdrawCash", body).await
     }
 
-    pub async fn get_customer_accounts(self, body: CustomerRequest) -> anyhow::Result<Vec<AccountData>> {
+    async fn move_cash(&self, service_name: &str, body: DepositRequest) -> Result<DepositResponse, TBankError> {
+        let res = self.invoke::<_, DepositResponse>(service_name, &body.header(), Some(&wrapped(&body.content())), Some(TELLER_CONSUMER)).await?;
+        let reply = res.content.service_response;
+        reply.status().ensure_success()?;
+        Ok(reply)
+    }
+
+    /// One page of the account's transactions, newest first.
+    pub async fn get_transaction_history(&self, body: CustomerRequest, content: TransactionHistoryBody) -> Result<Vec<TransactionDetail>, TBankError> {
+        let res = self.invoke::<_, TransactionHistory>("getTransactionHistory", &body, Some(&wrapped(&content)), Some(RIB_CONSUMER)).await?;
+        let reply = res.content.service_response;
+        reply.service_response_header.ensure_success()?;
+        Ok(reply.transaction_detail
+            .map(|details| details.transaction_detail.into())
+            .unwrap_or_default())
+    }
+
+    pub async fn get_balance_chart(&self, body: &ChartBody) -> Result<Bytes, TBankError> {
+        let serde_body = serde_json::to_string(body)?;
         let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let serde_body = serde_json::to_string(&body).unwrap();
-        let consumer_id = encode("RIB").to_string();
-        let encoded_header = encode(&serde_body).to_string();
-        let url = format!("{}?Header={}ConsumerID={}", self.tbank_url, encoded_header, consumer_id);
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
         let req = self.client
-            .post(url)
+            .post(self.chart_url.clone())
             .headers(headers)
+            .body(serde_body)
             .send()
             .await;
-        let res = match req {
+        match req {
             Ok(res) => {
-                let mut vec_to_return = Vec::new();
-                let json_val = res.json::<Value>().await.unwrap();
-                let result_single = serde_json::from_value::<TBankResponse<GetCustomerAccounts<AccountData>>>(json_val.clone());
-                if result_single.is_ok() {
-                    vec_to_return.push(result_single.unwrap().content.service_response.account_list.account)
-                }else{
-                    let result_multiple = serde_json::from_value::<TBankResponse<GetCustomerAccounts<Vec<AccountData>>>>(json_val.clone());
-                    if result_multiple.is_ok(){
-                        vec_to_return = result_multiple.unwrap().content.service_response.account_list.account;
-                    }else{
-                        return Err(anyhow!("Something went wrong with the RequestOTP API."))
-                    }
+                if !res.status().is_success() {
+                    warn!("Chart generator responded with {}", res.status());
+                    return Err(TBankError::ServiceUnavailable);
                 }
-                Ok(vec_to_return)
+                Ok(res.bytes().await?)
             }
             Err(e) => {
                 warn!("{}", e);
-                Err(anyhow!("Something went wrong with the RequestOTP API."))
+                Err(TBankError::from(e))
             }
+        }
+    }
+
+    pub async fn get_monthly_balance_trend(&self, body: CustomerRequest, content: HistoricalMonthlyBalanceBody) -> Result<ChartBody, TBankError> {
+        let res = self.invoke::<_, MonthlyBalanceTrend>("getMonthlyBalanceTrend", &body, Some(&content), Some(RIB_CONSUMER)).await?;
+        let reply = res.content.service_response;
+        reply.service_response_header.ensure_success()?;
+        Ok(reply.trend_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::{Arc, Mutex};
+    use axum::extract::Query;
+    use axum::http::StatusCode;
+    use axum::Router;
+    use axum::routing::post;
+    use rust_decimal::Decimal;
+    use serde_json::{json, Value};
+    use crate::models::money::Money;
+    use crate::models::registration::{Registration, RegistrationField};
+    use crate::testing::mock_tbank::{MockTBank, MOCK_OTP};
+    use super::*;
+
+    /// Starts a server that answers every POST with the given status and body.
+    fn serve_fixed(status: StatusCode, body: &'static str) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", post(move || async move { (status, body) }));
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+        addr
+    }
+
+    // The query of each request a test server got, in order
+    type Recorded = Arc<Mutex<Vec<HashMap<String, String>>>>;
+
+    /// Starts a server that keeps the query of every POST it gets and answers each with an empty object.
+    fn serve_recording() -> (SocketAddr, Recorded) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let seen = Arc::new(Mutex::new(vec![]));
+        let recorder = seen.clone();
+        let app = Router::new().route("/", post(move |Query(query): Query<HashMap<String, String>>| async move {
+            recorder.lock().unwrap().push(query);
+            "{}"
+        }));
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+        (addr, seen)
+    }
+
+    fn repository(body: &'static str) -> TBankRepository {
+        let addr = serve_fixed(StatusCode::OK, body);
+        TBankRepository::new(reqwest::Client::new(), format!("http://{}/", addr), format!("http://{}/", addr))
+    }
+
+    fn sgd(amount: &str) -> Money {
+        Money::parse(amount, HOME_CURRENCY).unwrap()
+    }
+
+    fn customer() -> CustomerRequest {
+        CustomerRequest {
+            service_name: "".to_owned(),
+            user_id: "user".to_owned(),
+            pin: "123456".to_owned(),
+            otp: "999999".to_owned(),
+        }
+    }
+
+    fn transfer_body() -> TransferBody {
+        TransferBody {
+            account_from: "1".to_owned(),
+            account_to: "2".to_owned(),
+            transaction_amount: sgd("1.00"),
+            transaction_reference_number: "1".to_owned(),
+            narrative: "".to_owned(),
+        }
+    }
+
+    fn onboard_body() -> OnBoardCustomerData {
+        serde_json::from_value(json!({
+            "serviceName": "onboardCustomer", "IC_number": "", "familyName": "", "givenName": "",
+            "dateOfBirth": "", "gender": "", "occupation": "", "streetAddress": "", "city": "",
+            "state": "", "country": "", "postalCode": "", "countryCode": "", "mobileNumber": "",
+            "preferredUserID": "", "currency": "", "bankID": ""
+        })).unwrap()
+    }
+
+    /// Calls every TBank service against a server answering with `body`.
+    async fn call_every_service(repo: &TBankRepository) -> Vec<(&'static str, Result<(), TBankError>)> {
+        let otp = RequestOTP {
+            service_name: "requestOTP".to_owned(),
+            user_id: "user".to_owned(),
+            pin: "123456".to_owned(),
+        };
+        let add_ben = AddBeneficiaryBody {
+            account_id: "2".to_owned(),
+            description: "".to_owned(),
+        };
+        let trend = HistoricalMonthlyBalanceBody {
+            account_id: "1".to_owned(),
+            num_months: "6".to_owned(),
         };
-        res
+        vec![
+            ("onboardCustomer", repo.onboard_customer(onboard_body()).await.map(|_| ())),
+            ("requestOTP", repo.request_otp(otp).await.map(|_| ())),
+            ("loginCustomer", repo.login_customer(customer()).await.map(|_| ())),
+            ("openDepositAccount", repo.create_account(customer()).await.map(|_| ())),
+            ("getCustomerAccounts", repo.get_customer_accounts(customer()).await.map(|_| ())),
+            ("getCustomerDetails", repo.get_customer_details(customer()).await.map(|_| ())),
+            ("getBeneficiaryList", repo.get_beneficiaries(customer(), BeneficiaryEnum::OTHER).await.map(|_| ())),
+            ("addBeneficiary", repo.add_beneficiary(customer(), add_ben).await),
+            ("updateBeneficiary", repo.update_beneficiary(customer(), UpdateBeneficiaryBody { beneficiary_id: "1".to_owned(), description: "".to_owned() }).await),
+            ("deleteBeneficiary", repo.delete_beneficiary(customer(), DeleteBeneficiaryBody { beneficiary_id: "1".to_owned() }).await),
+            ("creditTransfer", repo.transfer(customer(), transfer_body()).await),
+            ("getMonthlyBalanceTrend", repo.get_monthly_balance_trend(customer(), trend).await.map(|_| ())),
+        ]
     }
 
-    pub async fn get_customer_details(self, body: CustomerRequest) -> anyhow::Result<TBankResponse<GetCustomerDetails>> {
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let serde_body = serde_json::to_string(&body).unwrap();
-        let consumer_id = encode("RIB").to_string();
-        let encoded_header: String = encode(&serde_body).to_string();
-        let url = format!("{}?Header={}ConsumerID={}", self.tbank_url, encoded_header, consumer_id);
-        let req = self.client
-            .post(url)
-            .headers(headers)
-            .send()
-            .await;
-        let res = match req {
-            Ok(res) => {
-                
-                Ok(res.json::<TBankResponse<GetCustomerDetails>>().await.unwrap())
-            }
-            Err(e) => {
-                warn!("{}", e);
-                Err(anyhow!("Something went wrong with the RequestOTP API."))
-            }
+    #[tokio::test]
+    async fn every_service_is_sent_in_its_own_request_shape() {
+        let (addr, seen) = serve_recording();
+        let repo = TBankRepository::new(reqwest::Client::new(), format!("http://{}/", addr), format!("http://{}/", addr));
+        call_every_service(&repo).await;
+        let requests = seen.lock().unwrap().clone();
+
+        // Service, whether its content is nested under a `Content` key, and its ConsumerID.
+        // The services the bot had before `invoke` keep the shape they were first sent in.
+        let expected = [
+            ("onboardCustomer", Some(false), None),
+            ("requestOTP", None, Some("RIB")),
+            ("loginCustomer", None, Some("RIB")),
+            ("openDepositAccount", Some(true), Some("Teller")),
+            ("getCustomerAccounts", None, Some("RIB")),
+            ("getCustomerDetails", None, Some("RIB")),
+            ("getBeneficiaryList", Some(true), Some("RIB")),
+            ("addBeneficiary", Some(false), None),
+            ("updateBeneficiary", Some(true), Some("RIB")),
+            ("deleteBeneficiary", Some(true), Some("RIB")),
+            ("creditTransfer", Some(false), Some("RIB")),
+            ("getMonthlyBalanceTrend", Some(false), Some("RIB")),
+        ];
+        assert_eq!(requests.len(), expected.len());
+        for (service, nested, consumer_id) in expected {
+            let query = requests.iter()
+                .find(|query| serde_json::from_str::<Value>(&query["Header"]).unwrap()["serviceName"] == service)
+                .unwrap_or_else(|| panic!("{} was not sent", service));
+            let content = query.get("Content").map(|raw| serde_json::from_str::<Value>(raw).unwrap());
+            assert_eq!(content.map(|content| content.get("Content").is_some()), nested, "{} content", service);
+            assert_eq!(query.get("ConsumerID").map(String::as_str), consumer_id, "{} ConsumerID", service);
+        }
+        let transfer = requests.iter().find(|query| query["Header"].contains("creditTransfer")).unwrap();
+        let content: Value = serde_json::from_str(&transfer["Content"]).unwrap();
+        assert_eq!(content["accountFrom"], "1");
+    }
+
+    #[tokio::test]
+    async fn invalid_json_is_a_malformed_response() {
+        for (service, result) in call_every_service(&repository("{not json")).await {
+            assert!(matches!(result, Err(TBankError::MalformedResponse(_))), "{} did not fail cleanly", service);
+        }
+    }
+
+    #[tokio::test]
+    async fn unexpected_shape_is_a_malformed_response() {
+        for (service, result) in call_every_service(&repository(r#"{"Content":{"Unexpected":[1,2,3]}}"#)).await {
+            assert!(matches!(result, Err(TBankError::MalformedResponse(_))), "{} did not fail cleanly", service);
+        }
+    }
+
+    #[tokio::test]
+    async fn server_error_is_service_unavailable() {
+        let addr = serve_fixed(StatusCode::INTERNAL_SERVER_ERROR, "");
+        let repo = TBankRepository::new(reqwest::Client::new(), format!("http://{}/", addr), format!("http://{}/", addr));
+        let transfer = repo.transfer(customer(), transfer_body()).await;
+        assert!(matches!(transfer, Err(TBankError::ServiceUnavailable)));
+        let chart = repo.get_balance_chart(&serde_json::from_value(json!({
+            "MonthEndBalance": [],
+            "CurrentMonth": {"Year_Month": "2023-10", "Balance": "1.00"}
+        })).unwrap()).await;
+        assert!(matches!(chart, Err(TBankError::ServiceUnavailable)));
+    }
+
+    #[tokio::test]
+    async fn unreachable_tbank_is_a_transport_error() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let repo = TBankRepository::new(reqwest::Client::new(), format!("http://{}/", addr), format!("http://{}/", addr));
+        let result = repo.transfer(customer(), transfer_body()).await;
+        assert!(matches!(result, Err(TBankError::Transport(_))));
+    }
+
+    fn mock_bank() -> MockTBank {
+        MockTBank::new()
+            .customer("alice", "123456")
+            .account("alice", "100", "500.00")
+            .account("alice", "101", "20.00")
+            .customer("bob", "654321")
+            .account("bob", "200", "0.00")
+            .beneficiary("alice", "200", "Bob")
+    }
+
+    fn against(mock: &MockTBank) -> TBankRepository {
+        let addr = mock.spawn();
+        TBankRepository::new(reqwest::Client::new(), format!("http://{}/", addr), format!("http://{}/", addr))
+    }
+
+    fn alice() -> CustomerRequest {
+        CustomerRequest {
+            service_name: "".to_owned(),
+            user_id: "alice".to_owned(),
+            pin: "123456".to_owned(),
+            otp: MOCK_OTP.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_needs_a_requested_otp_and_the_right_pin() {
+        let mock = mock_bank();
+        let repo = against(&mock);
+        assert!(matches!(repo.login_customer(alice()).await, Err(TBankError::ExpiredOtp)));
+
+        let wrong_pin = RequestOTP {
+            service_name: "requestOTP".to_owned(),
+            user_id: "alice".to_owned(),
+            pin: "000000".to_owned(),
+        };
+        let reply = repo.request_otp(wrong_pin).await.unwrap();
+        assert!(matches!(reply.content.service_response.service_response_header.ensure_success(), Err(TBankError::InvalidPin)));
+
+        let otp = RequestOTP {
+            service_name: "requestOTP".to_owned(),
+            user_id: "alice".to_owned(),
+            pin: "123456".to_owned(),
         };
-        res
+        repo.request_otp(otp).await.unwrap().content.service_response.service_response_header.ensure_success().unwrap();
+        let login = repo.login_customer(alice()).await.unwrap().content.service_response;
+        login.service_response_header.ensure_success().unwrap();
+        assert!(login.login_otp_response.customer_id.is_some());
     }
-    pub async fn get_beneficiaries(self, body: CustomerRequest, _beneficiary_type: BeneficiaryEnum) -> anyhow::Result<Vec<models::transaction::Beneficiary>> {
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let serde_body = serde_json::to_string(&body).unwrap();
-        let consumer_id = encode("RIB").to_string();
-        let encoded_header: String = encode(&serde_body).to_string();
-        //let beneficiary_type = beneficiary_type.to_string();
-        let content: String = r#"{"Content":{"accountGroup":"OTHER"}}"#.to_string();
-        let encoded_content = encode(&content).to_string();
-        let url = format!("{}?Header={}&Content={}&ConsumerID={}", self.tbank_url, encoded_header, encoded_content, consumer_id);
-        let req = self.client
-            .post(url)
-            .headers(headers)
-            .send()
-            .await;
-        let res = match req {
-            Ok(res) => {
-                let temp = res.json::<Value>().await.unwrap();
-                let val = temp["Content"]["ServiceResponse"]["BeneficiaryList"]["Beneficiary"].clone();
-                if val.is_array(){
-                    let beneficiary_vec = match serde_json::from_value::<Beneficiaries>(temp["Content"]["ServiceResponse"]["BeneficiaryList"].clone()) {
-                        Ok(res) => {
-                            Ok(res.beneficiary)
-                        }
-                        Err(e) => {
-                            warn!("{}", e);
-                            Ok(vec![])
-                        }
-                    };
-                    return beneficiary_vec
-                }else{
-                    let beneficiary_vec = match serde_json::from_value::<Beneficiary>(val) {
-                        Ok(res) => {
-                            Ok(vec![res])
-                        }
-                        Err(e) => {
-                            warn!("{}", e);
-                            Ok(vec![])
-                        }
-                    };
-                    return beneficiary_vec
-                }
-            }
-            Err(e) => {
-                warn!("{}", e);
-                Err(anyhow!("Something went wrong with the RequestOTP API."))
-            }
+
+    #[tokio::test]
+    async fn accounts_and_details_are_read_back() {
+        let mock = mock_bank();
+        let repo = against(&mock);
+        let accounts = repo.get_customer_accounts(alice()).await.unwrap();
+        let ids: Vec<_> = accounts.iter().map(|account| account.account_id.as_str()).collect();
+        assert_eq!(ids, ["100", "101"]);
+        assert_eq!(accounts[0].money().to_string(), "SGD 500.00");
+
+        let details = repo.get_customer_details(alice()).await.unwrap().content.service_response;
+        assert!(details.cdm_customer.certificate.certificate_no.is_some());
+    }
+
+    #[tokio::test]
+    async fn blank_or_missing_currencies_are_the_home_currency() {
+        let mock = mock_bank().account_in("alice", "102", "7.00", "").beneficiary("alice", "102", "Savings");
+        let repo = against(&mock);
+        let accounts = repo.get_customer_accounts(alice()).await.unwrap();
+        assert!(accounts.iter().all(|account| account.currency == HOME_CURRENCY));
+        let own = repo.get_beneficiaries(alice(), BeneficiaryEnum::OWN).await.unwrap();
+        assert_eq!(own[0].currency, HOME_CURRENCY);
+
+        let repo = repository(r#"{"Content":{"ServiceResponse":{
+            "ServiceRespHeader":{"ErrorText":"invocation successful","ErrorDetails":"Success","GlobalErrorID":"010000"},
+            "BeneficiaryList":{"Beneficiary":{"AccountID":"200","Description":"Bob","BeneficiaryID":"1"}}
+        }}}"#);
+        let payees = repo.get_beneficiaries(alice(), BeneficiaryEnum::OTHER).await.unwrap();
+        assert_eq!(payees[0].currency, HOME_CURRENCY);
+    }
+
+    #[tokio::test]
+    async fn single_element_lists_are_read() {
+        let mock = mock_bank();
+        let repo = against(&mock);
+        let bob = CustomerRequest { user_id: "bob".to_owned(), pin: "654321".to_owned(), ..alice() };
+        assert_eq!(repo.get_customer_accounts(bob.clone()).await.unwrap().len(), 1);
+        assert!(repo.get_beneficiaries(bob, BeneficiaryEnum::OTHER).await.unwrap().is_empty());
+        assert_eq!(repo.get_beneficiaries(alice(), BeneficiaryEnum::OTHER).await.unwrap()[0].description, "Bob");
+    }
+
+    #[tokio::test]
+    async fn beneficiaries_must_exist_and_are_listed_once_added() {
+        let mock = mock_bank().customer("carol", "111111").account("carol", "300", "0.00");
+        let repo = against(&mock);
+        let unknown = AddBeneficiaryBody { account_id: "999".to_owned(), description: "Nobody".to_owned() };
+        assert!(matches!(repo.add_beneficiary(alice(), unknown).await, Err(TBankError::UnknownAccount)));
+
+        let carol = AddBeneficiaryBody { account_id: "300".to_owned(), description: "Carol".to_owned() };
+        repo.add_beneficiary(alice(), carol).await.unwrap();
+        let listed: Vec<_> = repo.get_beneficiaries(alice(), BeneficiaryEnum::OTHER).await.unwrap()
+            .into_iter()
+            .map(|beneficiary| beneficiary.account_id)
+            .collect();
+        assert_eq!(listed, ["200", "300"]);
+    }
+
+    #[tokio::test]
+    async fn beneficiaries_can_be_renamed_and_deleted() {
+        let mock = mock_bank();
+        let repo = against(&mock);
+        let beneficiary_id = mock.beneficiaries_of("alice")[0].beneficiary_id.clone();
+
+        let rename = UpdateBeneficiaryBody { beneficiary_id: beneficiary_id.clone(), description: "Bobby".to_owned() };
+        repo.update_beneficiary(alice(), rename).await.unwrap();
+        assert_eq!(repo.get_beneficiaries(alice(), BeneficiaryEnum::OTHER).await.unwrap()[0].description, "Bobby");
+
+        repo.delete_beneficiary(alice(), DeleteBeneficiaryBody { beneficiary_id: beneficiary_id.clone() }).await.unwrap();
+        assert!(mock.beneficiaries_of("alice").is_empty());
+        let again = repo.delete_beneficiary(alice(), DeleteBeneficiaryBody { beneficiary_id }).await;
+        assert!(matches!(again, Err(TBankError::Rejected { .. })), "{:?}", again);
+    }
+
+    #[tokio::test]
+    async fn beneficiaries_are_listed_by_account_group() {
+        let mock = mock_bank().beneficiary("alice", "101", "Savings");
+        let repo = against(&mock);
+        let group = |beneficiaries: Vec<models::transaction::Beneficiary>| -> Vec<String> {
+            beneficiaries.into_iter().map(|beneficiary| beneficiary.account_id).collect()
         };
-        res
+        assert_eq!(group(repo.get_beneficiaries(alice(), BeneficiaryEnum::OWN).await.unwrap()), ["101"]);
+        assert_eq!(group(repo.get_beneficiaries(alice(), BeneficiaryEnum::OTHER).await.unwrap()), ["200"]);
     }
 
-    pub async fn add_beneficiary(self, body: CustomerRequest, content: AddBeneficiaryBody) -> anyhow::Result<String> {
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let serde_body = serde_json::to_string(&body).unwrap();
-        let serde_content = serde_json::to_string(&content).unwrap();
-        let encoded_header: String = encode(&serde_body).to_string();
-        //let beneficiary_type = beneficiary_type.to_string();
-        let encoded_content = encode(&serde_content).to_string();
-        let url = format!("{}?Header={}&Content={}", self.tbank_url, encoded_header, encoded_content);
-        info!("{}", url);
-        let req = self.client
-            .post(url)
-            .headers(headers)
-            .send()
-            .await;
-        let res = match req {
-            Ok(res) => {
-                let temp = res.json::<Value>().await.unwrap();
-                let msg = temp["Content"]["ServiceResponse"]["ServiceRespHeader"]["ErrorText"].clone().to_string();
-                Ok(msg)
-            }
-            Err(e) => {
-                warn!("{}", e);
-                Err(anyhow!("Something went wrong with the RequestOTP API."))
-            }
+    #[tokio::test]
+    async fn transfers_move_money_and_refuse_overdrafts() {
+        let mock = mock_bank();
+        let repo = against(&mock);
+        let transfer = TransferBody {
+            account_from: "100".to_owned(),
+            account_to: "200".to_owned(),
+            transaction_amount: sgd("125.50"),
+            transaction_reference_number: "1".to_owned(),
+            narrative: "Simple Transfer".to_owned(),
         };
-        res
+        repo.transfer(alice(), transfer.clone()).await.unwrap();
+        assert_eq!(mock.balance("100"), Some(Decimal::new(37450, 2)));
+        assert_eq!(mock.balance("200"), Some(Decimal::new(12550, 2)));
+
+        let too_much = TransferBody { transaction_amount: sgd("1000"), ..transfer };
+        assert!(matches!(repo.transfer(alice(), too_much).await, Err(TBankError::InsufficientFunds)));
+        assert_eq!(mock.transfers().len(), 1);
     }
 
-    pub async fn transfer(self, body: CustomerRequest, content: TransferBody) -> anyhow::Result<String> {
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let serde_body = serde_json::to_string(&body).unwrap();
-        let serde_content = serde_json::to_string(&content).unwrap();
-        let consumer_id = encode("RIB").to_string();
-        let encoded_header: String = encode(&serde_body).to_string();
-        //let beneficiary_type = beneficiary_type.to_string();
-        let encoded_content = encode(&serde_content).to_string();
-        let url = format!("{}?Header={}&Content={}&ConsumerID={}", self.tbank_url, encoded_header, encoded_content, consumer_id);
-        let req = self.client
-            .post(url)
-            .headers(headers)
-            .send()
-            .await;
-        let res = match req {
-            Ok(res) => {
-                let temp = res.json::<Value>().await.unwrap();
-                let status = temp["Content"]["ServiceResponse"]["ServiceRespHeader"]["ErrorText"].clone().to_string();
-                Ok(status)
-            }
-            Err(e) => {
-                warn!("{}", e);
-                Err(anyhow!("Something went wrong with the RequestOTP API."))
-            }
+    #[tokio::test]
+    async fn deposit_accounts_are_opened_for_the_certificate_holder() {
+        let mock = mock_bank();
+        let repo = against(&mock);
+        let details = repo.get_customer_details(alice()).await.unwrap().content.service_response;
+        let teller = CustomerRequest {
+            user_id: details.cdm_customer.certificate.certificate_no.unwrap(),
+            pin: "1".to_owned(),
+            otp: "".to_owned(),
+            ..alice()
         };
-        res
+        let account_id = repo.create_account(teller).await.unwrap();
+        let opened = mock.accounts_of("alice").into_iter().find(|account| account.account_id == account_id).unwrap();
+        assert_eq!(opened.currency, "SGD");
     }
 
-    pub async fn get_balance_chart(self, body: ChartBody) -> anyhow::Result<Bytes> {
-        let serde_body = serde_json::to_string(&body).unwrap();
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let req = self.client
-            .post(self.chart_url.clone())
-            .headers(headers)
-            .body(serde_body)
-            .send()
-            .await;
-        let res = match req {
-            Ok(res) => {
-                let temp = res.bytes().await.unwrap();
-                Ok(temp)
-            }
-            Err(e) => {
-                warn!("{}", e);
-                Err(anyhow!("Something went wrong with the Chart API."))
-            }
+    #[tokio::test]
+    async fn monthly_balance_trend_covers_the_requested_months() {
+        let mock = mock_bank();
+        let repo = against(&mock);
+        let trend = HistoricalMonthlyBalanceBody {
+            account_id: "100".to_owned(),
+            num_months: "3".to_owned(),
+        };
+        let chart = serde_json::to_value(repo.get_monthly_balance_trend(alice(), trend).await.unwrap()).unwrap();
+        assert_eq!(chart["MonthEndBalance"].as_array().unwrap().len(), 3);
+        assert_eq!(chart["MonthEndBalance"][0]["Year_Month"], "2023-07");
+        assert_eq!(chart["CurrentMonth"]["Balance"], "500.00");
+
+        let someone_elses = HistoricalMonthlyBalanceBody {
+            account_id: "200".to_owned(),
+            num_months: "3".to_owned(),
         };
-        res
+        assert!(matches!(repo.get_monthly_balance_trend(alice(), someone_elses).await, Err(TBankError::UnknownAccount)));
     }
 
-    pub async fn get_monthly_balance_trend(self, body: CustomerRequest, content: HistoricalMonthlyBalanceBody) -> anyhow::Result<ChartBody> {
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-        let serde_body = serde_json::to_string(&body).unwrap();
-        let serde_content = serde_json::to_string(&content).unwrap();
-        println!("{:?}", serde_content);
-        let consumer_id = encode("RIB").to_string();
-        let encoded_header: String = encode(&serde_body).to_string();
-        let encoded_content = encode(&serde_content).to_string();
-        let url = format!("{}?Header={}&Content={}&ConsumerID={}", self.tbank_url, encoded_header, encoded_content, consumer_id);
-        println!("{:?}", url);
-        let req = self.client
-            .post(url)
-            .headers(headers)
-            .send()
-            .await;
-        let res = match req {
-            Ok(res) => {
-                let temp = res.json::<Value>().await.unwrap();
-                let chart = serde_json::from_value::<ChartBody>(temp["Content"]["ServiceResponse"]["TrendData"].clone());
-                match chart {
-                    Ok(res) => Ok(res),
-                    Err(e) => {
-                        warn!("{}", e);
-                        Err(anyhow!("Something went wrong with the getMonthlyBalanceTrend API."))
-                    }
-                }
-            }
-            Err(e) => {
-                warn!("{}", e);
-                Err(anyhow!("Something went wrong with the getMonthlyBalanceTrend API."))
-            }
+    #[tokio::test]
+    async fn transaction_history_pages_newest_first() {
+        let mock = mock_bank();
+        let repo = against(&mock);
+        for amount in ["10", "20", "30"] {
+            let transfer = TransferBody {
+                account_from: "100".to_owned(),
+                account_to: "200".to_owned(),
+                transaction_amount: sgd(amount),
+                transaction_reference_number: "1".to_owned(),
+                narrative: format!("Paying {}", amount),
+            };
+            repo.transfer(alice(), transfer).await.unwrap();
+        }
+        let page = |page_no: &str| TransactionHistoryBody {
+            account_id: "100".to_owned(),
+            start_date: "2000-01-01 00:00:00".to_owned(),
+            end_date: "2999-12-31 23:59:59".to_owned(),
+            num_records_per_page: "2".to_owned(),
+            page_no: page_no.to_owned(),
         };
-        res
+
+        let first = repo.get_transaction_history(alice(), page("1")).await.unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].narrative.as_deref(), Some("Paying 30"));
+        assert_eq!(first[0].amount_for("100").unwrap().to_string(), "-30.00");
+        assert_eq!(first[0].amount_for("200").unwrap().to_string(), "30.00");
+        assert_eq!(first[0].balance_for("100"), Some("440.00"));
+        assert_eq!(first[0].balance_for("200"), Some("60.00"));
+        assert_eq!(first[0].date().len(), 10);
+
+        // The last page holds a single transaction, which TBank sends as a bare object
+        let last = repo.get_transaction_history(alice(), page("2")).await.unwrap();
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].narrative.as_deref(), Some("Paying 10"));
+        assert!(repo.get_transaction_history(alice(), page("3")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn tellers_deposit_and_withdraw_cash() {
+        let mock = mock_bank().teller("teller1", "4321");
+        let repo = against(&mock);
+        let request = |amount: &str| DepositRequest {
+            service_name: "".to_owned(),
+            user_id: "teller1".to_owned(),
+            pin: "4321".to_owned(),
+            otp: "".to_owned(),
+            account_id: "100".to_owned(),
+            amount: sgd(amount),
+            narrative: "Counter".to_owned(),
+        };
+
+        let deposit = repo.deposit_cash(request("50")).await.unwrap();
+        assert_eq!(deposit.balance_before.map(|balance| balance.to_string()).as_deref(), Some("500.00"));
+        assert_eq!(deposit.balance_after.map(|balance| balance.to_string()).as_deref(), Some("550.00"));
+        assert!(deposit.transaction_id.is_some());
+
+        let withdrawal = repo.withdraw_cash(request("150")).await.unwrap();
+        assert_eq!(withdrawal.balance_after.map(|balance| balance.to_string()).as_deref(), Some("400.00"));
+        assert_eq!(mock.balance("100"), Some(Decimal::new(40000, 2)));
+
+        assert!(matches!(repo.withdraw_cash(request("1000")).await, Err(TBankError::InsufficientFunds)));
+        let unknown = DepositRequest { account_id: "999".to_owned(), ..request("1") };
+        assert!(matches!(repo.deposit_cash(unknown).await, Err(TBankError::UnknownAccount)));
+        let wrong_pin = DepositRequest { pin: "0000".to_owned(), ..request("1") };
+        assert!(matches!(repo.deposit_cash(wrong_pin).await, Err(TBankError::InvalidPin)));
+        assert_eq!(mock.balance("100"), Some(Decimal::new(40000, 2)));
+    }
+
+    #[tokio::test]
+    async fn onboarding_returns_the_new_customer() {
+        let mock = mock_bank();
+        let repo = against(&mock);
+        let mut registration = Registration::default();
+        registration.fill(RegistrationField::Name, "Lim Carol").unwrap();
+        registration.fill(RegistrationField::IcNumber, "S7654321Z").unwrap();
+        registration.fill(RegistrationField::UserId, "carol_lim").unwrap();
+
+        let reply = repo.onboard_customer(registration.clone().into_onboard_data()).await.unwrap();
+        let account_id = reply.account_id.unwrap();
+        assert!(reply.customer_id.is_some());
+        assert_eq!(mock.accounts_of("carol_lim")[0].account_id, account_id);
+
+        let again = repo.onboard_customer(registration.into_onboard_data()).await;
+        assert!(matches!(again, Err(TBankError::Rejected { .. })), "{:?}", again);
     }
 }
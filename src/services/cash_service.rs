@@ -0,0 +1,51 @@
+use tracing::info;
+use crate::enums::cash::CashMovement;
+use crate::errors::tbank_error::TBankError;
+use crate::models::CustomerRequest;
+use crate::models::transaction::{CashOrder, DepositResponse};
+use crate::repositories::tbank_repository::TBankRepository;
+
+/// Moves cash in and out of customer accounts with the teller login from the config.
+#[derive(Clone)]
+pub struct CashService {
+    tbank_repository: TBankRepository,
+    teller: CustomerRequest,
+}
+
+impl CashService {
+    pub fn new(tbank_repository: TBankRepository, teller_user_id: &str, teller_pin: &str) -> Self {
+        Self {
+            tbank_repository,
+            teller: CustomerRequest {
+                service_name: "".to_owned(),
+                user_id: teller_user_id.to_owned(),
+                pin: teller_pin.to_owned(),
+                otp: "".to_owned(),
+            },
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        !self.teller.user_id.is_empty() && !self.teller.pin.is_empty()
+    }
+
+    pub async fn execute(&self, order: CashOrder, requested_by: &str) -> Result<DepositResponse, TBankError> {
+        let movement = order.movement;
+        let account_id = order.account_id.clone();
+        let amount = order.amount;
+        let request = order.into_request(&self.teller);
+        let response = match movement {
+            CashMovement::Deposit => self.tbank_repository.deposit_cash(request).await?,
+            CashMovement::Withdrawal => self.tbank_repository.withdraw_cash(request).await?,
+        };
+        info!(
+            movement = movement.noun(),
+            account_id = %account_id,
+            amount = %amount,
+            requested_by,
+            transaction_id = response.transaction_id.as_deref().unwrap_or_default(),
+            "Cash {} completed", movement.noun()
+        );
+        Ok(response)
+    }
+}
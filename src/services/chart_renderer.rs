@@ -0,0 +1,17 @@
+use async_trait::async_trait;
+use crate::models::chart::{AccountTrend, ChartBody};
+
+/// Turns balance trends into a PNG the bot can send as a photo.
+#[async_trait]
+pub trait ChartRenderer: Send + Sync {
+    async fn render(&self, chart: &ChartBody) -> anyhow::Result<Vec<u8>>;
+
+    /// Whether `render_comparison` can overlay several accounts on one chart.
+    fn supports_comparison(&self) -> bool {
+        false
+    }
+
+    async fn render_comparison(&self, _trends: &[AccountTrend]) -> anyhow::Result<Vec<u8>> {
+        anyhow::bail!("this chart renderer draws one account at a time")
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crate::errors::credential_error::CredentialError;
+
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// AES-256-GCM keys used to seal credentials at rest.
+/// Sealed values are prefixed with the ID of the key that sealed them, so older keys keep working after a rotation.
+#[derive(Clone)]
+pub struct CredentialKeyring {
+    active_id: String,
+    keys: Arc<HashMap<String, Aes256Gcm>>,
+}
+
+impl CredentialKeyring {
+    /// Parses `key_id:base64_key` pairs separated by commas. The first key is the one new values are sealed with.
+    pub fn parse(spec: &str) -> Result<Self, CredentialError> {
+        let mut active_id = None;
+        let mut keys = HashMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (id, encoded) = entry.split_once(':')
+                .ok_or_else(|| CredentialError::InvalidKey("<unnamed>".to_owned()))?;
+            let key = STANDARD.decode(encoded.trim())
+                .ok()
+                .filter(|key| key.len() == KEY_LEN)
+                .ok_or_else(|| CredentialError::InvalidKey(id.to_owned()))?;
+            let cipher = Aes256Gcm::new_from_slice(&key)
+                .map_err(|_| CredentialError::InvalidKey(id.to_owned()))?;
+            if keys.insert(id.to_owned(), cipher).is_some() {
+                return Err(CredentialError::InvalidKey(id.to_owned()));
+            }
+            active_id.get_or_insert_with(|| id.to_owned());
+        }
+        Ok(Self {
+            active_id: active_id.ok_or(CredentialError::NoKeys)?,
+            keys: Arc::new(keys),
+        })
+    }
+
+    /// Encrypts `plaintext` with the active key. `context` is authenticated but not stored,
+    /// so a sealed value only opens for the same context.
+    pub fn seal(&self, plaintext: &[u8], context: &[u8]) -> Result<String, CredentialError> {
+        let cipher = &self.keys[&self.active_id];
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.encrypt(&nonce, Payload { msg: plaintext, aad: context })
+            .map_err(|_| CredentialError::Encryption)?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(format!("{}:{}", self.active_id, STANDARD.encode(sealed)))
+    }
+
+    pub fn open(&self, sealed: &str, context: &[u8]) -> Result<Vec<u8>, CredentialError> {
+        let (id, encoded) = sealed.split_once(':').ok_or(CredentialError::Malformed)?;
+        let cipher = self.keys.get(id).ok_or_else(|| CredentialError::UnknownKey(id.to_owned()))?;
+        let sealed = STANDARD.decode(encoded).map_err(|_| CredentialError::Malformed)?;
+        if sealed.len() < NONCE_LEN {
+            return Err(CredentialError::Malformed);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: context })
+            .map_err(|_| CredentialError::Decryption)
+    }
+
+    /// Whether `sealed` was produced by a key other than the active one.
+    pub fn needs_rotation(&self, sealed: &str) -> bool {
+        sealed.split_once(':').map(|(id, _)| id != self.active_id).unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> String {
+        STANDARD.encode([byte; KEY_LEN])
+    }
+
+    #[test]
+    fn sealed_values_open_with_the_same_context() {
+        let keyring = CredentialKeyring::parse(&format!("k1:{}", key(1))).unwrap();
+        let sealed = keyring.seal(b"secret", b"token").unwrap();
+        assert!(sealed.starts_with("k1:"));
+        assert_eq!(keyring.open(&sealed, b"token").unwrap(), b"secret");
+        assert_eq!(keyring.open(&sealed, b"other"), Err(CredentialError::Decryption));
+    }
+
+    #[test]
+    fn older_keys_still_open_after_rotation() {
+        let old = CredentialKeyring::parse(&format!("k1:{}", key(1))).unwrap();
+        let sealed = old.seal(b"secret", b"token").unwrap();
+
+        let rotated = CredentialKeyring::parse(&format!("k2:{},k1:{}", key(2), key(1))).unwrap();
+        assert!(rotated.needs_rotation(&sealed));
+        assert_eq!(rotated.open(&sealed, b"token").unwrap(), b"secret");
+        assert!(!rotated.needs_rotation(&rotated.seal(b"secret", b"token").unwrap()));
+
+        let retired = CredentialKeyring::parse(&format!("k2:{}", key(2))).unwrap();
+        assert_eq!(retired.open(&sealed, b"token"), Err(CredentialError::UnknownKey("k1".to_owned())));
+    }
+
+    #[test]
+    fn bad_key_specs_are_rejected() {
+        assert_eq!(CredentialKeyring::parse("").err(), Some(CredentialError::NoKeys));
+        assert_eq!(CredentialKeyring::parse("k1:c2hvcnQ=").err(), Some(CredentialError::InvalidKey("k1".to_owned())));
+        assert_eq!(
+            CredentialKeyring::parse(&format!("k1:{},k1:{}", key(1), key(2))).err(),
+            Some(CredentialError::InvalidKey("k1".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeSet;
+use std::io::Cursor;
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use image::{ImageOutputFormat, RgbImage};
+use plotters::prelude::*;
+use rust_decimal::prelude::ToPrimitive;
+use tracing::warn;
+use crate::models::chart::{AccountTrend, ChartBody, YearMonth};
+use crate::services::chart_renderer::ChartRenderer;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 480;
+const FONT_FAMILY: &str = "sans-serif";
+const MAX_X_LABELS: usize = 12;
+// Accounts past the last colour start again from the first
+const LINE_COLORS: [RGBColor; 6] = [BLUE, RED, GREEN, MAGENTA, CYAN, BLACK];
+
+/// Draws balance charts in process with plotters.
+#[derive(Clone)]
+pub struct LocalChartRenderer {
+    labelled: bool,
+}
+
+impl LocalChartRenderer {
+    /// Loads the label font once. Without it charts are still drawn, just without any text.
+    pub fn new(font_path: &str) -> Self {
+        let labelled = match std::fs::read(font_path) {
+            // Fonts are registered for the life of the process, so leaking the bytes is fine
+            Ok(bytes) => plotters::style::register_font(FONT_FAMILY, FontStyle::Normal, Box::leak(bytes.into_boxed_slice())).is_ok(),
+            Err(e) => {
+                warn!("Could not read chart font {}: {}", font_path, e);
+                false
+            }
+        };
+        if !labelled {
+            warn!("Balance charts will be drawn without labels");
+        }
+        Self {
+            labelled,
+        }
+    }
+
+    /// Month-end balances as a line, with the current month as the last point.
+    pub fn draw(&self, chart: &ChartBody) -> anyhow::Result<Vec<u8>> {
+        self.plot("Month-end balance", &[("", chart)])
+    }
+
+    /// One line per account, over every month any of them covers.
+    pub fn draw_comparison(&self, trends: &[AccountTrend]) -> anyhow::Result<Vec<u8>> {
+        let series: Vec<(&str, &ChartBody)> = trends.iter().map(|one| (one.account_id.as_str(), &one.trend)).collect();
+        self.plot("Month-end balance by account", &series)
+    }
+
+    fn plot(&self, caption: &str, series: &[(&str, &ChartBody)]) -> anyhow::Result<Vec<u8>> {
+        if series.is_empty() {
+            bail!("there are no balances to chart");
+        }
+        let months: Vec<YearMonth> = series.iter()
+            .flat_map(|(_, chart)| chart.records().map(|record| record.year_month))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let lines: Vec<Vec<(f64, f64)>> = series.iter()
+            .map(|(_, chart)| {
+                chart.records()
+                    .filter_map(|record| {
+                        let index = months.binary_search(&record.year_month).ok()?;
+                        Some((index as f64, record.balance.to_f64().unwrap_or_default()))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let balances = || lines.iter().flatten().map(|(_, balance)| *balance);
+        let lowest = balances().fold(f64::INFINITY, f64::min);
+        let highest = balances().fold(f64::NEG_INFINITY, f64::max);
+        // Keep a flat line off the edges of the plot
+        let padding = ((highest - lowest) * 0.1).max(1.0);
+        let last = (months.len() - 1) as f64;
+
+        let mut pixels = vec![0u8; (WIDTH * HEIGHT * 3) as usize];
+        {
+            let root = BitMapBackend::with_buffer(&mut pixels, (WIDTH, HEIGHT)).into_drawing_area();
+            root.fill(&WHITE)?;
+            let mut builder = ChartBuilder::on(&root);
+            builder.margin(20);
+            if self.labelled {
+                builder
+                    .caption(caption, (FONT_FAMILY, 24))
+                    .x_label_area_size(40)
+                    .y_label_area_size(80);
+            }
+            let mut plot = builder.build_cartesian_2d(-0.5..last + 0.5, (lowest - padding)..(highest + padding))?;
+
+            let x_label = |x: &f64| {
+                let index = x.round();
+                match months.get(index as usize) {
+                    Some(year_month) if (x - index).abs() < 0.01 && index >= 0.0 => year_month.to_string(),
+                    _ => "".to_owned(),
+                }
+            };
+            let y_label = |y: &f64| format!("{:.2}", y);
+            let mut mesh = plot.configure_mesh();
+            mesh.disable_x_mesh().x_label_formatter(&x_label).y_label_formatter(&y_label);
+            if self.labelled {
+                // Two years of months do not fit side by side, so only some get a label
+                mesh.x_labels(months.len().min(MAX_X_LABELS) + 1).y_labels(6);
+            } else {
+                mesh.x_labels(0).y_labels(0);
+            }
+            mesh.draw()?;
+
+            for (index, ((account_id, _), line)) in series.iter().zip(&lines).enumerate() {
+                let color = LINE_COLORS[index % LINE_COLORS.len()];
+                // The current month is still moving, so it stands out from the closed months
+                let current = if series.len() == 1 { RED } else { color };
+                plot.draw_series(LineSeries::new(line.iter().copied(), color.stroke_width(3)))?
+                    .label(*account_id)
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(3)));
+                plot.draw_series(line.iter().map(|point| Circle::new(*point, 5, color.filled())))?;
+                plot.draw_series(line.last().map(|point| Circle::new(*point, 7, current.filled())))?;
+            }
+            if self.labelled && series.len() > 1 {
+                plot.configure_series_labels()
+                    .position(SeriesLabelPosition::UpperLeft)
+                    .label_font((FONT_FAMILY, 14))
+                    .background_style(WHITE.mix(0.8))
+                    .border_style(BLACK)
+                    .draw()?;
+            }
+            root.present()?;
+        }
+
+        let image = RgbImage::from_raw(WIDTH, HEIGHT, pixels).context("chart buffer has the wrong size")?;
+        let mut png = Cursor::new(vec![]);
+        image.write_to(&mut png, ImageOutputFormat::Png)?;
+        Ok(png.into_inner())
+    }
+}
+
+#[async_trait]
+impl ChartRenderer for LocalChartRenderer {
+    async fn render(&self, chart: &ChartBody) -> anyhow::Result<Vec<u8>> {
+        let renderer = self.clone();
+        let chart = chart.clone();
+        tokio::task::spawn_blocking(move || renderer.draw(&chart)).await?
+    }
+
+    fn supports_comparison(&self) -> bool {
+        true
+    }
+
+    async fn render_comparison(&self, trends: &[AccountTrend]) -> anyhow::Result<Vec<u8>> {
+        let renderer = self.clone();
+        let trends = trends.to_vec();
+        tokio::task::spawn_blocking(move || renderer.draw_comparison(&trends)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    fn chart(balances: &[&str]) -> ChartBody {
+        let (current, closed) = balances.split_last().unwrap();
+        serde_json::from_value(json!({
+            "MonthEndBalance": closed.iter().enumerate()
+                .map(|(index, balance)| json!({"Year_Month": format!("2023-{:02}", 12 - closed.len() + index), "Balance": balance}))
+                .collect::<Vec<_>>(),
+            "CurrentMonth": {"Year_Month": "2023-12", "Balance": current},
+        })).unwrap()
+    }
+
+    fn size_of(png: &[u8]) -> (u32, u32) {
+        let image = image::load_from_memory(png).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn trends_are_drawn_as_png() {
+        let renderer = LocalChartRenderer::new("/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf");
+        let png = renderer.draw(&chart(&["100.00", "250.50", "-20.00", "400"])).unwrap();
+        assert!(png.starts_with(PNG_SIGNATURE));
+        assert_eq!(size_of(&png), (WIDTH, HEIGHT));
+    }
+
+    #[test]
+    fn comparisons_overlay_accounts_over_every_month() {
+        let renderer = LocalChartRenderer::new("/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf");
+        let trends = [
+            AccountTrend { account_id: "100".to_owned(), trend: chart(&["100.00", "250.50", "-20.00", "400"]) },
+            // Opened later, so it only covers the last two months
+            AccountTrend { account_id: "200".to_owned(), trend: chart(&["80", "120"]) },
+        ];
+        let png = renderer.draw_comparison(&trends).unwrap();
+        assert_eq!(size_of(&png), (WIDTH, HEIGHT));
+        assert!(renderer.draw_comparison(&[]).is_err());
+    }
+
+    #[test]
+    fn charts_are_drawn_without_a_font() {
+        let renderer = LocalChartRenderer::new("/nonexistent/font.ttf");
+        assert!(!renderer.labelled);
+        let trends = [AccountTrend { account_id: "100".to_owned(), trend: chart(&["1", "2"]) }];
+        assert_eq!(size_of(&renderer.draw_comparison(&trends).unwrap()), (WIDTH, HEIGHT));
+        // A single flat point still needs a range to plot in
+        let png = renderer.draw(&chart(&["0.00"])).unwrap();
+        assert_eq!(size_of(&png), (WIDTH, HEIGHT));
+    }
+}
@@ -1,2 +1,9 @@
 pub mod register_service;
-pub mod telegram_service;
\ No newline at end of file
+pub mod telegram_service;
+pub mod credential_keyring;
+pub mod session_service;
+pub mod statement_service;
+pub mod cash_service;
+pub mod chart_renderer;
+pub mod local_chart_renderer;
+pub mod remote_chart_renderer;
\ No newline at end of file
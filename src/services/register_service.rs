@@ -1,19 +1,26 @@
-use std::sync::Arc;
-use crate::config::AppConfig;
+use teloxide::types::Update;
+use tokio::sync::mpsc::UnboundedSender;
+use crate::context::AppContext;
 use crate::services::telegram_service::TelegramService;
 
 #[derive(Clone)]
 pub struct ServiceRegister {
-    pub telegram_service: Option<TelegramService>
+    pub context: AppContext,
+    pub telegram_service: Option<TelegramService>,
+    // Set in webhook mode; feeds updates posted by Telegram to the dispatcher
+    pub telegram_updates: Option<UnboundedSender<Update>>,
 }
 
 impl ServiceRegister {
     pub async fn new(
-        _app_config: Arc<AppConfig>,
+        context: AppContext,
         telegram_service: TelegramService,
+        telegram_updates: Option<UnboundedSender<Update>>,
     ) -> Self {
         Self {
-            telegram_service: Some(telegram_service)
+            context,
+            telegram_service: Some(telegram_service),
+            telegram_updates,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,26 @@
+use async_trait::async_trait;
+use crate::models::chart::ChartBody;
+use crate::repositories::tbank_repository::TBankRepository;
+use crate::services::chart_renderer::ChartRenderer;
+
+/// Forwards the trend to the external chart generator and returns whatever it draws.
+/// The generator takes a single account, so comparisons are not offered.
+#[derive(Clone)]
+pub struct RemoteChartRenderer {
+    tbank_repository: TBankRepository,
+}
+
+impl RemoteChartRenderer {
+    pub fn new(tbank_repository: TBankRepository) -> Self {
+        Self {
+            tbank_repository,
+        }
+    }
+}
+
+#[async_trait]
+impl ChartRenderer for RemoteChartRenderer {
+    async fn render(&self, chart: &ChartBody) -> anyhow::Result<Vec<u8>> {
+        Ok(self.tbank_repository.get_balance_chart(chart).await?.to_vec())
+    }
+}
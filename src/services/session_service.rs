@@ -0,0 +1,324 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::Rng;
+use teloxide::types::ChatId;
+use tracing::warn;
+use crate::errors::session_error::SessionError;
+use crate::models::CustomerRequest;
+use crate::models::session::Session;
+use crate::repositories::state_store::StateStore;
+use crate::services::credential_keyring::CredentialKeyring;
+
+// How long an expired session is remembered, so the chat can be told why it was logged out
+const EXPIRED_NOTICE_SECS: u64 = 24 * 60 * 60;
+// How long a PIN is held while the customer keys in their OTP
+const PENDING_PIN_SECS: u64 = 120;
+
+/// How long a login lasts. Whichever lifetime runs out first ends the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionPolicy {
+    pub absolute_ttl_secs: u64,
+    pub idle_ttl_secs: u64,
+}
+
+impl SessionPolicy {
+    /// Seconds `session` has left at `now`, or `None` once it has expired.
+    pub fn remaining(&self, session: &Session, now: u64) -> Option<u64> {
+        let absolute_end = session.created_at.saturating_add(self.absolute_ttl_secs);
+        let idle_end = session.last_seen.saturating_add(self.idle_ttl_secs);
+        let end = absolute_end.min(idle_end);
+        (end > now).then(|| end - now)
+    }
+
+    /// How long the session record is kept; past its absolute lifetime only to report the expiry.
+    fn record_ttl(&self, session: &Session, now: u64) -> u64 {
+        session.created_at
+            .saturating_add(self.absolute_ttl_secs)
+            .saturating_sub(now)
+            .saturating_add(EXPIRED_NOTICE_SECS)
+    }
+}
+
+/// Keeps logged in chats. A chat only holds an opaque session token; the TBank credentials
+/// behind it are sealed with the keyring, so the state store never sees the PIN in clear.
+#[derive(Clone)]
+pub struct SessionService {
+    store: Arc<dyn StateStore>,
+    keyring: CredentialKeyring,
+    policy: SessionPolicy,
+}
+
+impl SessionService {
+    pub fn new(store: Arc<dyn StateStore>, keyring: CredentialKeyring, policy: SessionPolicy) -> Self {
+        Self {
+            store,
+            keyring,
+            policy,
+        }
+    }
+
+    pub async fn create(&self, chat_id: ChatId, credentials: &CustomerRequest) -> anyhow::Result<Session> {
+        // Drop whatever the chat was logged in with before
+        let _ = self.destroy(chat_id).await;
+
+        let now = Self::now();
+        let session = Session {
+            token: Self::new_token(),
+            user_id: credentials.user_id.clone(),
+            chat_id: chat_id.0,
+            created_at: now,
+            last_seen: now,
+        };
+        let remaining = self.policy.remaining(&session, now).ok_or(SessionError::Expired)?;
+        let sealed = Self::seal(&self.keyring, &session, credentials)?;
+        self.store.set_with_ttl(&Self::credentials_key(&session.token), sealed, remaining).await?;
+        self.save(&session, now).await?;
+        Ok(session)
+    }
+
+    /// The chat's session, with its idle lifetime pushed back since this counts as activity.
+    pub async fn session(&self, chat_id: ChatId) -> anyhow::Result<Session> {
+        let mut session = self.stored(chat_id).await?.ok_or(SessionError::NotLoggedIn)?;
+        let now = Self::now();
+        if self.policy.remaining(&session, now).is_none() {
+            let _ = self.destroy(chat_id).await;
+            return Err(SessionError::Expired.into());
+        }
+        session.last_seen = now;
+        // Sliding the idle window never extends past the absolute lifetime
+        let remaining = self.policy.remaining(&session, now).ok_or(SessionError::Expired)?;
+        self.save(&session, now).await?;
+        self.store.expire(&Self::credentials_key(&session.token), remaining).await?;
+        Ok(session)
+    }
+
+    /// Opens the credentials of the chat's session, resealing them if they were sealed with a retired key.
+    pub async fn credentials(&self, chat_id: ChatId) -> anyhow::Result<CustomerRequest> {
+        let session = self.session(chat_id).await?;
+        let credentials_key = Self::credentials_key(&session.token);
+        let sealed = self.store.get(&credentials_key).await?.ok_or(SessionError::Expired)?;
+        let credentials = Self::open(&self.keyring, &session, &sealed)?;
+
+        if self.keyring.needs_rotation(&sealed) {
+            let resealed = Self::seal(&self.keyring, &session, &credentials)?;
+            let remaining = self.policy.remaining(&session, Self::now()).unwrap_or_default();
+            if let Err(e) = self.store.set_with_ttl(&credentials_key, resealed, remaining).await {
+                warn!("Could not reseal credentials for chat {} : {}", chat_id, e);
+            }
+        }
+        Ok(credentials)
+    }
+
+    /// Holds the PIN of a login that is waiting for its OTP, sealed to the chat, so the
+    /// conversation state never carries it.
+    pub async fn hold_pin(&self, chat_id: ChatId, pin: &str) -> anyhow::Result<()> {
+        let sealed = self.keyring.seal(pin.as_bytes(), Self::pending_pin_key(chat_id).as_bytes())?;
+        self.store.set_with_ttl(&Self::pending_pin_key(chat_id), sealed, PENDING_PIN_SECS).await
+    }
+
+    /// The PIN held by `hold_pin`. It can only be taken once.
+    pub async fn take_pin(&self, chat_id: ChatId) -> anyhow::Result<String> {
+        let key = Self::pending_pin_key(chat_id);
+        let sealed = self.store.get(&key).await?.ok_or(SessionError::Expired)?;
+        self.store.delete(&key).await?;
+        let pin = self.keyring.open(&sealed, key.as_bytes())?;
+        Ok(String::from_utf8(pin)?)
+    }
+
+    /// Ends the chat's session if it has run out. Returns whether it did, so the chat can be told.
+    pub async fn expire_if_stale(&self, chat_id: ChatId) -> anyhow::Result<bool> {
+        match self.stored(chat_id).await? {
+            Some(session) if self.policy.remaining(&session, Self::now()).is_none() => {
+                self.destroy(chat_id).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Sessions that are still live, with the seconds each has left.
+    pub async fn active_sessions(&self) -> anyhow::Result<Vec<(Session, u64)>> {
+        let now = Self::now();
+        let mut sessions = vec![];
+        for key in self.store.keys(&Self::session_key("*")).await? {
+            let Some(session) = self.store.get(&key).await? else {
+                continue;
+            };
+            let session: Session = serde_json::from_str(&session)?;
+            if let Some(remaining) = self.policy.remaining(&session, now) {
+                sessions.push((session, remaining));
+            }
+        }
+        sessions.sort_by_key(|(session, _)| session.created_at);
+        Ok(sessions)
+    }
+
+    pub async fn destroy(&self, chat_id: ChatId) -> anyhow::Result<()> {
+        let session = self.stored(chat_id).await?.ok_or(SessionError::NotLoggedIn)?;
+        self.store.delete(&Self::credentials_key(&session.token)).await?;
+        self.store.delete(&Self::session_key(chat_id)).await
+    }
+
+    async fn stored(&self, chat_id: ChatId) -> anyhow::Result<Option<Session>> {
+        match self.store.get(&Self::session_key(chat_id)).await? {
+            Some(session) => Ok(Some(serde_json::from_str(&session)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, session: &Session, now: u64) -> anyhow::Result<()> {
+        let ttl = self.policy.record_ttl(session, now);
+        self.store.set_with_ttl(&Self::session_key(session.chat_id), serde_json::to_string(session)?, ttl).await
+    }
+
+    fn seal(keyring: &CredentialKeyring, session: &Session, credentials: &CustomerRequest) -> anyhow::Result<String> {
+        Ok(keyring.seal(&serde_json::to_vec(credentials)?, session.token.as_bytes())?)
+    }
+
+    fn open(keyring: &CredentialKeyring, session: &Session, sealed: &str) -> anyhow::Result<CustomerRequest> {
+        let credentials = keyring.open(sealed, session.token.as_bytes())?;
+        Ok(serde_json::from_slice(&credentials)?)
+    }
+
+    fn new_token() -> String {
+        URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 32]>())
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default()
+    }
+
+    fn session_key(chat_id: impl std::fmt::Display) -> String {
+        format!("{}:{}", chat_id, "LoginCred")
+    }
+
+    fn pending_pin_key(chat_id: ChatId) -> String {
+        format!("{}:{}", chat_id, "PendingPin")
+    }
+
+    fn credentials_key(token: &str) -> String {
+        format!("{}:{}", "session", token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+    use crate::enums::callback::CallbackAction;
+    use crate::services::telegram_service::tests::{bank, Script, CHAT};
+    use crate::testing::mock_tbank::MOCK_OTP;
+
+    const PIN: &str = "482913";
+
+    fn keyring() -> CredentialKeyring {
+        CredentialKeyring::parse(&format!("k1:{}", STANDARD.encode([7u8; 32]))).unwrap()
+    }
+
+    fn credentials() -> CustomerRequest {
+        CustomerRequest {
+            service_name: "loginCustomer".to_owned(),
+            user_id: "alice".to_owned(),
+            pin: PIN.to_owned(),
+            otp: "999999".to_owned(),
+        }
+    }
+
+    fn session() -> Session {
+        Session {
+            token: SessionService::new_token(),
+            user_id: "alice".to_owned(),
+            chat_id: 42,
+            created_at: 1_000,
+            last_seen: 1_000,
+        }
+    }
+
+    const POLICY: SessionPolicy = SessionPolicy {
+        absolute_ttl_secs: 3_600,
+        idle_ttl_secs: 600,
+    };
+
+    /// Fails if any key or value in `store` gives `pin` away.
+    async fn assert_store_hides(store: &Arc<dyn StateStore>, pin: &str) {
+        for key in store.keys("*").await.unwrap() {
+            let value = store.get(&key).await.unwrap().unwrap_or_default();
+            for stored in [&key, &value] {
+                assert!(!stored.contains(pin), "{} = {}", key, value);
+                assert!(!stored.contains(&STANDARD.encode(pin)), "{} = {}", key, value);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn stored_values_contain_no_recoverable_pin() {
+        let session = session();
+        let sealed = SessionService::seal(&keyring(), &session, &credentials()).unwrap();
+        let session_json = serde_json::to_string(&session).unwrap();
+
+        for stored in [&sealed, &session_json] {
+            assert!(!stored.contains(PIN));
+            assert!(!stored.contains(&STANDARD.encode(PIN)));
+        }
+        let (_, payload) = sealed.split_once(':').unwrap();
+        let raw = String::from_utf8_lossy(&STANDARD.decode(payload).unwrap()).into_owned();
+        assert!(!raw.contains(PIN));
+
+        // A real login, checked while it waits for the OTP and once it is done
+        let script = Script::new(&bank()).await;
+        script.send("/start").await;
+        script.tap(CallbackAction::Login).await;
+        script.send("alice").await;
+        script.send("123456").await;
+        assert_store_hides(&script.ctx.state_store, "123456").await;
+
+        script.send(MOCK_OTP).await;
+        assert_eq!(script.ctx.session_service.credentials(CHAT).await.unwrap().pin, "123456");
+        assert_store_hides(&script.ctx.state_store, "123456").await;
+    }
+
+    #[test]
+    fn credentials_only_open_for_their_own_session() {
+        let session = session();
+        let sealed = SessionService::seal(&keyring(), &session, &credentials()).unwrap();
+        assert_eq!(SessionService::open(&keyring(), &session, &sealed).unwrap(), credentials());
+
+        let other = self::session();
+        assert!(SessionService::open(&keyring(), &other, &sealed).is_err());
+
+        let wrong_key = CredentialKeyring::parse(&format!("k1:{}", STANDARD.encode([8u8; 32]))).unwrap();
+        assert!(SessionService::open(&wrong_key, &session, &sealed).is_err());
+    }
+
+    #[test]
+    fn tokens_are_unique() {
+        assert_ne!(SessionService::new_token(), SessionService::new_token());
+    }
+
+    #[test]
+    fn idle_sessions_expire() {
+        let session = session();
+        assert_eq!(POLICY.remaining(&session, 1_000), Some(600));
+        assert_eq!(POLICY.remaining(&session, 1_599), Some(1));
+        assert_eq!(POLICY.remaining(&session, 1_600), None);
+    }
+
+    #[test]
+    fn activity_slides_the_idle_window_up_to_the_absolute_lifetime() {
+        let mut session = session();
+        session.last_seen = 3_500;
+        assert_eq!(POLICY.remaining(&session, 3_500), Some(600));
+
+        session.last_seen = 4_400;
+        assert_eq!(POLICY.remaining(&session, 4_400), Some(200));
+        assert_eq!(POLICY.remaining(&session, 4_600), None);
+    }
+
+    #[test]
+    fn records_outlive_the_session_to_report_its_expiry() {
+        let session = session();
+        assert_eq!(POLICY.record_ttl(&session, 1_000), 3_600 + EXPIRED_NOTICE_SECS);
+        assert_eq!(POLICY.record_ttl(&session, 9_000), EXPIRED_NOTICE_SECS);
+    }
+}
@@ -0,0 +1,241 @@
+use tracing::warn;
+use crate::errors::tbank_error::TBankError;
+use crate::models::CustomerRequest;
+use crate::models::statement::StatementPeriod;
+use crate::models::transaction::{TransactionDetail, TransactionHistoryBody};
+use crate::repositories::tbank_repository::TBankRepository;
+
+// History is read in pages this big, and a statement stops after this many of them
+const STATEMENT_PAGE_SIZE: usize = 100;
+const MAX_STATEMENT_PAGES: usize = 50;
+
+// A4 in points, typeset in 9pt Courier so the columns line up
+const PAGE_WIDTH: u32 = 595;
+const PAGE_HEIGHT: u32 = 842;
+const MARGIN: u32 = 40;
+const FONT_SIZE: u32 = 9;
+const LEADING: u32 = 12;
+const LINES_PER_PAGE: usize = ((PAGE_HEIGHT - 2 * MARGIN) / LEADING) as usize - 2;
+
+/// Builds account statements as CSV and PDF documents.
+#[derive(Clone)]
+pub struct StatementService {
+    tbank_repository: TBankRepository,
+}
+
+impl StatementService {
+    pub fn new(tbank_repository: TBankRepository) -> Self {
+        Self {
+            tbank_repository,
+        }
+    }
+
+    /// Every transaction in the period, oldest first.
+    pub async fn transactions(&self, credentials: CustomerRequest, period: &StatementPeriod) -> Result<Vec<TransactionDetail>, TBankError> {
+        let (start_date, end_date) = period.tbank_range();
+        let mut transactions = vec![];
+        for page in 1..=MAX_STATEMENT_PAGES {
+            let content = TransactionHistoryBody {
+                account_id: period.account_id.clone(),
+                start_date: start_date.clone(),
+                end_date: end_date.clone(),
+                num_records_per_page: STATEMENT_PAGE_SIZE.to_string(),
+                page_no: page.to_string(),
+            };
+            let batch = self.tbank_repository.get_transaction_history(credentials.clone(), content).await?;
+            let last_page = batch.len() < STATEMENT_PAGE_SIZE;
+            transactions.extend(batch);
+            if last_page {
+                break;
+            }
+        }
+        transactions.reverse();
+        Ok(transactions)
+    }
+
+    pub fn to_csv(period: &StatementPeriod, transactions: &[TransactionDetail]) -> Vec<u8> {
+        let mut csv = String::from("Date,Transaction ID,Description,Amount,Balance,Currency\r\n");
+        for one in transactions {
+            let fields = [
+                one.date().to_owned(),
+                one.transaction_id.clone(),
+                Self::description(one),
+                Self::amount(one, &period.account_id),
+                one.balance_for(&period.account_id).unwrap_or_default().to_owned(),
+                one.currency.clone().unwrap_or_default(),
+            ];
+            let row: Vec<String> = fields.iter().map(|field| Self::csv_field(field)).collect();
+            csv = format!("{}{}\r\n", csv, row.join(","));
+        }
+        csv.into_bytes()
+    }
+
+    pub fn to_pdf(period: &StatementPeriod, transactions: &[TransactionDetail]) -> Vec<u8> {
+        let mut lines = vec![
+            "TBank Account Statement".to_owned(),
+            format!("Account: {}", period.account_id),
+            format!("Period: {} to {}", period.start.format("%Y-%m-%d"), period.end.format("%Y-%m-%d")),
+            "".to_owned(),
+            format!("{:<10}  {:<36}  {:>12}  {:>12}", "Date", "Description", "Amount", "Balance"),
+        ];
+        for one in transactions {
+            let description: String = Self::description(one).chars().take(36).collect();
+            lines.push(format!(
+                "{:<10}  {:<36}  {:>12}  {:>12}",
+                one.date(),
+                description,
+                Self::amount(one, &period.account_id),
+                one.balance_for(&period.account_id).unwrap_or("-"),
+            ));
+        }
+        if transactions.is_empty() {
+            lines.push("No transactions in this period.".to_owned());
+        }
+
+        let pages: Vec<&[String]> = lines.chunks(LINES_PER_PAGE).collect();
+        let total = pages.len();
+        let streams = pages.iter().enumerate().map(|(index, page)| {
+            let mut content = format!("BT /F1 {} Tf {} TL {} {} Td", FONT_SIZE, LEADING, MARGIN, PAGE_HEIGHT - MARGIN);
+            for line in page.iter() {
+                content = format!("{} ({}) Tj T*", content, Self::pdf_text(line));
+            }
+            format!("{} T* ({}) Tj ET", content, Self::pdf_text(&format!("Page {} of {}", index + 1, total)))
+        });
+        Self::pdf_document(streams.collect())
+    }
+
+    /// The signed amount, or TBank's own text when it is not a number, so no row is dropped or shown as zero.
+    fn amount(transaction: &TransactionDetail, account_id: &str) -> String {
+        match transaction.amount_for(account_id) {
+            Some(amount) => format!("{:.2}", amount),
+            None => {
+                warn!("Transaction {} has an amount that is not a number : {:?}", transaction.transaction_id, transaction.transaction_amount);
+                transaction.transaction_amount.trim().to_owned()
+            }
+        }
+    }
+
+    fn description(transaction: &TransactionDetail) -> String {
+        transaction.narrative.clone().filter(|narrative| !narrative.is_empty()).unwrap_or_else(|| "Transfer".to_owned())
+    }
+
+    fn csv_field(field: &str) -> String {
+        if field.contains([',', '"', '\r', '\n']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_owned()
+        }
+    }
+
+    /// Escapes a line for a PDF string; the standard fonts only cover ASCII here.
+    fn pdf_text(line: &str) -> String {
+        line.chars()
+            .map(|c| match c {
+                '(' | ')' | '\\' => format!("\\{}", c),
+                c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+                _ => "?".to_owned(),
+            })
+            .collect()
+    }
+
+    /// Lays out a PDF with one page per content stream.
+    fn pdf_document(streams: Vec<String>) -> Vec<u8> {
+        let page_ids: Vec<usize> = (0..streams.len()).map(|index| 4 + 2 * index).collect();
+        let kids: Vec<String> = page_ids.iter().map(|id| format!("{} 0 R", id)).collect();
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+            format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), streams.len()),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Courier >>".to_owned(),
+        ];
+        for (page_id, stream) in page_ids.iter().zip(&streams) {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH, PAGE_HEIGHT, page_id + 1
+            ));
+            objects.push(format!("<< /Length {} >>\nstream\n{}\nendstream", stream.len(), stream));
+        }
+
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = vec![];
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf = format!("{}{} 0 obj\n{}\nendobj\n", pdf, index + 1, object);
+        }
+        let xref_offset = pdf.len();
+        pdf = format!("{}xref\n0 {}\n0000000000 65535 f \n", pdf, objects.len() + 1);
+        for offset in offsets {
+            pdf = format!("{}{:010} 00000 n \n", pdf, offset);
+        }
+        pdf = format!("{}trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", pdf, objects.len() + 1, xref_offset);
+        pdf.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn period() -> StatementPeriod {
+        StatementPeriod::parse("100", "2023-01-01 to 2023-01-31").unwrap()
+    }
+
+    fn transaction(id: u32, narrative: &str) -> TransactionDetail {
+        TransactionDetail {
+            transaction_id: id.to_string(),
+            account_from: "100".to_owned(),
+            account_to: "200".to_owned(),
+            transaction_amount: "12.50".to_owned(),
+            transaction_date: "2023-01-05T10:00:00".to_owned(),
+            narrative: Some(narrative.to_owned()),
+            account_from_balance: Some("87.50".to_owned()),
+            account_to_balance: Some("12.50".to_owned()),
+            currency: Some("SGD".to_owned()),
+        }
+    }
+
+    #[test]
+    fn csv_rows_are_signed_and_escaped() {
+        let csv = StatementService::to_csv(&period(), &[transaction(1, "Lunch, \"cheap\"")]);
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "Date,Transaction ID,Description,Amount,Balance,Currency\r\n2023-01-05,1,\"Lunch, \"\"cheap\"\"\",-12.50,87.50,SGD\r\n"
+        );
+    }
+
+    #[test]
+    fn amounts_that_are_not_numbers_are_kept_as_sent() {
+        let mut odd = transaction(2, "Refund");
+        odd.transaction_amount = "12,50".to_owned();
+        let csv = String::from_utf8(StatementService::to_csv(&period(), &[odd.clone()])).unwrap();
+        assert!(csv.ends_with(",Refund,\"12,50\",87.50,SGD\r\n"), "{}", csv);
+        let pdf = String::from_utf8(StatementService::to_pdf(&period(), &[odd])).unwrap();
+        assert!(pdf.contains("Refund") && pdf.contains("12,50"), "{}", pdf);
+    }
+
+    #[test]
+    fn pdf_cross_references_point_at_their_objects() {
+        let transactions: Vec<_> = (1..=150).map(|id| transaction(id, "Coffee (large)")).collect();
+        let pdf = String::from_utf8(StatementService::to_pdf(&period(), &transactions)).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+        assert!(pdf.contains("/Count 3 "));
+        assert!(pdf.contains("Coffee \\(large\\)"));
+        assert!(pdf.contains("(Page 3 of 3) Tj"));
+
+        let startxref: usize = pdf.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        let xref = &pdf[startxref..];
+        assert!(xref.starts_with("xref\n0 10\n"));
+        for (index, entry) in xref.lines().skip(3).take(9).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj\n", index + 1)), "object {}", index + 1);
+        }
+    }
+
+    #[test]
+    fn an_empty_period_still_makes_a_statement() {
+        let pdf = String::from_utf8(StatementService::to_pdf(&period(), &[])).unwrap();
+        assert!(pdf.contains("(No transactions in this period.) Tj"));
+        assert!(pdf.contains("/Count 1 "));
+        assert_eq!(StatementService::to_csv(&period(), &[]), b"Date,Transaction ID,Description,Amount,Balance,Currency\r\n");
+    }
+}
@@ -1,69 +1,132 @@
 
 use std::sync::Arc;
-
-use crate::config::AppConfig;
+use crate::context::AppContext;
+use crate::errors::session_error::SessionError;
+use crate::errors::tbank_error::TBankError;
 use crate::enums::telegram::Command;
-use crate::models::transaction::{TransferBody, AddBeneficiaryBody};
+use crate::enums::callback::CallbackAction;
+use crate::enums::conversation::{ConversationEvent, ConversationState, Effect, Transition};
+use crate::enums::cash::CashMovement;
 use crate::models::{Error, CustomerRequest};
-use crate::models::authentication::{RequestOTP, ServiceLoginOtpResponse};
-use clap::Parser;
+use crate::models::authentication::RequestOTP;
+use crate::models::transaction::{Beneficiary, DeleteBeneficiaryBody, TransactionDetail, TransactionHistoryBody, TransferBody};
+use crate::models::authentication::ServiceLoginOtpResponse;
+use anyhow::Context;
 use rand::Rng;
-use reqwest::Client;
 use teloxide::prelude::ResponseResult;
 use teloxide::{prelude::Requester, types::Message, Bot};
-use tracing::{info, warn};
-use crate::repositories::tbank_repository::TBankRepository;
+use tracing::{error, info, warn};
 use teloxide::{
     payloads::SendMessageSetters,
     prelude::*,
     types::{
-        InlineKeyboardButton, InlineKeyboardMarkup, Me
+        InlineKeyboardButton, InlineKeyboardMarkup, Me, MessageId
     },
     utils::command::BotCommands,
 };
 use teloxide::types::InputFile;
+use teloxide::dispatching::UpdateHandler;
+use teloxide::stop::{mk_stop_token, StopToken};
+use teloxide::update_listeners::StatefulListener;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use futures_util::future::try_join_all;
+use futures_util::StreamExt;
+use std::convert::Infallible;
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Duration, Utc};
 use crate::enums::beneficiary::BeneficiaryEnum;
-use crate::models::customer::HistoricalMonthlyBalanceBody;
-use crate::repositories::redis_repository::RedisRepository;
+use crate::models::chart::{AccountTrend, CHART_WINDOWS};
+use crate::models::customer::{AccountData, HistoricalMonthlyBalanceBody};
+use crate::models::money::{Currency, Money, HOME_CURRENCY};
+use crate::repositories::state_store::StateStore;
+use crate::repositories::tbank_repository::TBankRepository;
+use crate::models::statement::MAX_STATEMENT_DAYS;
+use crate::services::statement_service::StatementService;
 
+// Conversation steps that expire, such as login, are dropped after this long
+const STEP_TTL_SECS: u64 = 120;
+// Recent Transactions looks this far back, a few at a time
+const HISTORY_WINDOW_DAYS: i64 = 90;
+const TRANSACTIONS_PER_PAGE: u32 = 5;
 
 #[derive(Clone)]
 pub struct TelegramService {
     bot: Bot,
+    context: AppContext,
 }
 
 impl TelegramService {
     pub fn new(
-        bot_token: &String,
+        context: AppContext,
     ) -> Self {
-        let reqwest_client = Client::new();
-        let bot = Bot::with_client(bot_token, reqwest_client);
+        let bot = Bot::with_client(&context.config.teloxide_token, context.http_client.clone());
         Self {
             bot,
+            context,
         }
     }
 
-    pub async fn listen_and_reply(self) {
-    
-        let handler = dptree::entry()
+    /// Routes updates to the message and callback handlers, however they were received.
+    fn handler() -> UpdateHandler<teloxide::RequestError> {
+        dptree::entry()
         .branch(Update::filter_message().endpoint(Self::message_handler))
-        .branch(Update::filter_callback_query().endpoint(Self::callback_handler));
+        .branch(Update::filter_callback_query().endpoint(Self::callback_handler))
         // .branch(Update::filter_inline_query().endpoint(Self::inline_query_handler));
+    }
+
+    pub async fn listen_and_reply(self) {
+        Dispatcher::builder(self.bot.clone(), Self::handler())
+            .dependencies(dptree::deps![self.context.clone()])
+            .enable_ctrlc_handler()
+            .build()
+            .dispatch()
+            .await;
+    }
+
+    /// Tells Telegram to post updates to `url`, signed with `secret`, instead of waiting to be polled.
+    pub async fn register_webhook(&self, url: reqwest::Url, secret: String) -> anyhow::Result<()> {
+        self.bot.set_webhook(url).secret_token(secret).await?;
+        Ok(())
+    }
+
+    /// Handles updates the webhook route pushes into `updates`, the same way polling would.
+    pub async fn listen_to_webhook(self, updates: UnboundedReceiver<Update>) {
+        fn stream_of<S>(state: &mut (S, StopToken)) -> &mut S {
+            &mut state.0
+        }
+
+        let (stop_token, _stop_flag) = mk_stop_token();
+        let stream = UnboundedReceiverStream::new(updates).map(Ok::<Update, Infallible>);
+        let listener = StatefulListener::new(
+            (stream, stop_token),
+            stream_of,
+            |(_, stop_token): &mut (_, StopToken)| stop_token.clone(),
+        );
+
+        Dispatcher::builder(self.bot.clone(), Self::handler())
+            .dependencies(dptree::deps![self.context.clone()])
+            .enable_ctrlc_handler()
+            .build()
+            .dispatch_with_listener(listener, LoggingErrorHandler::with_custom_text("An error from the webhook listener"))
+            .await;
+    }
 
-        Dispatcher::builder(self.bot.clone(), handler).enable_ctrlc_handler().build().dispatch().await;
+    /// Creates a keyboard made by buttons in a big column, labelled with each action's default text.
+    fn make_keyboard(options: Vec<CallbackAction>) -> InlineKeyboardMarkup {
+        Self::make_labelled_keyboard(options.into_iter().map(|action| (action.label(), action)).collect())
     }
 
     /// Creates a keyboard made by buttons in a big column.
-    fn make_keyboard(options:Vec<String>) -> InlineKeyboardMarkup {
+    /// Buttons whose action cannot fit in Telegram's callback data are left out rather than misrouted.
+    fn make_labelled_keyboard(options: Vec<(String, CallbackAction)>) -> InlineKeyboardMarkup {
         let mut keyboard: Vec<Vec<InlineKeyboardButton>> = vec![];
 
-        for chunk_options in options.chunks(1) {
-            let row = chunk_options
-                .iter()
-                .map(|option| InlineKeyboardButton::callback(option.to_owned(), option.to_owned()))
-                .collect();
-
-            keyboard.push(row);
+        for (label, action) in options {
+            match action.encode() {
+                Ok(data) => keyboard.push(vec![InlineKeyboardButton::callback(label, data)]),
+                Err(e) => warn!("Leaving out button {:?} : {}", label, e),
+            }
         }
 
         InlineKeyboardMarkup::new(keyboard)
@@ -73,21 +136,44 @@ impl TelegramService {
         bot: Bot,
         msg: Message,
         me: Me,
+        ctx: AppContext,
     ) -> ResponseResult<()>  {
-        //Instantiate service
-        let app_config: Arc<AppConfig> = Arc::new(AppConfig::parse());
-        info!("READ APP CONFIG");
-
-        let redis_repo = RedisRepository::new(
-            app_config.redis_url.clone()
-        ).await;
-        info!("GOT REDIS");
-        
-        let tbank_repo = TBankRepository::new(
-            app_config.tbank_url.clone(),
-            app_config.chart_generator_url.clone()
-        );
-        info!("GOT TBANK");
+        let chat_id = msg.chat.id;
+        if let Err(e) = Self::handle_message(bot.clone(), msg, me, ctx).await {
+            Self::reply_with_failure(bot, chat_id, "message", e).await?;
+        }
+        Ok(())
+    }
+
+    async fn callback_handler(bot: Bot, q: CallbackQuery, ctx: AppContext) -> ResponseResult<()> {
+        let chat_id = match &q.message {
+            Some(msg) => msg.chat.id,
+            None => ChatId(q.from.id.0 as i64),
+        };
+        if let Err(e) = Self::handle_callback(bot.clone(), q, ctx).await {
+            Self::reply_with_failure(bot, chat_id, "callback", e).await?;
+        }
+        Ok(())
+    }
+
+    /// Logs a failed update and tells the user, instead of dropping the handler task.
+    async fn reply_with_failure(bot: Bot, chat_id: ChatId, update_kind: &str, e: anyhow::Error) -> ResponseResult<()> {
+        error!(chat_id = %chat_id, update_kind, error = ?e, "Failed to handle telegram update");
+        bot.send_message(chat_id, "Sorry something went wrong. Please try again.").await?;
+        TelegramService::send_start(bot, chat_id.to_string()).await
+    }
+
+    async fn handle_message(
+        bot: Bot,
+        msg: Message,
+        me: Me,
+        ctx: AppContext,
+    ) -> anyhow::Result<()>  {
+        let store = ctx.state_store.clone();
+        let tbank_repo = ctx.tbank_repository.clone();
+        if Self::end_expired_session(&bot, msg.chat.id, &ctx).await? {
+            return Ok(());
+        }
         if let Some(text) = msg.text() {
             match BotCommands::parse(text, me.username()) {
                 Ok(Command::Help) => {
@@ -96,252 +182,280 @@ impl TelegramService {
                 }
                 Ok(Command::Start) => {
                     // Create a list of buttons and send them.
-                    TelegramService::to_send_correct_start(bot, msg, redis_repo.clone(), true).await?;            
+                    TelegramService::to_send_correct_start(bot, msg, &ctx, true).await?;            
+                }
+                Ok(Command::Sessions) => {
+                    if !ctx.config.is_admin(msg.chat.id) {
+                        bot.send_message(msg.chat.id, "Command not found!").await?;
+                        return Ok(());
+                    }
+                    let sessions = ctx.session_service.active_sessions().await?;
+                    let mut full_text = format!("Active sessions: {}\n", sessions.len());
+                    for (session, remaining) in sessions {
+                        let temp = format!("Chat {} - {} - expires in {}m\n", session.chat_id, session.user_id, remaining.div_ceil(60));
+                        full_text = format!("{}{}", full_text, temp);
+                    }
+                    bot.send_message(msg.chat.id, full_text).await?;
+                }
+                Ok(command @ (Command::Deposit | Command::Withdraw)) => {
+                    if !ctx.config.is_admin(msg.chat.id) {
+                        bot.send_message(msg.chat.id, "Command not found!").await?;
+                        return Ok(());
+                    }
+                    if !ctx.cash_service.is_configured() {
+                        bot.send_message(msg.chat.id, "Cash services are not set up. Please configure a teller login.").await?;
+                        return Ok(());
+                    }
+                    let movement = match command {
+                        Command::Deposit => CashMovement::Deposit,
+                        _ => CashMovement::Withdrawal,
+                    };
+                    Self::advance_state(&store, msg.chat.id, ConversationEvent::StartCash { movement }).await?;
+                    let keyboard = Self::make_keyboard(vec![CallbackAction::Cancel]);
+                    bot.send_message(msg.chat.id, format!("Which account is the {} for?", movement.noun())).reply_markup(keyboard).await?;
                 }
                 Err(_) => {
-                    // Check redis state on what step he is on or if he has any valid state.
-                    let action_key: String = format!("{}:{}",msg.chat.id.to_string(), "action");
-
-                    match redis_repo.clone().get_data_from_redis(&action_key).await {
-                        Ok(result) => {
-                            match &result.as_str() {
-                                &"Add Ben Desc" => {
-                                    let _ = redis_repo.clone().remove_data_in_redis(&action_key).await;
-                                    let my_int: i32 = msg.id.to_string().parse().unwrap();
-                                    bot.edit_message_text(msg.chat.id, teloxide::types::MessageId(my_int-1), "Please wait ...").await?;
-                                    let full_key: String = format!("{}:{}",msg.chat.id.to_string(), "AddBen");
-                                    let temp = redis_repo.clone().get_data_from_redis(&full_key).await.unwrap();
-                                    let mut add_ben_data=  serde_json::from_str::<AddBeneficiaryBody>(&temp).unwrap();
-                                    let _ = redis_repo.clone().remove_data_in_redis(&full_key).await;
-                                    add_ben_data.description = text.to_string();
-                                    let full_key: String = format!("{}:{}",msg.chat.id.to_string(), "LoginCred");
-                                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
-                                        match result {
-                                            Ok(login_cred) => {
-                                                let mut data: CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                                data.service_name = "addBeneficiary".to_owned();
-                                                let result = tbank_repo.add_beneficiary(data, add_ben_data).await;
-                                                if let Ok(status) = result{
-                                                    if status.contains("invocation successful"){
-                                                        bot.delete_message(msg.chat.id, msg.id).await?;
-                                                        bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
-                                                        bot.send_message(msg.chat.id,  "Beneficiary has been added").await?;
-                                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), true).await?;
-
-                                                    }else{
-                                                        bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
-                                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;
-                                                    }
-                                                }else{
-                                                    bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
-                                                    TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;
-                                                }
-                                            },
-                                            Err(_) => {
-                                                bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
-                                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;
-                                            },
-                                        }
-                                }
-                                &"Add Beneficiary" => {
-                                    let _ = redis_repo.clone().remove_data_in_redis(&action_key).await;
-                                    let temp  = AddBeneficiaryBody{
-                                        account_id: text.to_string(),
-                                        description: "".to_owned(),
-                                    };
-                                    bot.delete_message(msg.chat.id, msg.id).await?;
-                                    let my_int: i32 = msg.id.to_string().parse().unwrap();
-                                    bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
-                                    let full_key: String = format!("{}:{}",msg.chat.id.to_string(), "AddBen");
-                                    let temp_string: String =  serde_json::to_string(&temp).unwrap();
-                                    let _ = redis_repo.clone().set_data_in_redis(&full_key, temp_string, false).await;
-                                    let _ = redis_repo.clone().set_data_in_redis(&action_key, "Add Ben Desc".to_owned(), false).await;
+                    // Move the conversation forward with what the user typed.
+                    let my_int: i32 = msg.id.0;
+                    let state = Self::load_state(&store, msg.chat.id).await?;
+                    if state == ConversationState::Idle {
+                        bot.send_message(msg.chat.id, "Command not found!").await?;
+                        return Ok(());
+                    }
+                    let transition = match state.transition(ConversationEvent::Text(text.to_string())) {
+                        Ok(transition) => transition,
+                        Err(e) => {
+                            warn!("Rejected message for chat {} : {}", msg.chat.id, e);
+                            Self::save_state(&store, msg.chat.id, &ConversationState::Idle).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            TelegramService::to_send_correct_start(bot, msg, &ctx, false).await?;
+                            return Ok(());
+                        }
+                    };
+                    Self::save_state(&store, msg.chat.id, &transition.next).await?;
 
-                                    let keyboard = Self::make_keyboard(["Back".to_owned()].to_vec());
-                                    bot.send_message(msg.chat.id,  "Label for the account?").reply_markup(keyboard).await?;
+                    match transition.effect {
+                        Effect::AskPin => {
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::Cancel]);
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            bot.send_message(msg.chat.id, "Please key in your PIN").reply_markup(keyboard).await?;
+                        }
+                        Effect::RequestOtp(data) => {
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            bot.send_message(msg.chat.id, "Checking your credentials....").await?;
+                            ctx.session_service.hold_pin(msg.chat.id, &data.pin).await?;
+                            let result = tbank_repo.request_otp(data).await
+                                .and_then(|reply| (reply.content.service_response.service_response_header as Error).ensure_success());
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int+1)).await?;
+                            match result {
+                                Ok(()) => {
+                                    let keyboard = Self::make_keyboard(vec![CallbackAction::Cancel]);
+                                    bot.send_message(msg.chat.id, "Please key in your OTP").reply_markup(keyboard).await?;
                                 }
-                                &"Amount" => {
-                                    let _ = redis_repo.clone().remove_data_in_redis(&action_key).await;
-                                    let amount = text.trim().parse::<f64>();
-                                    let my_int: i32 = msg.id.to_string().parse().unwrap();
-                                    bot.edit_message_text(msg.chat.id, teloxide::types::MessageId(my_int-1), "Please wait ...").await?;
-                                    if let Ok(a) = amount{
-                                        bot.delete_message(msg.chat.id, msg.id).await?;
-
-                                        let full_key: String = format!("{}:{}",msg.chat.id.to_string(), "LoginCred");
-                                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
-                                        match result {
-                                            Ok(login_cred) => {
-                                                let mut data: CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                                data.service_name = "getCustomerAccounts".to_owned();
-                                                let account_result = tbank_repo.get_customer_accounts(data).await;
-                                                match account_result{
-                                                    Ok(accounts) => {
-                                                        let mut vec_kb: Vec<String> = vec![];
-                                                        for one in accounts {
-                                                            if one.balance.parse::<f64>().unwrap_or(0.0) > a{
-                                                                let acc = format!("Transfer From {}", one.account_id).to_string();
-                                                                vec_kb.push(acc);
-                                                            }
-                                                        }
-                                                        if vec_kb.len() > 0{
-                                                            let tx_key: String = format!("{}:{}", msg.chat.id.to_string(), "Transfer");
-                                                            let temp = redis_repo.clone().get_data_from_redis(&tx_key).await.unwrap();
-                                                            let mut tx_body =  serde_json::from_str::<TransferBody>(&temp).unwrap();
-                                                            tx_body.transaction_amount = format!("{:.2}", a);
-                                                            let _ = redis_repo.clone().remove_data_in_redis(&tx_key).await;
-                                                            let tx_body_string =  serde_json::to_string(&tx_body).unwrap();
-                                                            let _ = redis_repo.clone().set_data_in_redis(&tx_key, tx_body_string, false).await;
-                                                            vec_kb.push("Back".to_owned());
-                                                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
-                                                            let keyboard = Self::make_keyboard(vec_kb);
-                                                            bot.send_message(msg.chat.id,  "Which account would you like to use?").reply_markup(keyboard).await?;
-                                                        }else{
-                                                            TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;                                                        }
-                                                    }
-                                                    Err(_) => {
-                                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;
-                                                    },
-                                                }
-                                            },
-                                            Err(_) => {
-                                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;
-                                            },
-                                        }
-                
-                                    }else{
-                                        bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
-                                        TelegramService::to_send_correct_start( bot, msg, redis_repo.clone(), false).await?; 
-                                    }
+                                Err(e) => {
+                                    Self::save_state(&store, msg.chat.id, &ConversationState::Idle).await?;
+                                    bot.send_message(msg.chat.id, format!("Sorry It seems like we could not authenticate you. {}", e)).await?;
+                                    TelegramService::send_start( bot, msg.chat.id.to_string()).await?; 
                                 }
-                                &"Login" =>{
-                                    let _ = redis_repo.clone().remove_data_in_redis(&action_key).await;
-                                    let _ = redis_repo.clone().set_data_in_redis(&action_key,"Login:PIN".to_owned(), true).await;
-                                    let part_key: String = format!("{}:{}",msg.chat.id.to_string(), "LoginStep");
-                                    let _ = redis_repo.clone().remove_data_in_redis(&part_key).await;
-                                    let empty = RequestOTP{ 
-                                        user_id: text.to_string(),
-                                        service_name: "requestOTP".to_string(),
-                                        pin: "".to_string(),
-                                    };
-                                    let j = serde_json::to_string(&empty).unwrap();
-                                    let _ = redis_repo.clone().set_data_in_redis(&part_key,j, true).await;
-                                    let keyboard = Self::make_keyboard(["Cancel".to_owned()].to_vec());
-                                    let my_int: i32 = msg.id.to_string().parse().unwrap();  
-                                    bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int)).await?;
-                                    bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
-                                    bot.send_message(msg.chat.id, "Please key in your PIN").reply_markup(keyboard).await?;
+                            }
+                        }
+                        Effect::Login(mut data) => {
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            bot.send_message(msg.chat.id, "Logging In ....").await?;
+                            let result: anyhow::Result<()> = match ctx.session_service.take_pin(msg.chat.id).await {
+                                Ok(pin) => {
+                                    data.pin = pin;
+                                    tbank_repo.login_customer(data.clone()).await
+                                        .and_then(|reply| (reply.content.service_response as ServiceLoginOtpResponse).service_response_header.ensure_success())
+                                        .map_err(Into::into)
                                 }
-                                &"Login:PIN"=>{
-                                    bot.delete_message(msg.chat.id, msg.id).await?;
-                                    let my_int: i32 = msg.id.to_string().parse().unwrap();
+                                Err(e) => Err(e),
+                            };
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int+1)).await?;
+                            match result {
+                                Ok(()) => {
+                                    data.otp = "999999".to_string();
+                                    ctx.session_service.create(msg.chat.id, &data).await?;
+                                    let invest_key: String = format!("{}:{}",data.user_id.clone(), "MicroInvest");
+                                    let has_invest = matches!(store.get(&invest_key).await, Ok(Some(_)));
+                                    TelegramService::send_logged_in_user_start( bot, msg.chat.id.to_string(), has_invest).await?; 
+                                }
+                                Err(e) => {
+                                    bot.send_message(msg.chat.id, format!("Sorry It seems like we could not authenticate you. {}", e)).await?;
+                                    TelegramService::send_start( bot, msg.chat.id.to_string()).await?; 
+                                }
+                            }
+                        }
+                        Effect::ChooseSourceAccount(tx_body) => {
+                            bot.edit_message_text(msg.chat.id, teloxide::types::MessageId(my_int-1), "Please wait ...").await?;
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            let data = ctx.session_service.credentials(msg.chat.id).await?;
+                            let accounts = tbank_repo.get_customer_accounts(data).await?;
+                            let amount = tx_body.transaction_amount;
+                            let mut vec_kb: Vec<(String, CallbackAction)> = vec![];
+                            for one in accounts {
+                                // TBank only documents transfers within one currency
+                                let affordable = one.currency == amount.currency && one.balance > amount.amount;
+                                // Transfers between own accounts cannot go back into the same account
+                                if one.account_id != tx_body.account_to && affordable {
+                                    let label = format!("Transfer From {} ({})", one.account_id, one.money());
+                                    vec_kb.push((label, CallbackAction::TransferFrom { account_id: one.account_id }));
+                                }
+                            }
+                            if !vec_kb.is_empty(){
+                                vec_kb.push((CallbackAction::Back.label(), CallbackAction::Back));
+                                bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                                let keyboard = Self::make_labelled_keyboard(vec_kb);
+                                bot.send_message(msg.chat.id,  "Which account would you like to use?").reply_markup(keyboard).await?;
+                            }else{
+                                Self::save_state(&store, msg.chat.id, &ConversationState::Idle).await?;
+                                bot.edit_message_text(msg.chat.id, teloxide::types::MessageId(my_int-1), format!("None of your {} accounts has enough balance for this transfer.", amount.currency)).await?;
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
+                            }
+                        }
+                        Effect::AskBeneficiaryAccount { problem } => {
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::Back]);
+                            bot.send_message(msg.chat.id, format!("{}\nKey in account number to add?", problem)).reply_markup(keyboard).await?;
+                        }
+                        Effect::AskBeneficiaryDescription { problem } => {
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::Back]);
+                            let text = match problem {
+                                Some(problem) => format!("{}\nLabel for the account?", problem),
+                                None => "Label for the account?".to_owned(),
+                            };
+                            bot.send_message(msg.chat.id, text).reply_markup(keyboard).await?;
+                        }
+                        Effect::RenamePayee(update_body) => {
+                            bot.edit_message_text(msg.chat.id, teloxide::types::MessageId(my_int-1), "Please wait ...").await?;
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            let data = ctx.session_service.credentials(msg.chat.id).await?;
+                            let description = update_body.description.clone();
+                            match tbank_repo.update_beneficiary(data, update_body).await {
+                                Ok(()) => {
                                     bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
-                                    let _ = redis_repo.clone().remove_data_in_redis(&action_key).await;
-                                    let part_key: String = format!("{}:{}",msg.chat.id.to_string(), "LoginStep");
-                                    match redis_repo.clone().get_data_from_redis(&part_key).await{
-                                        Ok(partial_result) => {
-                                            let _ = redis_repo.clone().remove_data_in_redis(&part_key).await;
-                                            let mut data:RequestOTP = serde_json::from_str(&partial_result).unwrap();
-                                            data.pin = text.to_string();
-                                            bot.send_message(msg.chat.id, "Checking your credentials....").await?;
-                                            match tbank_repo.request_otp(data.clone()).await{
-                                                Ok(reply) => {
-                                                    bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int+1)).await?;
-                                                    let reply_otp = reply.content.service_response.service_response_header as Error;
-                                                    match reply_otp.error_details{
-                                                        Some(status) => {
-                                                            info!("{}", status);
-                                                            if status != "success" {
-                                                                bot.send_message(msg.chat.id, "Sorry It seems like we could not authenticate you. Please try again.").await?;
-                                                                TelegramService::send_start( bot, msg.chat.id.to_string()).await?; 
-                                                            }else{
-                                                                let partial_login_request = CustomerRequest{ 
-                                                                    service_name: "loginCustomer".to_owned(), 
-                                                                    user_id: data.user_id, 
-                                                                    pin: data.pin, 
-                                                                    otp: "".to_owned() 
-                                                                };
-                                                                let j = serde_json::to_string(&partial_login_request).unwrap();
-                                                                let _ = redis_repo.clone().set_data_in_redis(&part_key,j, true).await;
-                                                                let _ = redis_repo.clone().set_data_in_redis(&action_key,"Login:OTP".to_owned(), true).await;
-                                                                let keyboard = Self::make_keyboard(["Cancel".to_owned()].to_vec());
-                                                                bot.send_message(msg.chat.id, "Please key in your OTP").reply_markup(keyboard).await?;
-                                                            }   
-                                                        },
-                                                        None => {
-                                                            bot.send_message(msg.chat.id, "Sorry It seems like we could not authenticate you. Please try again.").await?;
-                                                            TelegramService::send_start( bot, msg.chat.id.to_string()).await?; 
-                                                        }
-                                                    }
-                                                                                 
-                                                },
-                                                Err(_) => {
-                                                    bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int+1)).await?;
-                                                    bot.send_message(msg.chat.id, "Sorry It seems like we could not authenticate you. Please try again.").await?;
-                                                    TelegramService::send_start( bot, msg.chat.id.to_string()).await?; 
-                                                },
-                                            }
-                                        },
-                                        Err(_) => {
-                                            bot.send_message(msg.chat.id, "Sorry that your session is gone. Please try again.").await?;
-                                            TelegramService::send_start( bot, msg.chat.id.to_string()).await?; 
-                                        },
-                                    }
+                                    bot.send_message(msg.chat.id, format!("Payee has been renamed to {}", description)).await?;
+                                }
+                                Err(e) => {
+                                    warn!("Something went wrong while renaming a payee : {}", e);
+                                    bot.edit_message_text(msg.chat.id, teloxide::types::MessageId(my_int-1), format!("Payee could not be renamed. {}", e)).await?;
                                 }
-                                &"Login:OTP"=>{
+                            }
+                            TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
+                        }
+                        Effect::SendStatement(period) => {
+                            let prompt = teloxide::types::MessageId(my_int-1);
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.edit_message_text(msg.chat.id, prompt, "Please wait ...").await?;
+                            let data = ctx.session_service.credentials(msg.chat.id).await?;
+                            match ctx.statement_service.transactions(data, &period).await {
+                                Ok(transactions) => {
+                                    let csv = InputFile::memory(StatementService::to_csv(&period, &transactions))
+                                        .file_name(format!("{}.csv", period.file_stem()));
+                                    let pdf = InputFile::memory(StatementService::to_pdf(&period, &transactions))
+                                        .file_name(format!("{}.pdf", period.file_stem()));
+                                    let caption = format!("Statement for {} from {} to {}", period.account_id, period.start, period.end);
+                                    bot.delete_message(msg.chat.id, prompt).await?;
+                                    bot.send_document(msg.chat.id, csv).await?;
+                                    bot.send_document(msg.chat.id, pdf).caption(caption).await?;
+                                    TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
+                                }
+                                Err(e) => {
+                                    warn!("Something went wrong while preparing a statement : {}", e);
+                                    bot.edit_message_text(msg.chat.id, prompt, format!("The statement could not be prepared. {}", e)).await?;
+                                    TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
+                                }
+                            }
+                        }
+                        Effect::AddBeneficiary(add_ben_data) => {
+                            bot.edit_message_text(msg.chat.id, teloxide::types::MessageId(my_int-1), "Please wait ...").await?;
+                            let data = ctx.session_service.credentials(msg.chat.id).await?;
+                            let payees = Self::payees(&tbank_repo, data.clone()).await?;
+                            if payees.iter().any(|payee| payee.account_id == add_ben_data.account_id) {
+                                bot.delete_message(msg.chat.id, msg.id).await?;
+                                let text = format!("Account {} is already one of your payees.", add_ben_data.account_id);
+                                bot.edit_message_text(msg.chat.id, teloxide::types::MessageId(my_int-1), text).await?;
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
+                                return Ok(());
+                            }
+                            match tbank_repo.add_beneficiary(data, add_ben_data).await {
+                                Ok(()) => {
                                     bot.delete_message(msg.chat.id, msg.id).await?;
-                                    let my_int: i32 = msg.id.to_string().parse().unwrap();
                                     bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
-                                    let _ = redis_repo.clone().remove_data_in_redis(&action_key).await;
-                                    let part_key: String = format!("{}:{}",msg.chat.id.to_string(), "LoginStep");
-                                    match redis_repo.clone().get_data_from_redis(&part_key).await{
-                                        Ok(partial_result) => {
-                                            let _ = redis_repo.clone().remove_data_in_redis(&part_key).await;
-                                            let mut data:CustomerRequest = serde_json::from_str(&partial_result).unwrap();
-                                            data.otp = text.to_string();
-                                            bot.send_message(msg.chat.id, "Logging In ....").await?;
-                                            match tbank_repo.login_customer(data.clone()).await{
-                                                Ok(reply) => {
-                                                    bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int+1)).await?;
-                                                    let response = reply.content.service_response as ServiceLoginOtpResponse;
-                                                    if response.service_response_header.error_details.unwrap() != "Success".to_string() {
-                                                        bot.send_message(msg.chat.id, "Sorry It seems like we could not authenticate you. Please try again.").await?;
-                                                        TelegramService::send_start( bot, msg.chat.id.to_string()).await?; 
-                                                    }else{
-                                                        data.otp = "999999".to_string();
-                                                        let j = serde_json::to_string(&data).unwrap();
-                                                        let full_key: String = format!("{}:{}",msg.chat.id.to_string(), "LoginCred");
-                                                        let _ = redis_repo.clone().set_data_in_redis(&full_key,j, false).await;
-                                                        let invest_key: String = format!("{}:{}",data.user_id.clone(), "MicroInvest");
-                                                        let has_invest = match redis_repo.clone().get_data_from_redis(&invest_key).await{
-                                                            Ok(_) => true,
-                                                            Err(_) => false,
-                                                        };
-                                                        TelegramService::send_logged_in_user_start( bot, msg.chat.id.to_string(), has_invest).await?; 
-                                                    }                                
-                                                },
-                                                Err(_) => {
-                                                    bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int+1)).await?;
-                                                    bot.send_message(msg.chat.id, "Sorry It seems like we could not authenticate you. Please try again.").await?;
-                                                    TelegramService::send_start( bot, msg.chat.id.to_string()).await?; 
-                                                },
-                                            }
-                                        },
-                                        Err(_) => {
-                                            bot.send_message(msg.chat.id, "Sorry that your session for is gone. Please try again.").await?;
-                                            TelegramService::send_start( bot, msg.chat.id.to_string()).await?; 
-                                        },
-                                    }
+                                    bot.send_message(msg.chat.id,  "Beneficiary has been added").await?;
+                                    TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
                                 }
-                                _ => {
-                                    TelegramService::to_send_correct_start(bot, msg, redis_repo.clone(), false).await?;            
+                                Err(e) => {
+                                    warn!("Something went wrong while adding beneficiary : {}", e);
+                                    bot.delete_message(msg.chat.id, msg.id).await?;
+                                    bot.edit_message_text(msg.chat.id, teloxide::types::MessageId(my_int-1), format!("Beneficiary could not be added. {}", e)).await?;
+                                    TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
                                 }
                             }
-                        },
-                        Err(_) => {
-                            bot.send_message(msg.chat.id, "Command not found!").await?;
-                        },
-                    };
+                        }
+                        Effect::TransferWithOtp { transfer, otp } => {
+                            let prompt = teloxide::types::MessageId(my_int-1);
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.edit_message_text(msg.chat.id, prompt, "Please wait ...").await?;
+                            let mut data = ctx.session_service.credentials(msg.chat.id).await?;
+                            data.otp = otp;
+                            TelegramService::complete_transfer(bot, msg, prompt, &ctx, data, transfer).await?;
+                        }
+                        Effect::AskCashCurrency { account_id } => {
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::Cancel]);
+                            let text = format!("Which currency is account {} in? Key in its code, such as SGD.", account_id);
+                            bot.send_message(msg.chat.id, text).reply_markup(keyboard).await?;
+                        }
+                        Effect::AskCashAmount { movement, currency } => {
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::Cancel]);
+                            let text = match movement {
+                                CashMovement::Deposit => format!("How much cash is being deposited? Key in the amount in {}.", currency),
+                                CashMovement::Withdrawal => format!("How much cash is being withdrawn? Key in the amount in {}.", currency),
+                            };
+                            bot.send_message(msg.chat.id, text).reply_markup(keyboard).await?;
+                        }
+                        Effect::ShowCashSummary(order) => {
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::ConfirmCash, CallbackAction::Cancel]);
+                            let text = format!(
+                                "Please confirm the cash {}\nAccount: {}\nAmount: {}\nNarrative: {}",
+                                order.movement.noun(), order.account_id, order.amount, order.narrative
+                            );
+                            bot.send_message(msg.chat.id, text).reply_markup(keyboard).await?;
+                        }
+                        Effect::AskRegistrationField { field, problem } => {
+                            // Answers carry personal details, so none of them stay in the chat
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::Cancel]);
+                            let text = match problem {
+                                Some(problem) => format!("{}\n{}", problem, field.prompt()),
+                                None => field.prompt().to_owned(),
+                            };
+                            bot.send_message(msg.chat.id, text).reply_markup(keyboard).await?;
+                        }
+                        Effect::ShowRegistration(registration) => {
+                            bot.delete_message(msg.chat.id, msg.id).await?;
+                            bot.delete_message(msg.chat.id, teloxide::types::MessageId(my_int-1)).await?;
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::ConfirmRegistration, CallbackAction::Cancel]);
+                            let text = format!("Please check your details\n{}", registration.summary());
+                            bot.send_message(msg.chat.id, text).reply_markup(keyboard).await?;
+                        }
+                        Effect::None | Effect::ShowSummary(_) | Effect::Transfer(_) | Effect::RequestTransferOtp(_) | Effect::MoveCash(_) | Effect::Onboard(_) => {
+                            TelegramService::to_send_correct_start(bot, msg, &ctx, false).await?;
+                        }
+                    }
                 }
             }
         }
@@ -349,247 +463,432 @@ impl TelegramService {
         Ok(())
     }
 
-    async fn callback_handler(bot: Bot, q: CallbackQuery) -> ResponseResult<()> {
-        if let Some(mut action) = q.data {
+    async fn handle_callback(bot: Bot, q: CallbackQuery, ctx: AppContext) -> anyhow::Result<()> {
+        if let Some(data) = q.data {
             bot.answer_callback_query(q.id).await?;
-            //Instantiate service
-            let app_config: Arc<AppConfig> = Arc::new(AppConfig::parse());
-            info!("READ APP CONFIG");
-
-            let redis_repo = RedisRepository::new(
-                app_config.redis_url.clone()
-            ).await;
-            info!("GOT REDIS");
-            
-            let tbank_repo = TBankRepository::new(
-                app_config.tbank_url.clone(),
-                app_config.chart_generator_url.clone()
-            );
-            let mut account_number = "".to_owned();
-            let mut percentage_to_invest = "2".to_owned();
-
-            if action.contains("Reselect") {
-                if q.message.is_some() {
-                    let msg = q.message.clone().unwrap();
+            let chat_id = match &q.message {
+                Some(msg) => msg.chat.id,
+                None => ChatId(q.from.id.0 as i64),
+            };
+            if Self::end_expired_session(&bot, chat_id, &ctx).await? {
+                return Ok(());
+            }
+            let store = ctx.state_store.clone();
+            let tbank_repo = ctx.tbank_repository.clone();
+            let mut action = match CallbackAction::decode(&data) {
+                Ok(action) => Some(action),
+                Err(e) => {
+                    warn!("Ignoring callback data {:?} : {}", data, e);
+                    None
+                }
+            };
+
+            if action == Some(CallbackAction::ReselectInvestAccount) {
+                if let Some(msg) = q.message.clone() {
                     let chat = msg.clone().chat; 
-                    let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                    let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                    let result = ctx.session_service.credentials(chat.id).await;
                     match result {
-                        Ok(login_cred) => {
-                            let data:CustomerRequest = serde_json::from_str(&login_cred).unwrap();
+                        Ok(data) => {
                             let full_key: String = format!("{}:{}",data.user_id, "MicroInvest");
-                            action = "Enable MicroInvest".to_owned();
-                            let _ = redis_repo.clone().remove_data_in_redis(&full_key).await;
+                            action = Some(CallbackAction::EnableMicroInvest);
+                            let _ = store.delete(&full_key).await;
                         }
                         Err(_) => {
-                            action = "".to_owned();
+                            action = None;
                         },
                     }
                 }
-
-            } else if action.contains("Balance History") {
-                account_number = action.split(" ").nth(2).unwrap().to_string();
-                action = "Chart".to_owned();
-            }
-            else if action.contains("%"){
-                percentage_to_invest = action.replace("%", "");
-                action = "Percentage".to_owned();
-
-            } else if action.contains("Account") && action != "Remove Account"{
-                account_number = action.split(" ").last().unwrap().to_string();
-                action = "Account".to_owned();
-            } else if action.contains("Transfer To") {
-                account_number = action.split(" ").last().unwrap().to_string();
-                action = "Amount".to_owned();
-            }else if action.contains("Transfer From"){
-                account_number = action.split(" ").last().unwrap().to_string();
-                action = "TransferFrom".to_owned();
             }
             
-            info!("GOT TBANK");
-            match &action.as_str() {
-                &"Amount" => {
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+            match action {
+                Some(CallbackAction::TransferTo { account_id: account_number }) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;
-                        // TODO: Transfer To String
-                        let num = rand::thread_rng().gen_range(u64::MIN..u64::MAX);
-
-                        let tx_body = TransferBody{
-                            account_from: "".to_owned(),
-                            account_to: account_number,
-                            transaction_amount: "".to_owned(),
-                            transaction_reference_number: format!("{}", num),
-                            narrative: "".to_owned(),
-                        };
-                        let tx_key: String = format!("{}:{}", chat.id.to_string(), "Transfer");
-                        let full_key: String = format!("{}:{}", chat.id.to_string(), "action");
-                        let _ = redis_repo.clone().set_data_in_redis(&full_key, "Amount".to_owned(), false).await;
-                        let _ = redis_repo.clone().set_data_in_redis(&tx_key, serde_json::to_string(&tx_body).unwrap(), false).await;
-                        let keyboard = Self::make_keyboard(["Back".to_owned()].to_vec());
-                        bot.edit_message_text(chat.id, id, "How much do you want to transfer?").reply_markup(keyboard).await?;
+                        let result = ctx.session_service.credentials(chat.id).await;
+                        match result {
+                            Ok(data) => {
+                                let currency = Self::currency_of(&tbank_repo, data, &account_number).await?;
+                                let num = rand::thread_rng().gen::<u64>();
+
+                                Self::advance_state(&store, chat.id, ConversationEvent::StartTransfer {
+                                    account_to: account_number.clone(),
+                                    currency,
+                                    reference: format!("{}", num),
+                                }).await?;
+                                let text = if currency == HOME_CURRENCY {
+                                    "How much do you want to transfer?".to_owned()
+                                } else {
+                                    format!("How much do you want to transfer?\nKey in the amount in {} that account {} should receive.", currency, account_number)
+                                };
+                                let keyboard = Self::make_keyboard(vec![CallbackAction::Back]);
+                                bot.edit_message_text(chat.id, id, text).reply_markup(keyboard).await?;
+                            }
+                            Err(_) => {
+                                TelegramService::to_send_correct_start(bot.clone(), msg.clone(), &ctx, false).await?;
+                            },
+                        }
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Login" => {
+                Some(CallbackAction::Login) => {
                     // Push to redis user state to invalidate 
                     let text = "Please key in your username";
                     // Edit text of the message to which the buttons were attached
-                    let keyboard = Self::make_keyboard(["Cancel".to_owned()].to_vec());
+                    let keyboard = Self::make_keyboard(vec![CallbackAction::Cancel]);
                     if let Some(Message { id, chat, .. }) = q.message {
-                        let action_key = format!("{}:{}", chat.id.to_string(), "action");
-                        let _ = redis_repo.clone().remove_data_in_redis(&action_key).await;
-                        let _ = redis_repo.set_data_in_redis(&action_key,"Login".to_owned(), true).await;
+                        Self::advance_state(&store, chat.id, ConversationEvent::StartLogin).await?;
                         bot.edit_message_text(chat.id, id, text).reply_markup(keyboard).await?;
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Cancel" =>{
+                Some(CallbackAction::Register) => {
+                    if let Some(Message { id, chat, .. }) = q.message {
+                        let transition = Self::advance_state(&store, chat.id, ConversationEvent::StartRegistration).await?;
+                        if let Effect::AskRegistrationField { field, .. } = transition.effect {
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::Cancel]);
+                            let text = format!("Let's open your TBank account.\n{}", field.prompt());
+                            bot.edit_message_text(chat.id, id, text).reply_markup(keyboard).await?;
+                        }
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot, id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::ConfirmRegistration) => {
+                    if let Some(Message { id, chat, .. }) = q.message {
+                        bot.edit_message_text(chat.id, id, "Please wait ...").await?;
+                        // Taken rather than read, so a double tap on Confirm cannot register twice
+                        let state = Self::take_state(&store, chat.id).await?;
+                        let transition = state.transition(ConversationEvent::Confirm { step_up: false })?;
+                        Self::save_state(&store, chat.id, &transition.next).await?;
+                        if let Effect::Onboard(registration) = transition.effect {
+                            let user_id = registration.preferred_user_id.clone();
+                            match tbank_repo.onboard_customer(registration.into_onboard_data()).await {
+                                Ok(reply) => {
+                                    // The PIN TBank replies with never goes into the chat history
+                                    let text = format!(
+                                        "Welcome to TBank! Your account is ready.\nCustomer ID: {}\nAccount ID: {}\nUser ID: {}\nFor your security your PIN is not sent here. Please collect it from TBank before you log in.",
+                                        reply.customer_id.unwrap_or_default(),
+                                        reply.account_id.unwrap_or_default(),
+                                        user_id,
+                                    );
+                                    bot.edit_message_text(chat.id, id, text).await?;
+                                }
+                                Err(e) => {
+                                    warn!("Onboarding failed : {}", e);
+                                    bot.edit_message_text(chat.id, id, format!("We could not register you. {}", e)).await?;
+                                }
+                            }
+                        }
+                        TelegramService::send_start( bot, chat.id.to_string()).await?;
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot, id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::Cancel) => {
                     // Delete user state to invalidate 
                     if let Some(Message { id, chat, .. }) = q.message {
-                        let action_key = format!("{}:{}", chat.id.to_string(), "action");
-                        let _ = redis_repo.clone().remove_data_in_redis(&action_key).await;
+                        Self::advance_state(&store, chat.id, ConversationEvent::Reset).await?;
                         bot.delete_message(chat.id, id).await?;
                         TelegramService::send_start( bot, chat.id.to_string()).await?;
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Add Beneficiary" => {
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::ConfirmCash) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        if !ctx.config.is_admin(chat.id) {
+                            Self::advance_state(&store, chat.id, ConversationEvent::Reset).await?;
+                            bot.delete_message(chat.id, id).await?;
+                            TelegramService::send_start( bot, chat.id.to_string()).await?;
+                            return Ok(());
+                        }
+                        bot.edit_message_text(chat.id, id, "Please wait ...").await?;
+                        // Taken rather than read, so a double tap on Confirm cannot move the cash twice
+                        let state = Self::take_state(&store, chat.id).await?;
+                        let transition = state.transition(ConversationEvent::Confirm { step_up: false })?;
+                        Self::save_state(&store, chat.id, &transition.next).await?;
+                        if let Effect::MoveCash(order) = transition.effect {
+                            let noun = order.movement.noun();
+                            let account_id = order.account_id.clone();
+                            let amount = order.amount;
+                            match ctx.cash_service.execute(order, &chat.id.to_string()).await {
+                                Ok(response) => {
+                                    let text = format!(
+                                        "Cash {} of {} for account {} is done.\nBalance before: {}\nBalance after: {}\nTransaction ID: {}",
+                                        noun,
+                                        amount,
+                                        account_id,
+                                        Money::new(response.balance_before.unwrap_or_default(), amount.currency),
+                                        Money::new(response.balance_after.unwrap_or_default(), amount.currency),
+                                        response.transaction_id.unwrap_or_default(),
+                                    );
+                                    bot.edit_message_text(chat.id, id, text).await?;
+                                }
+                                Err(e) => {
+                                    warn!("Cash {} for account {} failed : {}", noun, account_id, e);
+                                    bot.edit_message_text(chat.id, id, format!("The cash {} could not be completed. {}", noun, e)).await?;
+                                }
+                            }
+                        }
+                        TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot, id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::AddBeneficiary) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;
-                        let full_key: String = format!("{}:{}", chat.id.to_string(), "action");
-                        let _ = redis_repo.clone().set_data_in_redis(&full_key, "Add Beneficiary".to_owned(), false).await;
-                        let keyboard = Self::make_keyboard(["Back".to_owned()].to_vec());
+                        Self::advance_state(&store, chat.id, ConversationEvent::StartAddBeneficiary).await?;
+                        let keyboard = Self::make_keyboard(vec![CallbackAction::Back]);
                         bot.edit_message_text(chat.id, id, "Key in account number to add?").reply_markup(keyboard).await?;
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Transfer" =>{
-                    // Delete user state to invalidate 
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::ManagePayees) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        match ctx.session_service.credentials(chat.id).await {
+                            Ok(data) => {
+                                // Leaving a rename half way through drops it
+                                Self::advance_state(&store, chat.id, ConversationEvent::Reset).await?;
+                                let payees = Self::payees(&tbank_repo, data).await?;
+                                let text = if payees.is_empty() { "You have no payees yet." } else { "Which payee would you like to manage?" };
+                                let mut vec_kb: Vec<(String, CallbackAction)> = vec![];
+                                for payee in payees {
+                                    let label = format!("{} {}", payee.description, payee.account_id);
+                                    vec_kb.push((label, CallbackAction::ViewPayee { beneficiary_id: payee.beneficiary_id }));
+                                }
+                                vec_kb.push((CallbackAction::AddBeneficiary.label(), CallbackAction::AddBeneficiary));
+                                vec_kb.push((CallbackAction::Back.label(), CallbackAction::Back));
+                                let keyboard = Self::make_labelled_keyboard(vec_kb);
+                                bot.edit_message_text(chat.id, id, text).reply_markup(keyboard).await?;
+                            }
+                            Err(_) => {
+                                TelegramService::to_send_correct_start(bot.clone(), msg.clone(), &ctx, false).await?;
+                            }
+                        }
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot.clone(), id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::ViewPayee { beneficiary_id }) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        let data = match ctx.session_service.credentials(chat.id).await {
+                            Ok(data) => data,
+                            Err(_) => {
+                                TelegramService::to_send_correct_start(bot.clone(), msg.clone(), &ctx, false).await?;
+                                return Ok(());
+                            }
+                        };
+                        let Some(payee) = Self::find_payee(&tbank_repo, data.clone(), &beneficiary_id).await? else {
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::ManagePayees, CallbackAction::Back]);
+                            bot.edit_message_text(chat.id, id, "That payee no longer exists.").reply_markup(keyboard).await?;
+                            return Ok(());
+                        };
+                        let keyboard = Self::make_keyboard(vec![
+                            CallbackAction::RenamePayee { beneficiary_id: beneficiary_id.clone() },
+                            CallbackAction::DeletePayee { beneficiary_id },
+                            CallbackAction::ManagePayees,
+                        ]);
+                        let text = format!("Payee: {}\nAccount: {}\nCurrency: {}", payee.description, payee.account_id, payee.currency);
+                        bot.edit_message_text(chat.id, id, text).reply_markup(keyboard).await?;
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot.clone(), id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::DeletePayee { beneficiary_id }) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        let data = match ctx.session_service.credentials(chat.id).await {
+                            Ok(data) => data,
+                            Err(_) => {
+                                TelegramService::to_send_correct_start(bot.clone(), msg.clone(), &ctx, false).await?;
+                                return Ok(());
+                            }
+                        };
+                        let Some(payee) = Self::find_payee(&tbank_repo, data.clone(), &beneficiary_id).await? else {
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::ManagePayees, CallbackAction::Back]);
+                            bot.edit_message_text(chat.id, id, "That payee no longer exists.").reply_markup(keyboard).await?;
+                            return Ok(());
+                        };
+                        let keyboard = Self::make_keyboard(vec![CallbackAction::ConfirmDeletePayee { beneficiary_id }, CallbackAction::ManagePayees]);
+                        let text = format!("Remove {} ({}) from your payees?", payee.description, payee.account_id);
+                        bot.edit_message_text(chat.id, id, text).reply_markup(keyboard).await?;
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot.clone(), id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::ConfirmDeletePayee { beneficiary_id }) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        let data = match ctx.session_service.credentials(chat.id).await {
+                            Ok(data) => data,
+                            Err(_) => {
+                                TelegramService::to_send_correct_start(bot.clone(), msg.clone(), &ctx, false).await?;
+                                return Ok(());
+                            }
+                        };
+                        let Some(payee) = Self::find_payee(&tbank_repo, data.clone(), &beneficiary_id).await? else {
+                            let keyboard = Self::make_keyboard(vec![CallbackAction::ManagePayees, CallbackAction::Back]);
+                            bot.edit_message_text(chat.id, id, "That payee no longer exists.").reply_markup(keyboard).await?;
+                            return Ok(());
+                        };
+                        bot.edit_message_text(chat.id, id, "Please wait ...").await?;
+                        match tbank_repo.delete_beneficiary(data, DeleteBeneficiaryBody { beneficiary_id }).await {
+                            Ok(()) => {
+                                bot.edit_message_text(chat.id, id, format!("{} has been removed from your payees.", payee.description)).await?;
+                            }
+                            Err(e) => {
+                                warn!("Something went wrong while deleting a payee : {}", e);
+                                bot.edit_message_text(chat.id, id, format!("Payee could not be removed. {}", e)).await?;
+                            }
+                        }
+                        TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot.clone(), id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::RenamePayee { beneficiary_id }) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        if ctx.session_service.credentials(chat.id).await.is_err() {
+                            TelegramService::to_send_correct_start(bot.clone(), msg.clone(), &ctx, false).await?;
+                            return Ok(());
+                        }
+                        Self::advance_state(&store, chat.id, ConversationEvent::StartRenamePayee { beneficiary_id }).await?;
+                        let keyboard = Self::make_keyboard(vec![CallbackAction::Back]);
+                        bot.edit_message_text(chat.id, id, "New label for the payee?").reply_markup(keyboard).await?;
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot.clone(), id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::Transfer) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        if ctx.session_service.credentials(chat.id).await.is_err() {
+                            TelegramService::to_send_correct_start(bot.clone(), msg.clone(), &ctx, false).await?;
+                            return Ok(());
+                        }
+                        let keyboard = Self::make_keyboard(vec![CallbackAction::TransferOwn, CallbackAction::TransferOther, CallbackAction::Back]);
+                        bot.edit_message_text(chat.id, id, "Who would you like to transfer to?").reply_markup(keyboard).await?;
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot.clone(), id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::TransferOwn) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        let result = ctx.session_service.credentials(chat.id).await;
+                        match result {
+                            Ok(data) => {
+                                let accounts = tbank_repo.get_customer_accounts(data).await?;
+                                if accounts.len() < 2 {
+                                    let keyboard = Self::make_keyboard(vec![CallbackAction::Back]);
+                                    bot.edit_message_text(chat.id, id, "You need at least two accounts to transfer between them.").reply_markup(keyboard).await?;
+                                    return Ok(());
+                                }
+                                let mut vec_kb: Vec<(String, CallbackAction)> = vec![];
+                                for one in accounts {
+                                    let label = format!("Transfer To My Account {} ({})", one.account_id, one.money());
+                                    vec_kb.push((label, CallbackAction::TransferTo { account_id: one.account_id }));
+                                }
+                                vec_kb.push((CallbackAction::Back.label(), CallbackAction::Back));
+                                let keyboard = Self::make_labelled_keyboard(vec_kb);
+                                bot.edit_message_text(chat.id, id, "Which of your accounts would you like to transfer to?").reply_markup(keyboard).await?;
+                            }
+                            Err(_) => {
+                                TelegramService::to_send_correct_start(bot.clone(), msg.clone(), &ctx, false).await?;
+                            },
+                        }
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot.clone(), id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::TransferOther) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id; 
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        let result = ctx.session_service.credentials(chat.id).await;
                         match result {
-                            Ok(login_cred) => {
-                                let mut data: CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                data.service_name = "getBeneficiaryList".to_owned(); 
+                            Ok(data) => {
                                 let beneficiaries = match tbank_repo.get_beneficiaries(data, BeneficiaryEnum::OTHER).await {
                                     Ok(ben) => ben,
                                     Err(e) => {
                                         warn!("Something went wrong while getting beneficiaries : {}", e);
-                                        TelegramService::to_send_correct_start(bot.clone(), msg.clone(), redis_repo.clone(), false).await?;
+                                        TelegramService::to_send_correct_start(bot.clone(), msg.clone(), &ctx, false).await?;
                                         vec![]
                                     }
                                 };
 
-                                let mut vec_kb: Vec<String> = vec![];
+                                let mut vec_kb: Vec<(String, CallbackAction)> = vec![];
                                 for ben in beneficiaries {
-                                    let acc = format!("Transfer To {} {}", ben.description, ben.account_id).to_string();
-                                    vec_kb.push(acc);
+                                    let label = format!("Transfer To {} {}", ben.description, ben.account_id);
+                                    vec_kb.push((label, CallbackAction::TransferTo { account_id: ben.account_id }));
                                 }
 
-                                vec_kb.push("Add Beneficiary".to_owned());
-                                vec_kb.push("Back".to_owned());
-                                // TODO: Add Beneficiary
-                                let keyboard = Self::make_keyboard(vec_kb);
+                                vec_kb.push((CallbackAction::AddBeneficiary.label(), CallbackAction::AddBeneficiary));
+                                vec_kb.push((CallbackAction::Back.label(), CallbackAction::Back));
+                                let keyboard = Self::make_labelled_keyboard(vec_kb);
                                 bot.edit_message_text(chat.id, id, "Where would you like to transfer to?").reply_markup(keyboard).await?;
                             }
                             Err(_) => {
-                                TelegramService::to_send_correct_start(bot.clone(), msg.clone(), redis_repo.clone(), false).await?;
+                                TelegramService::to_send_correct_start(bot.clone(), msg.clone(), &ctx, false).await?;
                             },
                         }
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot.clone(), id.to_string()).await?;
                     }
                 }
-                &"TransferFrom" => {
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::TransferFrom { account_id: account_number }) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;
                         bot.edit_message_text(chat.id, id, "Please wait ...").await?;
-                        let tx_key: String = format!("{}:{}", msg.chat.id.to_string(), "Transfer");
-                        let temp = redis_repo.clone().get_data_from_redis(&tx_key).await.unwrap();
-                        let mut tx_body =  serde_json::from_str::<TransferBody>(&temp).unwrap();
-                        tx_body.account_from = account_number;
-                        tx_body.narrative = "Simple Transfer".to_owned();
-                        let _ = redis_repo.clone().remove_data_in_redis(&tx_key).await;
-                        let _ = redis_repo.clone().set_data_in_redis(&tx_key, serde_json::to_string(&tx_body).unwrap(), false).await;
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        let tx_body = match Self::advance_state(&store, chat.id, ConversationEvent::SelectSourceAccount {
+                            account_from: account_number,
+                        }).await?.effect {
+                            Effect::ShowSummary(tx_body) => tx_body,
+                            other => anyhow::bail!("Unexpected effect {:?} when selecting a source account", other),
+                        };
+                        let result = ctx.session_service.credentials(chat.id).await;
                         match result {
-                            Ok(login_cred) => {
-                                let data:CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                let invest_key: String = format!("{}:{}",data.user_id, "MicroInvest");
-                                let acct = match redis_repo.clone().get_data_from_redis(&invest_key).await{
-                                    Ok(acct) => if acct != ""{Some(acct)}else{None},
-                                    Err(_) => None,
-                                };
-                                info!("{:?} --data??", acct);
-
-                                if let Some(acct) = acct {
-                                    if acct != tx_body.account_from{
-                                        let full_key: String = format!("{}:{}",data.user_id, "Percentage");
-                                        match redis_repo.clone().get_data_from_redis(&full_key).await{
-                                            Ok(percentage_str) => {
-                                                info!("{:?} --data??", percentage_str);
-                                                let percentage = percentage_str.parse::<f64>().unwrap();
-                                                let temp = tx_body.transaction_amount.parse::<f64>().unwrap();
-                                                let to_invest = temp * (percentage/100.0);
-                                                let total: f64 = temp+to_invest;
-                                                info!("{:?} --data??", total);
-                                                let mut data:CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                                data.service_name = "getCustomerAccounts".to_owned();
-                                                let account_result = tbank_repo.get_customer_accounts(data).await;
-                                                let is_enough = match account_result{
-                                                    Ok(accounts) => {   
-                                                        let mut to_return = false;                
-                                                        for one in accounts{
-                                                            if one.account_id == tx_body.account_from{
-                                                                to_return =  one.balance.parse::<f64>().unwrap() >= total;
-                                                                info!("{:?} --data??", to_return);
-                                                                break;
-                                                            }
-                                                        }
-                                                        to_return
-                                                    }
-                                                    Err(_) => {
-                                                        false
-                                                    },
-                                                };
-                                                if is_enough {
-                                                    let keyboard: InlineKeyboardMarkup = Self::make_keyboard(["Confirm".to_owned(), "Back".to_owned()].to_vec());
-                                                    bot.edit_message_text(chat.id, id, format!("SUMMARY\nTransferring to: {}\nTransferring from {}\nAmount: ${:.2}\nMicro-Invest amount: ${:.2}", tx_body.account_to, tx_body.account_from, temp, to_invest)).reply_markup(keyboard).await?;        
-                                                }else{
-                                                    TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
-                                                }
-                                            },  
-                                            Err(_) => {
-                                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
-                                            }                                   
-                                        }
-                                    }else{
-                                        let keyboard: InlineKeyboardMarkup = Self::make_keyboard(["Confirm".to_owned(), "Back".to_owned()].to_vec());
-                                        bot.edit_message_text(chat.id, id, format!("SUMMARY\nTransferring to: {}\nTransferring from {}\nAmount: ${}", tx_body.account_to, tx_body.account_from, tx_body.transaction_amount)).reply_markup(keyboard).await?;    
+                            Ok(data) => {
+                                let (summary, source, debit) = match Self::transfer_summary(&ctx, data, &tx_body).await {
+                                    Ok(prepared) => prepared,
+                                    Err(e) => {
+                                        warn!("Transfer for chat {} could not be set up : {}", chat.id, e);
+                                        Self::save_state(&store, chat.id, &ConversationState::Idle).await?;
+                                        bot.edit_message_text(chat.id, id, format!("The transfer could not be set up. {}", e)).await?;
+                                        TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
+                                        return Ok(());
                                     }
-
-                                }else{
-                                    let keyboard: InlineKeyboardMarkup = Self::make_keyboard(["Confirm".to_owned(), "Back".to_owned()].to_vec());
-                                    bot.edit_message_text(chat.id, id, format!("SUMMARY\nTransferring to: {}\nTransferring from {}\nAmount: ${}", tx_body.account_to, tx_body.account_from, tx_body.transaction_amount)).reply_markup(keyboard).await?;
+                                };
+                                if source.balance < debit.amount {
+                                    Self::save_state(&store, chat.id, &ConversationState::Idle).await?;
+                                    bot.edit_message_text(chat.id, id, format!("Account {} does not have the {} this transfer needs.", source.account_id, debit)).await?;
+                                    TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
+                                    return Ok(());
                                 }
+                                let keyboard: InlineKeyboardMarkup = Self::make_keyboard(vec![CallbackAction::Confirm, CallbackAction::Back]);
+                                bot.edit_message_text(chat.id, id, summary).reply_markup(keyboard).await?;
                             }
                             Err(_) => {
-                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                             },
                         }
                         
@@ -597,162 +896,129 @@ impl TelegramService {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Confirm" =>{
-                    if q.message.is_some() {
-                        
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::Confirm) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;
                         bot.edit_message_text(chat.id, id, "Please wait ...").await?;        
 
-                        let tx_key: String = format!("{}:{}", msg.chat.id.to_string(), "Transfer");
-                        let temp: String = redis_repo.clone().get_data_from_redis(&tx_key).await.unwrap();
-                        let mut tx_body: TransferBody =  serde_json::from_str::<TransferBody>(&temp).unwrap();
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        // Taken rather than read, so a double tap on Confirm cannot send the transfer twice
+                        let state = Self::take_state(&store, chat.id).await?;
+                        let result = ctx.session_service.credentials(chat.id).await;
                         match result {
-                            Ok(login_cred) => {
-                                let mut data:CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                let invest_key: String = format!("{}:{}",data.user_id, "MicroInvest");
-                                let acct = match redis_repo.clone().get_data_from_redis(&invest_key).await{
-                                    Ok(acct) => if acct != ""{Some(acct)}else{None},
-                                    Err(_) => None,
-                                };
-                                let percent_key: String = format!("{}:{}",data.user_id, "Percentage");
-                                let percentage_str = match redis_repo.clone().get_data_from_redis(&percent_key).await{
-                                    Ok(percent) => if percent != ""{Some(percent)}else{None},
-                                    Err(_) => None,
+                            Ok(data) => {
+                                let step_up = match &state {
+                                    ConversationState::ConfirmingTransfer { transfer } => Self::needs_step_up(&ctx, transfer.transaction_amount),
+                                    _ => false,
                                 };
-                                data.service_name = "creditTransfer".to_owned();
-                                let r =tbank_repo.clone().transfer(data.clone(), tx_body.clone()).await;
-                                if let Ok(status) = r{
-                                    if status.contains("invocation successful"){
-                                        if let Some(acct) = acct {
-                                            if acct != tx_body.account_from{
-                                                let percentage = percentage_str.unwrap().parse::<f64>().unwrap();
-                                                let temp = tx_body.transaction_amount.parse::<f64>().unwrap();
-                                                let to_invest = temp * (percentage/100.0);
-                                                tx_body.account_to = acct;
-                                                tx_body.transaction_amount = format!("{:.2}", to_invest);
-                                                tx_body.narrative = "Micro-Invest".to_owned();
-                                                let other_r =tbank_repo.clone().transfer(data.clone(), tx_body.clone()).await;
-                                                if let Ok(other_status) = other_r{
-                                                    if other_status.contains("invocation successful"){
-                                                        bot.edit_message_text(chat.id, id, "The transfer has been done").await?;        
-                                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), true).await?;   
-                                                    }else{
-                                                        bot.edit_message_text(chat.id, id, "The transfer has been done except for your Micro Invest").await?;        
-                                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), true).await?;            
-                                                    }
-                                                }else{
-                                                    bot.edit_message_text(chat.id, id, "The transfer has been done except for your Micro Inve   st").await?;        
-                                                    TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), true).await?;            
-                                                }
-                                            }else{
-                                                bot.edit_message_text(chat.id, id, "The transfer has been done").await?;        
-                                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), true).await?;            
+                                let transition = state.transition(ConversationEvent::Confirm { step_up })?;
+                                Self::save_state(&store, chat.id, &transition.next).await?;
+                                match transition.effect {
+                                    Effect::Transfer(tx_body) => {
+                                        TelegramService::complete_transfer(bot, msg.clone(), id, &ctx, data, tx_body).await?;
+                                    }
+                                    Effect::RequestTransferOtp(_) => {
+                                        let request = RequestOTP {
+                                            service_name: "requestOTP".to_owned(),
+                                            user_id: data.user_id,
+                                            pin: data.pin,
+                                        };
+                                        let result = tbank_repo.request_otp(request).await
+                                            .and_then(|reply| reply.content.service_response.service_response_header.ensure_success());
+                                        match result {
+                                            Ok(()) => {
+                                                let keyboard = Self::make_keyboard(vec![CallbackAction::Cancel]);
+                                                let threshold = Money::new(ctx.config.step_up_threshold, HOME_CURRENCY);
+                                                let text = format!("Transfers above {} need a fresh OTP.\nPlease key in the OTP sent to you", threshold);
+                                                bot.edit_message_text(chat.id, id, text).reply_markup(keyboard).await?;
+                                            }
+                                            Err(e) => {
+                                                warn!("Step-up OTP request failed : {}", e);
+                                                Self::save_state(&store, chat.id, &ConversationState::Idle).await?;
+                                                bot.edit_message_text(chat.id, id, format!("The transfer could not be completed. {}", e)).await?;
+                                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;
                                             }
-        
-                                        }else{
-                                            bot.edit_message_text(chat.id, id, "The transfer has been done").await?;        
-                                            TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), true).await?;            
                                         }
                                     }
-                                    else{
-                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
-                                    }
-                                }else{
-                                    TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                    other => anyhow::bail!("Unexpected effect {:?} when confirming a transfer", other),
                                 }
                             }
                             Err(_) => {
-                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                             },
                         }
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Create" =>{
+                Some(CallbackAction::CreateInvestAccount) => {
                     // Delete user state to invalidate 
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id; 
                         bot.edit_message_text(chat.id, id, "Please wait we are creating your new account...").await?;
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        let result = ctx.session_service.credentials(chat.id).await;
                         match result {
-                            Ok(login_cred) => {
-                                let mut request_data:CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                request_data.service_name = "getCustomerDetails".to_owned();
+                            Ok(mut request_data) => {
                                 let full_key: String = format!("{}:{}",request_data.user_id, "MicroInvest");
-                                let result_details: Result<crate::models::TBankResponse<crate::models::customer::GetCustomerDetails>, anyhow::Error> = tbank_repo.clone().get_customer_details(request_data.clone()).await;
+                                let result_details = tbank_repo.clone().get_customer_details(request_data.clone()).await;
                                 match result_details{
                                     Ok(data) => {
-                                        request_data.service_name = "openDepositAccount".to_owned();
                                         request_data.pin = "1".to_owned();
                                         request_data.otp = "".to_owned();
-                                        request_data.user_id = data.content.service_response.cdm_customer.certificate.certificate_no.unwrap();
+                                        request_data.user_id = data.content.service_response.cdm_customer.certificate.certificate_no.context("Customer has no certificate number")?;
                                         let open_result = tbank_repo.clone().create_account(request_data.clone()).await;
                                         match open_result{
                                             Ok(account_id) => {
-                                                if account_id != "null"{
-                                                    let _ = redis_repo.clone().remove_data_in_redis(&full_key).await;
-                                                    let _ = redis_repo.clone().set_data_in_redis(&full_key, account_id.clone(), false).await;
-                                                    bot.edit_message_text(chat.id, id, format!("We have created: {}", account_id)).await?;
-                                                    let keyboard: InlineKeyboardMarkup = Self::make_keyboard(["2%".to_owned(), "5%".to_owned(), "10%".to_owned(), "Reselect account".to_owned()].to_vec());
-                                                    bot.edit_message_text(chat.id, id, "What percentage of a transaction would you like to be added to your chosen account?").reply_markup(keyboard).await?;
-                                                }else{
-                                                    bot.edit_message_text(chat.id, id, "Failed creating the account.").await?;
-                                                    TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
-                                                }
+                                                let _ = store.set(&full_key, account_id.clone()).await;
+                                                bot.edit_message_text(chat.id, id, format!("We have created: {}", account_id)).await?;
+                                                let keyboard: InlineKeyboardMarkup = Self::make_keyboard(vec![CallbackAction::InvestPercentage(2), CallbackAction::InvestPercentage(5), CallbackAction::InvestPercentage(10), CallbackAction::ReselectInvestAccount]);
+                                                bot.edit_message_text(chat.id, id, "What percentage of a transaction would you like to be added to your chosen account?").reply_markup(keyboard).await?;
                                             },
-                                            Err(_) => {
-                                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                            Err(e) => {
+                                                warn!("Something went wrong while creating account : {}", e);
+                                                bot.edit_message_text(chat.id, id, format!("Failed creating the account. {}", e)).await?;
+                                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, true).await?;            
                                             },
                                         }
                                     },
                                     Err(_) => {
-                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                        TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                                     },
                                 }
                             }
                             Err(_) => {
-                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                             },
                         }
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Logout" =>{
+                Some(CallbackAction::Logout) => {
                     // Delete user creds
                     if let Some(Message { id, chat, .. }) = q.message {
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let _ = redis_repo.clone().remove_data_in_redis(&full_key).await;
+                        let _ = ctx.session_service.destroy(chat.id).await;
+                        Self::advance_state(&store, chat.id, ConversationEvent::Reset).await?;
                         bot.delete_message(chat.id, id).await?;
                         TelegramService::send_start( bot, chat.id.to_string()).await?;
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Remove Account" =>{
+                Some(CallbackAction::RemoveInvestAccount) => {
                     // Delete user MicroInvest
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;   
-                         let full_key: String = format!("{}:{}", msg.chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                         let result = ctx.session_service.session(msg.chat.id).await;
                         match result {
-                            Ok(data_string) => {      
-                                let data:CustomerRequest = serde_json::from_str(&data_string).unwrap();
-                                let full_key: String = format!("{}:{}",data.user_id.to_string(), "MicroInvest");
-                                let _ = redis_repo.clone().remove_data_in_redis(&full_key).await;
-                                let full_key: String = format!("{}:{}",data.user_id.to_string(), "Percentage");
-                                let _ = redis_repo.clone().remove_data_in_redis(&full_key).await;
-                                let keyboard: InlineKeyboardMarkup = Self::make_keyboard(["Check Balance".to_owned(), "Transfer".to_owned(), "Logout".to_owned(), "Enable MicroInvest".to_owned(),].to_vec());
+                            Ok(data) => {
+                                let full_key: String = format!("{}:{}",data.user_id, "MicroInvest");
+                                let _ = store.delete(&full_key).await;
+                                let full_key: String = format!("{}:{}",data.user_id, "Percentage");
+                                let _ = store.delete(&full_key).await;
+                                let keyboard: InlineKeyboardMarkup = Self::make_keyboard(vec![CallbackAction::CheckBalance, CallbackAction::Transfer, CallbackAction::ManagePayees, CallbackAction::Logout, CallbackAction::EnableMicroInvest]);
                                 bot.edit_message_text(chat.id, id, "Hello! What banking service can I help you with today?").reply_markup(keyboard).await?;
                             },
                             Err(_) => {
@@ -764,30 +1030,22 @@ impl TelegramService {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Back" =>{
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::Back) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;    
-                        let add_ben_key: String = format!("{}:{}",msg.chat.id.to_string(), "AddBen");
-                        let action_key = format!("{}:{}", chat.id.to_string(), "action");
-                        let tx_key: String = format!("{}:{}", chat.id.to_string(), "Transfer");
-                        let _ = redis_repo.clone().remove_data_in_redis(&tx_key).await;
-                        let _ = redis_repo.clone().remove_data_in_redis(&action_key).await;
-                        let _ = redis_repo.clone().remove_data_in_redis(&add_ben_key).await;
-                        let full_key: String = format!("{}:{}", msg.chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        Self::advance_state(&store, chat.id, ConversationEvent::Reset).await?;
+                        let result = ctx.session_service.session(msg.chat.id).await;
                         match result {
-                            Ok(data_string) => {      
-                                let data:CustomerRequest = serde_json::from_str(&data_string).unwrap();
+                            Ok(data) => {
                                 let invest_key: String = format!("{}:{}",data.user_id, "MicroInvest");
                                 info!("{}", invest_key);
-                                let has_invest = match redis_repo.clone().get_data_from_redis(&invest_key).await{
-                                    Ok(acct) => if acct != ""{true}else{false},
-                                    Err(_) => false,
+                                let has_invest = match store.get(&invest_key).await{
+                                    Ok(Some(acct)) => !acct.is_empty(),
+                                    _ => false,
                                 };
-                                let invest_option = if has_invest{"Update MicroInvest".to_owned()}else{"Enable MicroInvest".to_owned()};
-                                let keyboard = Self::make_keyboard(["Check Balance".to_owned(), "Transfer".to_owned(), "Logout".to_owned(), invest_option].to_vec());
+                                let invest_option = if has_invest{CallbackAction::UpdateMicroInvest}else{CallbackAction::EnableMicroInvest};
+                                let keyboard = Self::make_keyboard(vec![CallbackAction::CheckBalance, CallbackAction::Transfer, CallbackAction::ManagePayees, CallbackAction::Logout, invest_option]);
                                 bot.edit_message_text(chat.id, id, "Hello! What banking service can I help you with today?").reply_markup(keyboard).await?;
                             },
                             Err(_) => {
@@ -799,74 +1057,62 @@ impl TelegramService {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Account" =>{
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::SelectInvestAccount { account_id: account_number }) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;    
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        let result = ctx.session_service.credentials(chat.id).await;
                         match result {
-                            Ok(login_cred) => {
-                                let data:CustomerRequest = serde_json::from_str(&login_cred).unwrap();
+                            Ok(data) => {
                                 let full_key: String = format!("{}:{}",data.user_id, "MicroInvest");
-                                let _ = redis_repo.clone().remove_data_in_redis(&full_key).await;
-                                let _ = redis_repo.clone().set_data_in_redis(&full_key, account_number.clone(), false).await;
+                                let _ = store.set(&full_key, account_number.clone()).await;
                                 bot.edit_message_text(chat.id, id, format!("You have chosen: {}", account_number)).await?;
-                                let keyboard: InlineKeyboardMarkup = Self::make_keyboard(["2%".to_owned(), "5%".to_owned(), "10%".to_owned(), "Reselect account".to_owned()].to_vec());
+                                let keyboard: InlineKeyboardMarkup = Self::make_keyboard(vec![CallbackAction::InvestPercentage(2), CallbackAction::InvestPercentage(5), CallbackAction::InvestPercentage(10), CallbackAction::ReselectInvestAccount]);
                                 bot.edit_message_text(chat.id, id, "What percentage of a transaction would you like to be added to your chosen account?").reply_markup(keyboard).await?;
                             }
                             Err(_) => {
-                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                             },
                         }
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Percentage" =>{
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::InvestPercentage(percentage_to_invest)) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;    
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        let result = ctx.session_service.credentials(chat.id).await;
                         match result {
-                            Ok(login_cred) => {
-                                let data:CustomerRequest = serde_json::from_str(&login_cred).unwrap();
+                            Ok(data) => {
                                 let full_key: String = format!("{}:{}",data.user_id, "Percentage");
-                                let _ = redis_repo.clone().remove_data_in_redis(&full_key).await;
-                                let _ = redis_repo.clone().set_data_in_redis(&full_key, percentage_to_invest.clone(), false).await;
-                                let keyboard = Self::make_keyboard(["Check Balance".to_owned(), "Transfer".to_owned(), "Logout".to_owned(), "Update MicroInvest".to_owned()].to_vec());
+                                let _ = store.set(&full_key, percentage_to_invest.to_string()).await;
+                                let keyboard = Self::make_keyboard(vec![CallbackAction::CheckBalance, CallbackAction::Transfer, CallbackAction::ManagePayees, CallbackAction::Logout, CallbackAction::UpdateMicroInvest]);
                                 bot.edit_message_text(chat.id, id, "Hello! What banking service can I help you with today?").reply_markup(keyboard).await?;
         
                             }
                             Err(_) => {
-                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                             },
                         }
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Enable MicroInvest" =>{
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::EnableMicroInvest) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;
                         bot.edit_message_text(chat.id, id, "Please wait ...").await?;
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        let result = ctx.session_service.credentials(chat.id).await;
                         match result {
-                            Ok(login_cred) => {
-                                let mut data:CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                data.service_name = "getCustomerAccounts".to_owned();
+                            Ok(data) => {
                                 let account_result = tbank_repo.get_customer_accounts(data).await;
                                 match account_result{
                                     Ok(accounts) => {
                                         if accounts.len() == 1 {
                                             let full_text = "You only have one account.\nPlease open a new account".to_owned();
-                                            let keyboard = Self::make_keyboard(["Create".to_owned(), "Back".to_owned()].to_vec());
+                                            let keyboard = Self::make_keyboard(vec![CallbackAction::CreateInvestAccount, CallbackAction::Back]);
                                             bot.edit_message_text(chat.id, id, full_text).reply_markup(keyboard).await?;
                                         }else{
                                             let mut full_text = "You have more than one account.\nPlease select one or would you like to create a new one?\n".to_owned();
@@ -875,46 +1121,42 @@ impl TelegramService {
 
                                             for one in accounts{
                                                 if one.product_id == "101"{
-                                                    let temp =format!("{} - {}%\n", one.account_id.to_string(), one.interest_rate);
-                                                    options.push( format!("Account: {}", one.account_id.clone()));
+                                                    let temp =format!("{} - {}%\n", one.account_id, one.interest_rate);
+                                                    options.push(CallbackAction::SelectInvestAccount { account_id: one.account_id.clone() });
                                                     full_text = format!("{}{}", full_text, temp);
                                                 }
                                             }
-                                            options.push("Create".to_string());
-                                            options.push("Back".to_string());
+                                            options.push(CallbackAction::CreateInvestAccount);
+                                            options.push(CallbackAction::Back);
                                             let keyboard = Self::make_keyboard(options);
                                             bot.edit_message_text(chat.id, id, full_text).reply_markup(keyboard).await?;
                                         }
                                     }
                                     Err(_) => {
-                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                        TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                                     },
                                 }
                             },
                             Err(_) => {
-                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                             },
                         }
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Update MicroInvest" =>{
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::UpdateMicroInvest) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;
                         bot.edit_message_text(chat.id, id, "Please wait ...").await?;
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        let result = ctx.session_service.credentials(chat.id).await;
                         match result {
-                            Ok(login_cred) => {
-                                let mut data:CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                data.service_name = "getCustomerAccounts".to_owned();
-                                let invest_key: String = format!("{}:{}",data.user_id.to_string(), "MicroInvest");
-                                let invest_account = match redis_repo.clone().get_data_from_redis(&invest_key).await{
-                                    Ok(r) => r,
-                                    Err(_) => "".to_string(),
+                            Ok(data) => {
+                                let invest_key: String = format!("{}:{}",data.user_id, "MicroInvest");
+                                let invest_account = match store.get(&invest_key).await{
+                                    Ok(Some(r)) => r,
+                                    _ => "".to_string(),
                                 };
                                 let account_result = tbank_repo.get_customer_accounts(data).await;
                                 match account_result{
@@ -926,67 +1168,71 @@ impl TelegramService {
 
                                             for one in accounts{
                                                 if one.product_id == "101"{
-                                                    let temp =format!("{} - {}%\n", one.account_id.to_string(), one.interest_rate);
+                                                    let temp =format!("{} - {}%\n", one.account_id, one.interest_rate);
                                                     full_text = format!("{}{}", full_text, temp);
 
                                                     if one.account_id != invest_account{
-                                                        options.push( format!("Account: {}", one.account_id.clone()));
+                                                        options.push(CallbackAction::SelectInvestAccount { account_id: one.account_id.clone() });
                                                     }
                                                 }
                                             }
-                                            options.push("Remove Account".to_string());
-                                            options.push("Back".to_string());
+                                            options.push(CallbackAction::RemoveInvestAccount);
+                                            options.push(CallbackAction::Back);
                                             let keyboard = Self::make_keyboard(options);
                                             bot.edit_message_text(chat.id, id, full_text).reply_markup(keyboard).await?;
                                         }
                                     }
                                     Err(_) => {
-                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                        TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                                     },
                                 }
                             },
                             Err(_) => {
-                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                             },
                         }
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
                 }
-                &"Check Balance" =>{
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::CheckBalance) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;
                         bot.edit_message_text(chat.id, id, "Please wait ...").await?;
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        let result = ctx.session_service.credentials(chat.id).await;
                         match result {
-                            Ok(login_cred) => {
-                                let mut data:CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                data.service_name = "getCustomerAccounts".to_owned();
+                            Ok(data) => {
                                 let account_result = tbank_repo.get_customer_accounts(data).await;
                                 match account_result{
                                     Ok(accounts) => {
                                         let mut full_text = "Your Account Balance is:\n".to_string();
                                         let mut vec_acc = vec![];
+                                        let totals = Money::totals(accounts.iter().map(AccountData::money));
 
                                         for one in accounts {
-                                            let temp =format!("{} - {}{}\n", one.account_id, one.currency, one.balance);
+                                            let temp =format!("{} - {}\n", one.account_id, one.money());
                                             full_text = format!("{}{}", full_text, temp);
-                                            vec_acc.push(format!("View Account {} Balance History", one.account_id));
+                                            vec_acc.push(CallbackAction::BalanceHistory { account_id: one.account_id.clone() });
+                                            vec_acc.push(CallbackAction::RecentTransactions { account_id: one.account_id.clone(), page: 1 });
+                                            vec_acc.push(CallbackAction::DownloadStatement { account_id: one.account_id });
                                         }
-                                        vec_acc.push("Back".to_owned());
+                                        // Balances in different currencies are never added together
+                                        full_text = format!("{}\nTotal:\n", full_text);
+                                        for total in totals {
+                                            full_text = format!("{}{}\n", full_text, total);
+                                        }
+                                        vec_acc.push(CallbackAction::Back);
                                         let keyboard = Self::make_keyboard(vec_acc);
                                         bot.edit_message_text(chat.id, id, full_text).reply_markup(keyboard).await?;
                                     }
                                     Err(_) => {
-                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                        TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                                     },
                                 }
                             },
                             Err(_) => {
-                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;            
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;            
                             },
                         }
                     } else if let Some(id) = q.inline_message_id {
@@ -994,53 +1240,140 @@ impl TelegramService {
                     }
 
                 }
-                &"Chart" =>{
-                    if q.message.is_some() {
-                        let msg = q.message.unwrap();
+                Some(CallbackAction::RecentTransactions { account_id: account_number, page }) => {
+                    if let Some(msg) = q.message {
                         let chat = msg.clone().chat;
                         let id = msg.clone().id;
                         bot.edit_message_text(chat.id, id, "Please wait ...").await?;
-                        let full_key: String = format!("{}:{}",chat.id.to_string(), "LoginCred");
-                        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+                        let result = ctx.session_service.credentials(chat.id).await;
                         match result {
-                            Ok(login_cred) => {
-                                let mut data: CustomerRequest = serde_json::from_str(&login_cred).unwrap();
-                                data.service_name = "getMonthlyBalanceTrend".to_owned();
+                            Ok(data) => {
+                                let (start_date, end_date) = Self::history_window(HISTORY_WINDOW_DAYS);
+                                let content = TransactionHistoryBody {
+                                    account_id: account_number.clone(),
+                                    start_date,
+                                    end_date,
+                                    num_records_per_page: TRANSACTIONS_PER_PAGE.to_string(),
+                                    page_no: page.to_string(),
+                                };
+                                let currency = Self::currency_of(&tbank_repo, data.clone(), &account_number).await?;
+                                match tbank_repo.get_transaction_history(data, content).await {
+                                    Ok(transactions) => {
+                                        let (full_text, keyboard) = Self::transactions_page(&account_number, currency, page, &transactions);
+                                        bot.edit_message_text(chat.id, id, full_text).reply_markup(keyboard).await?;
+                                    }
+                                    Err(e) => {
+                                        warn!("Something went wrong while getting transactions : {}", e);
+                                        TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;
+                                    }
+                                }
+                            },
+                            Err(_) => {
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;
+                            },
+                        }
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot, id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::DownloadStatement { account_id: account_number }) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        Self::advance_state(&store, chat.id, ConversationEvent::StartStatement { account_id: account_number.clone() }).await?;
+                        let keyboard = Self::make_keyboard(vec![CallbackAction::Back]);
+                        let text = format!("Which period should the statement for {} cover?\nKey it in as YYYY-MM-DD to YYYY-MM-DD, up to {} days.", account_number, MAX_STATEMENT_DAYS);
+                        bot.edit_message_text(chat.id, id, text).reply_markup(keyboard).await?;
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot, id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::BalanceHistory { account_id: account_number }) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        if ctx.session_service.credentials(chat.id).await.is_err() {
+                            TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;
+                            return Ok(());
+                        }
+                        let mut options: Vec<CallbackAction> = CHART_WINDOWS.iter()
+                            .map(|months| CallbackAction::ChartWindow { account_id: account_number.clone(), months: *months })
+                            .collect();
+                        options.push(CallbackAction::Back);
+                        let text = format!("How far back should the chart for {} go?", account_number);
+                        bot.edit_message_text(chat.id, id, text).reply_markup(Self::make_keyboard(options)).await?;
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot, id.to_string()).await?;
+                    }
+                }
+                Some(CallbackAction::ChartWindow { account_id: account_number, months }) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        bot.edit_message_text(chat.id, id, "Please wait ...").await?;
+                        let result = ctx.session_service.credentials(chat.id).await;
+                        match result {
+                            Ok(data) => {
                                 let content = HistoricalMonthlyBalanceBody {
                                     account_id: account_number.clone(),
-                                    //Default to 6
-                                    num_months: "6".to_string(),
+                                    num_months: months.to_string(),
                                 };
                                 let monthly_balance_result = tbank_repo.clone().get_monthly_balance_trend(data,  content).await;
                                 match monthly_balance_result{
                                     Ok(accounts) => {
-                                        let chart = tbank_repo.clone().get_balance_chart(accounts).await.unwrap();
-                                        let full_text = format!("{} balance over the past 6 months", account_number.clone());
+                                        let chart = ctx.chart_renderer.render(&accounts).await?;
+                                        let full_text = format!("{} balance over the past {} months\n\n{}", account_number.clone(), months, accounts.statistics().summary());
                                         bot.delete_message(chat.id, msg.id).await?;
                                         let png = InputFile::memory(chart);
                                         bot.send_photo(chat.id, png).await?;
-                                        let keyboard = Self::make_keyboard(["Back".to_owned()].to_vec());
-                                        bot.send_message(chat.id, full_text).reply_markup(keyboard).await?;
+                                        let mut options = vec![];
+                                        if ctx.chart_renderer.supports_comparison() {
+                                            options.push(CallbackAction::CompareAccounts { months });
+                                        }
+                                        options.push(CallbackAction::Back);
+                                        bot.send_message(chat.id, full_text).reply_markup(Self::make_keyboard(options)).await?;
                                     }
                                     Err(_) => {
-                                        TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;
+                                        TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;
                                     },
                                 }
                             },
                             Err(_) => {
-                                TelegramService::to_send_correct_start(bot, msg.clone(), redis_repo.clone(), false).await?;
+                                TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;
                             },
                         }
                     } else if let Some(id) = q.inline_message_id {
                         TelegramService::send_start( bot, id.to_string()).await?;
                     }
-
+                }
+                Some(CallbackAction::CompareAccounts { months }) => {
+                    if let Some(msg) = q.message {
+                        let chat = msg.clone().chat;
+                        let id = msg.clone().id;
+                        bot.edit_message_text(chat.id, id, "Please wait ...").await?;
+                        let Ok(data) = ctx.session_service.credentials(chat.id).await else {
+                            TelegramService::to_send_correct_start(bot, msg.clone(), &ctx, false).await?;
+                            return Ok(());
+                        };
+                        let accounts = tbank_repo.get_customer_accounts(data.clone()).await?;
+                        let account_ids: Vec<String> = accounts.into_iter().map(|one| one.account_id).collect();
+                        let trends = Self::balance_trends(&tbank_repo, data, &account_ids, months).await?;
+                        let chart = ctx.chart_renderer.render_comparison(&trends).await?;
+                        let mut full_text = format!("All accounts over the past {} months", months);
+                        for one in &trends {
+                            full_text = format!("{}\n\nAccount {}\n{}", full_text, one.account_id, one.trend.statistics().summary());
+                        }
+                        bot.delete_message(chat.id, msg.id).await?;
+                        bot.send_photo(chat.id, InputFile::memory(chart)).await?;
+                        bot.send_message(chat.id, full_text).reply_markup(Self::make_keyboard(vec![CallbackAction::Back])).await?;
+                    } else if let Some(id) = q.inline_message_id {
+                        TelegramService::send_start( bot, id.to_string()).await?;
+                    }
                 }
                 _ => {
                     //Invalidate user state
                     if let Some(Message { id, chat, .. }) = q.message {
-                        let action_key = format!("{}:{}", chat.id.to_string(), "action");
-                        let _ = redis_repo.clone().remove_data_in_redis(&action_key).await;
+                        Self::advance_state(&store, chat.id, ConversationEvent::Reset).await?;
                         bot.delete_message(chat.id, id).await?;
                         TelegramService::send_start( bot, chat.id.to_string()).await?;
                     } else if let Some(id) = q.inline_message_id {
@@ -1054,21 +1387,233 @@ impl TelegramService {
     }
 
 
-    async fn to_send_correct_start(bot:Bot, msg: Message, redis_repo:RedisRepository, is_start: bool) -> ResponseResult<()> {
-        let full_key: String = format!("{}:{}", msg.chat.id.to_string(), "LoginCred");
-        let result = redis_repo.clone().get_data_from_redis(&full_key).await;
+    /// Runs a confirmed transfer, then tops up the MicroInvest account if one is set up.
+    /// `id` is the message that gets the outcome.
+    async fn complete_transfer(bot: Bot, msg: Message, id: MessageId, ctx: &AppContext, data: CustomerRequest, mut tx_body: TransferBody) -> anyhow::Result<()> {
+        match ctx.tbank_repository.clone().transfer(data.clone(), tx_body.clone()).await {
+            Ok(()) => {
+                let text = match Self::micro_invest_for(ctx, &data, &tx_body).await {
+                    Ok(Some((acct, percentage))) => {
+                        tx_body.account_to = acct;
+                        tx_body.transaction_amount = tx_body.transaction_amount.share(percentage);
+                        tx_body.narrative = "Micro-Invest".to_owned();
+                        match ctx.tbank_repository.clone().transfer(data.clone(), tx_body.clone()).await {
+                            Ok(()) => "The transfer has been done".to_owned(),
+                            Err(e) => {
+                                warn!("Micro-Invest transfer failed : {}", e);
+                                format!("The transfer has been done except for your Micro Invest. {}", e)
+                            }
+                        }
+                    }
+                    Ok(None) => "The transfer has been done".to_owned(),
+                    Err(e) => {
+                        warn!("Micro-Invest transfer failed : {}", e);
+                        format!("The transfer has been done except for your Micro Invest. {}", e)
+                    }
+                };
+                bot.edit_message_text(msg.chat.id, id, text).await?;
+                TelegramService::to_send_correct_start(bot, msg.clone(), ctx, true).await?;
+            }
+            Err(e) => {
+                warn!("Transfer failed : {}", e);
+                bot.edit_message_text(msg.chat.id, id, format!("The transfer could not be completed. {}", e)).await?;
+                TelegramService::to_send_correct_start(bot, msg.clone(), ctx, true).await?;            
+            }
+        }
+        Ok(())
+    }
+
+    /// The MicroInvest account and percentage to top up after `tx_body`, if one is set up.
+    /// Top-ups only follow transfers in the MicroInvest account's own currency.
+    async fn micro_invest_for(ctx: &AppContext, data: &CustomerRequest, tx_body: &TransferBody) -> anyhow::Result<Option<(String, u8)>> {
+        let invest_key: String = format!("{}:{}",data.user_id, "MicroInvest");
+        let acct = match ctx.state_store.get(&invest_key).await{
+            Ok(Some(acct)) if !acct.is_empty() && acct != tx_body.account_from => acct,
+            _ => return Ok(None),
+        };
+        let accounts = ctx.tbank_repository.get_customer_accounts(data.clone()).await?;
+        if !accounts.iter().any(|one| one.account_id == acct && one.currency == tx_body.transaction_amount.currency) {
+            return Ok(None);
+        }
+        let percent_key: String = format!("{}:{}",data.user_id, "Percentage");
+        let percentage = ctx.state_store.get(&percent_key).await?
+            .filter(|percent| !percent.is_empty())
+            .context("MicroInvest percentage is missing")?
+            .parse::<u8>()?;
+        Ok(Some((acct, percentage)))
+    }
+
+    /// Currency of one of the customer's accounts or payees. TBank lists both in the home currency when it says nothing.
+    async fn currency_of(tbank_repo: &TBankRepository, data: CustomerRequest, account_id: &str) -> anyhow::Result<Currency> {
+        let accounts = tbank_repo.get_customer_accounts(data.clone()).await?;
+        if let Some(account) = accounts.iter().find(|one| one.account_id == account_id) {
+            return Ok(account.currency);
+        }
+        let payees = tbank_repo.get_beneficiaries(data, BeneficiaryEnum::OTHER).await?;
+        Ok(payees.iter().find(|payee| payee.account_id == account_id).map(|payee| payee.currency).unwrap_or(HOME_CURRENCY))
+    }
+
+    /// The summary shown before a transfer is confirmed, with the source account and what it will be debited.
+    /// A source in another currency is refused, as TBank documents no conversion for `creditTransfer`.
+    async fn transfer_summary(ctx: &AppContext, data: CustomerRequest, tx_body: &TransferBody) -> anyhow::Result<(String, AccountData, Money)> {
+        let accounts = ctx.tbank_repository.get_customer_accounts(data.clone()).await?;
+        let source = accounts.into_iter()
+            .find(|one| one.account_id == tx_body.account_from)
+            .context("The source account does not belong to the customer")?;
+        let amount = tx_body.transaction_amount;
+        if source.currency != amount.currency {
+            return Err(TBankError::UnsupportedConversion { from: source.currency, to: amount.currency }.into());
+        }
+        let mut summary = format!("SUMMARY\nTransferring to: {}\nTransferring from {}\nAmount: {}", tx_body.account_to, tx_body.account_from, amount);
+        let mut total = amount;
+        if let Some((_, percentage)) = Self::micro_invest_for(ctx, &data, tx_body).await? {
+            let to_invest = amount.share(percentage);
+            summary = format!("{}\nMicro-Invest amount: {}", summary, to_invest);
+            total = amount.checked_add(to_invest).context("Micro-Invest share is in another currency")?;
+        }
+        Ok((summary, source, total))
+    }
+
+    /// Whether `amount` is over the step-up threshold, which is in the home currency.
+    /// TBank documents no exchange rate, so any other currency is treated as large.
+    fn needs_step_up(ctx: &AppContext, amount: Money) -> bool {
+        amount.currency != HOME_CURRENCY || amount.amount > ctx.config.step_up_threshold
+    }
+
+    /// Start and end dates, in TBank's format, of the `days` up to now.
+    fn history_window(days: i64) -> (String, String) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+        let end = DateTime::from_timestamp(now as i64, 0).unwrap_or_default();
+        let start = end - Duration::days(days);
+        let format = |date: DateTime<Utc>| date.format("%Y-%m-%d %H:%M:%S").to_string();
+        (format(start), format(end))
+    }
+
+    /// One page of Recent Transactions, with buttons to the neighbouring pages.
+    /// Rows without a currency of their own are in the account's `currency`.
+    fn transactions_page(account_id: &str, currency: Currency, page: u32, transactions: &[TransactionDetail]) -> (String, InlineKeyboardMarkup) {
+        let mut full_text = format!("Recent transactions for {} (page {})\n", account_id, page);
+        if transactions.is_empty() {
+            full_text = format!("{}\nNo transactions in the past {} days.\n", full_text, HISTORY_WINDOW_DAYS);
+        }
+        for one in transactions {
+            let currency = one.currency_or(currency);
+            let narrative = one.narrative.clone().filter(|narrative| !narrative.is_empty()).unwrap_or_else(|| "Transfer".to_owned());
+            let balance = one.balance_for(account_id).unwrap_or("-");
+            let amount = match one.amount_for(account_id) {
+                Some(amount) => format!("{:+.2}", amount),
+                None => {
+                    warn!("Transaction {} has an amount that is not a number : {:?}", one.transaction_id, one.transaction_amount);
+                    one.transaction_amount.trim().to_owned()
+                }
+            };
+            let temp = format!("\n{} {}\n{} {} | Balance: {} {}\n", one.date(), narrative, amount, currency, balance, currency);
+            full_text = format!("{}{}", full_text, temp);
+        }
+
+        let mut vec_kb: Vec<(String, CallbackAction)> = vec![];
+        if page > 1 {
+            vec_kb.push(("Previous".to_owned(), CallbackAction::RecentTransactions { account_id: account_id.to_owned(), page: page - 1 }));
+        }
+        // A full page may have more behind it
+        if transactions.len() as u32 == TRANSACTIONS_PER_PAGE {
+            vec_kb.push(("Next".to_owned(), CallbackAction::RecentTransactions { account_id: account_id.to_owned(), page: page + 1 }));
+        }
+        vec_kb.push((CallbackAction::Back.label(), CallbackAction::Back));
+        (full_text, Self::make_labelled_keyboard(vec_kb))
+    }
+
+    /// Tells the chat its login ran out and sends it back to the start.
+    async fn end_expired_session(bot: &Bot, chat_id: ChatId, ctx: &AppContext) -> anyhow::Result<bool> {
+        if !ctx.session_service.expire_if_stale(chat_id).await? {
+            return Ok(false);
+        }
+        Self::save_state(&ctx.state_store, chat_id, &ConversationState::Idle).await?;
+        bot.send_message(chat_id, SessionError::Expired.to_string()).await?;
+        TelegramService::send_start(bot.clone(), chat_id.to_string()).await?;
+        Ok(true)
+    }
+
+    async fn load_state(store: &Arc<dyn StateStore>, chat_id: ChatId) -> anyhow::Result<ConversationState> {
+        let state_key = format!("{}:{}", chat_id, "conversation");
+        match store.get(&state_key).await? {
+            Some(state) => Ok(serde_json::from_str(&state)?),
+            // Nothing stored (or it expired) means the chat is not in a flow
+            None => Ok(ConversationState::Idle),
+        }
+    }
+
+    /// Like `load_state`, but leaves the chat Idle so concurrent updates cannot act on the same state.
+    async fn take_state(store: &Arc<dyn StateStore>, chat_id: ChatId) -> anyhow::Result<ConversationState> {
+        let state_key = format!("{}:{}", chat_id, "conversation");
+        match store.get_and_delete(&state_key).await? {
+            Some(state) => Ok(serde_json::from_str(&state)?),
+            None => Ok(ConversationState::Idle),
+        }
+    }
+
+    async fn save_state(store: &Arc<dyn StateStore>, chat_id: ChatId, state: &ConversationState) -> anyhow::Result<()> {
+        let state_key = format!("{}:{}", chat_id, "conversation");
+        if *state == ConversationState::Idle {
+            return store.delete(&state_key).await;
+        }
+        let value = serde_json::to_string(state)?;
+        if state.expires() {
+            return store.set_with_ttl(&state_key, value, STEP_TTL_SECS).await;
+        }
+        store.set(&state_key, value).await
+    }
+
+    /// Fetches every account's trend at once rather than one after another, in the order given.
+    async fn balance_trends(tbank_repo: &TBankRepository, data: CustomerRequest, account_ids: &[String], months: u8) -> anyhow::Result<Vec<AccountTrend>> {
+        let requests = account_ids.iter().map(|account_id| {
+            let content = HistoricalMonthlyBalanceBody {
+                account_id: account_id.clone(),
+                num_months: months.to_string(),
+            };
+            let data = data.clone();
+            async move {
+                let trend = tbank_repo.get_monthly_balance_trend(data, content).await;
+                trend.map(|trend| AccountTrend { account_id: account_id.clone(), trend })
+            }
+        });
+        Ok(try_join_all(requests).await?)
+    }
+
+    /// Every beneficiary of the customer, third parties first.
+    async fn payees(tbank_repo: &TBankRepository, data: CustomerRequest) -> anyhow::Result<Vec<Beneficiary>> {
+        let mut payees = tbank_repo.get_beneficiaries(data.clone(), BeneficiaryEnum::OTHER).await?;
+        payees.extend(tbank_repo.get_beneficiaries(data, BeneficiaryEnum::OWN).await?);
+        Ok(payees)
+    }
+
+    async fn find_payee(tbank_repo: &TBankRepository, data: CustomerRequest, beneficiary_id: &str) -> anyhow::Result<Option<Beneficiary>> {
+        let payees = Self::payees(tbank_repo, data).await?;
+        Ok(payees.into_iter().find(|payee| payee.beneficiary_id == beneficiary_id))
+    }
+
+    /// Applies `event` to the stored state and persists the result.
+    async fn advance_state(store: &Arc<dyn StateStore>, chat_id: ChatId, event: ConversationEvent) -> anyhow::Result<Transition> {
+        let state = Self::load_state(store, chat_id).await?;
+        let transition = state.transition(event)?;
+        Self::save_state(store, chat_id, &transition.next).await?;
+        Ok(transition)
+    }
+
+    async fn to_send_correct_start(bot:Bot, msg: Message, ctx: &AppContext, is_start: bool) -> anyhow::Result<()> {
+        let store = ctx.state_store.clone();
+        let result = ctx.session_service.session(msg.chat.id).await;
         match result {
-            Ok(data_string) => {
+            Ok(data) => {
                 if !is_start{
                     bot.delete_message(msg.chat.id, msg.id).await?;
                     bot.send_message(msg.chat.id, "Sorry something went wrong. Please try again.").await?;
                 }
-                let data:CustomerRequest = serde_json::from_str(&data_string).unwrap();
                 let invest_key: String = format!("{}:{}",data.user_id, "MicroInvest");
                 info!("{}", invest_key);
-                let has_invest = match redis_repo.clone().get_data_from_redis(&invest_key).await{
-                    Ok(acct) => if acct != ""{true}else{false},
-                    Err(_) => false,
+                let has_invest = match store.get(&invest_key).await{
+                    Ok(Some(acct)) => !acct.is_empty(),
+                    _ => false,
                 };
                 TelegramService::send_logged_in_user_start( bot, msg.chat.id.to_string(), has_invest).await?; 
             },
@@ -1083,15 +1628,636 @@ impl TelegramService {
     }
 
     async fn send_start(bot:Bot, id:String) -> ResponseResult<()> {
-        let keyboard = Self::make_keyboard(["Login".to_owned()].to_vec());
+        let keyboard = Self::make_keyboard(vec![CallbackAction::Login, CallbackAction::Register]);
         bot.send_message(id, "Welcome to TBANK Bot! How can I help you today?").reply_markup(keyboard).await?;
         Ok(())
     }
 
     async fn send_logged_in_user_start(bot:Bot, id:String, has_invest:bool) -> ResponseResult<()> {
-        let invest_option = if has_invest{"Update MicroInvest".to_owned()}else{"Enable MicroInvest".to_owned()};
-        let keyboard = Self::make_keyboard(["Check Balance".to_owned(), "Transfer".to_owned(), "Logout".to_owned(), invest_option].to_vec());
+        let invest_option = if has_invest{CallbackAction::UpdateMicroInvest}else{CallbackAction::EnableMicroInvest};
+        let keyboard = Self::make_keyboard(vec![CallbackAction::CheckBalance, CallbackAction::Transfer, CallbackAction::ManagePayees, CallbackAction::Logout, invest_option]);
         bot.send_message(id, "Hello! What banking service can I help you with today?").reply_markup(keyboard).await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rust_decimal::Decimal;
+    use crate::config::AppConfig;
+    use crate::enums::chart_backend::ChartBackend;
+    use crate::models::registration::RegistrationField;
+    use crate::testing::context::{test_config, test_context};
+    use crate::testing::fake_telegram::{BotCall, FakeTelegram};
+    use crate::testing::mock_tbank::{MockTBank, MOCK_OTP};
+    use super::*;
+
+    pub(crate) const CHAT: ChatId = ChatId(4242);
+    const MENU: &str = "Hello! What banking service can I help you with today?";
+
+    /// Drives the bot the way a user in one chat would, against a fake Bot API and a mock TBank.
+    pub(crate) struct Script {
+        telegram: FakeTelegram,
+        bot: Bot,
+        pub(crate) ctx: AppContext,
+    }
+
+    impl Script {
+        pub(crate) async fn new(tbank: &MockTBank) -> Self {
+            Self::configured(tbank, |_| {}).await
+        }
+
+        /// A script whose chat is on the admin list.
+        async fn admin(tbank: &MockTBank) -> Self {
+            Self::configured(tbank, |config| config.admin_chat_ids = CHAT.to_string()).await
+        }
+
+        async fn configured(tbank: &MockTBank, configure: impl FnOnce(&mut AppConfig)) -> Self {
+            let mut config = test_config(&format!("http://{}/", tbank.spawn()));
+            configure(&mut config);
+            let telegram = FakeTelegram::new();
+            Self {
+                bot: telegram.spawn(),
+                telegram,
+                ctx: test_context(config).await,
+            }
+        }
+
+        /// Types `text` into the chat and returns what the bot called in response.
+        pub(crate) async fn send(&self, text: &str) -> Vec<BotCall> {
+            let msg = self.telegram.user_message(CHAT, text);
+            Box::pin(TelegramService::message_handler(self.bot.clone(), msg, self.telegram.me(), self.ctx.clone())).await.unwrap();
+            self.telegram.take_calls()
+        }
+
+        /// Taps the button for `action` on the newest message still showing it.
+        pub(crate) async fn tap(&self, action: CallbackAction) -> Vec<BotCall> {
+            let data = action.encode().unwrap();
+            let message_id = self.telegram.message_with_button(CHAT, &data)
+                .unwrap_or_else(|| panic!("no button for {:?} on screen", action));
+            let q = self.telegram.callback(CHAT, message_id, &data);
+            Box::pin(TelegramService::callback_handler(self.bot.clone(), q, self.ctx.clone())).await.unwrap();
+            self.telegram.take_calls()
+        }
+
+        async fn login(&self, user_id: &str, pin: &str) -> Vec<BotCall> {
+            self.send("/start").await;
+            self.tap(CallbackAction::Login).await;
+            self.send(user_id).await;
+            self.send(pin).await;
+            self.send(MOCK_OTP).await
+        }
+    }
+
+    /// Text the chat is left looking at after the bot's last message or edit.
+    fn last_text(calls: &[BotCall]) -> String {
+        calls.iter()
+            .rev()
+            .find(|call| call.method == "sendMessage" || call.method == "editMessageText")
+            .and_then(|call| call.text.clone())
+            .unwrap_or_default()
+    }
+
+    fn texts(calls: &[BotCall]) -> Vec<String> {
+        calls.iter().filter_map(|call| call.text.clone()).collect()
+    }
+
+    pub(crate) fn bank() -> MockTBank {
+        MockTBank::new()
+            .customer("alice", "123456")
+            .account("alice", "100", "500.00")
+            .customer("bob", "654321")
+            .account("bob", "200", "10.00")
+            .beneficiary("alice", "200", "Bob")
+    }
+
+    #[tokio::test]
+    async fn login_check_balance_transfer_and_confirm() {
+        let tbank = bank();
+        let script = Script::new(&tbank).await;
+
+        let calls = script.send("/start").await;
+        assert_eq!(last_text(&calls), "Welcome to TBANK Bot! How can I help you today?");
+        assert_eq!(calls[0].buttons, vec![("Login".to_owned(), "1:li".to_owned()), ("Register".to_owned(), "1:rg".to_owned())]);
+
+        assert_eq!(last_text(&script.tap(CallbackAction::Login).await), "Please key in your username");
+        assert_eq!(last_text(&script.send("alice").await), "Please key in your PIN");
+
+        let calls = script.send("123456").await;
+        assert_eq!(last_text(&calls), "Please key in your OTP");
+        // The PIN the user typed does not stay in the chat
+        let pin_message = MessageId(calls[0].message_id.unwrap());
+        assert!(calls.iter().any(|call| call.method == "deleteMessage" && call.message_id == Some(pin_message.0)));
+        assert_eq!(script.telegram.text_of(CHAT, pin_message), None);
+
+        assert_eq!(last_text(&script.send(MOCK_OTP).await), MENU);
+
+        let balance = last_text(&script.tap(CallbackAction::CheckBalance).await);
+        assert!(balance.starts_with("Your Account Balance is:\n100 - SGD 500.00"), "{}", balance);
+        assert_eq!(last_text(&script.tap(CallbackAction::Back).await), MENU);
+
+        let calls = script.tap(CallbackAction::Transfer).await;
+        assert_eq!(last_text(&calls), "Who would you like to transfer to?");
+        let calls = script.tap(CallbackAction::TransferOther).await;
+        assert_eq!(last_text(&calls), "Where would you like to transfer to?");
+        assert_eq!(calls.last().unwrap().buttons[0].0, "Transfer To Bob 200");
+
+        script.tap(CallbackAction::TransferTo { account_id: "200".to_owned() }).await;
+        assert_eq!(last_text(&script.send("50").await), "Which account would you like to use?");
+
+        let summary = last_text(&script.tap(CallbackAction::TransferFrom { account_id: "100".to_owned() }).await);
+        assert_eq!(summary, "SUMMARY\nTransferring to: 200\nTransferring from 100\nAmount: SGD 50.00");
+
+        let calls = script.tap(CallbackAction::Confirm).await;
+        assert!(texts(&calls).contains(&"The transfer has been done".to_owned()), "{:?}", calls);
+        assert_eq!(last_text(&calls), MENU);
+        assert_eq!(tbank.balance("100"), Some(Decimal::new(45000, 2)));
+        assert_eq!(tbank.balance("200"), Some(Decimal::new(6000, 2)));
+    }
+
+    #[tokio::test]
+    async fn large_transfers_ask_for_a_fresh_otp_in_chat() {
+        let tbank = bank().account("alice", "101", "5000.00");
+        let script = Script::new(&tbank).await;
+        assert_eq!(last_text(&script.login("alice", "123456").await), MENU);
+
+        script.tap(CallbackAction::Transfer).await;
+        script.tap(CallbackAction::TransferOther).await;
+        script.tap(CallbackAction::TransferTo { account_id: "200".to_owned() }).await;
+        script.send("$2,000").await;
+        script.tap(CallbackAction::TransferFrom { account_id: "101".to_owned() }).await;
+
+        let prompt = last_text(&script.tap(CallbackAction::Confirm).await);
+        assert!(prompt.starts_with("Transfers above SGD 1,000.00 need a fresh OTP."), "{}", prompt);
+        assert!(tbank.transfers().is_empty());
+
+        let calls = script.send(MOCK_OTP).await;
+        assert!(texts(&calls).contains(&"The transfer has been done".to_owned()), "{:?}", calls);
+        assert_eq!(tbank.balance("101"), Some(Decimal::new(300000, 2)));
+    }
+
+    #[tokio::test]
+    async fn a_transfer_that_cannot_be_set_up_goes_back_to_the_menu() {
+        let tbank = bank();
+        let script = Script::new(&tbank).await;
+        script.login("alice", "123456").await;
+        script.tap(CallbackAction::Transfer).await;
+        script.tap(CallbackAction::TransferOther).await;
+        script.tap(CallbackAction::TransferTo { account_id: "200".to_owned() }).await;
+        script.send("50").await;
+
+        // A stale or forged button naming an account that is not the customer's
+        let shown = CallbackAction::TransferFrom { account_id: "100".to_owned() }.encode().unwrap();
+        let message_id = script.telegram.message_with_button(CHAT, &shown).unwrap();
+        let forged = CallbackAction::TransferFrom { account_id: "200".to_owned() }.encode().unwrap();
+        let q = script.telegram.callback(CHAT, message_id, &forged);
+        Box::pin(TelegramService::callback_handler(script.bot.clone(), q, script.ctx.clone())).await.unwrap();
+        let calls = script.telegram.take_calls();
+
+        assert!(texts(&calls).contains(&"The transfer could not be set up. The source account does not belong to the customer".to_owned()), "{:?}", calls);
+        assert_eq!(last_text(&calls), MENU);
+        assert_eq!(ConversationState::Idle, TelegramService::load_state(&script.ctx.state_store, CHAT).await.unwrap());
+        assert!(tbank.transfers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn transfers_between_currencies_are_refused() {
+        let tbank = bank()
+            .account_in("alice", "102", "80.00", "USD")
+            .customer("carol", "111111")
+            .account_in("carol", "300", "0", "JPY")
+            .beneficiary("alice", "300", "Carol");
+        let script = Script::new(&tbank).await;
+        script.login("alice", "123456").await;
+
+        let balance = last_text(&script.tap(CallbackAction::CheckBalance).await);
+        assert!(balance.contains("102 - USD 80.00\n"), "{}", balance);
+        assert!(balance.ends_with("Total:\nSGD 500.00\nUSD 80.00\n"), "{}", balance);
+        script.tap(CallbackAction::Back).await;
+
+        // Only accounts in the payee's currency are offered
+        script.tap(CallbackAction::Transfer).await;
+        script.tap(CallbackAction::TransferOther).await;
+        script.tap(CallbackAction::TransferTo { account_id: "200".to_owned() }).await;
+        let calls = script.send("10").await;
+        let labels: Vec<_> = calls.iter().flat_map(|call| &call.buttons).map(|(label, _)| label.as_str()).collect();
+        assert!(labels.contains(&"Transfer From 100 (SGD 500.00)"), "{:?}", labels);
+        assert!(!labels.iter().any(|label| label.starts_with("Transfer From 102")), "{:?}", labels);
+        // A stale button for the USD account is refused rather than sent to TBank
+        let shown = CallbackAction::TransferFrom { account_id: "100".to_owned() }.encode().unwrap();
+        let message_id = script.telegram.message_with_button(CHAT, &shown).unwrap();
+        let stale = CallbackAction::TransferFrom { account_id: "102".to_owned() }.encode().unwrap();
+        let q = script.telegram.callback(CHAT, message_id, &stale);
+        Box::pin(TelegramService::callback_handler(script.bot.clone(), q, script.ctx.clone())).await.unwrap();
+        let calls = script.telegram.take_calls();
+        assert!(texts(&calls).contains(&"The transfer could not be set up. Transfers between USD and SGD accounts are not supported.".to_owned()), "{:?}", calls);
+        assert_eq!(last_text(&calls), MENU);
+
+        script.tap(CallbackAction::Transfer).await;
+        script.tap(CallbackAction::TransferOther).await;
+        let prompt = last_text(&script.tap(CallbackAction::TransferTo { account_id: "300".to_owned() }).await);
+        assert_eq!(prompt, "How much do you want to transfer?\nKey in the amount in JPY that account 300 should receive.");
+        let calls = script.send("1,500").await;
+        assert!(texts(&calls).contains(&"None of your JPY accounts has enough balance for this transfer.".to_owned()), "{:?}", calls);
+        assert!(tbank.transfers().is_empty());
+        assert_eq!(tbank.balance("102"), Some(Decimal::new(8000, 2)));
+    }
+
+    #[tokio::test]
+    async fn admins_deposit_and_withdraw_cash() {
+        let tbank = bank().teller("teller", "4321");
+        let script = Script::admin(&tbank).await;
+
+        assert_eq!(last_text(&script.send("/deposit").await), "Which account is the deposit for?");
+        assert_eq!(last_text(&script.send("200").await), "Which currency is account 200 in? Key in its code, such as SGD.");
+        assert_eq!(last_text(&script.send("SGD").await), "How much cash is being deposited? Key in the amount in SGD.");
+        assert_eq!(last_text(&script.send("40").await), "Please confirm the cash deposit\nAccount: 200\nAmount: SGD 40.00\nNarrative: Cash deposit");
+        let receipt = texts(&script.tap(CallbackAction::ConfirmCash).await);
+        assert!(receipt.iter().any(|text| text.starts_with("Cash deposit of SGD 40.00 for account 200 is done.\nBalance before: SGD 10.00\nBalance after: SGD 50.00")), "{:?}", receipt);
+        assert_eq!(tbank.balance("200"), Some(Decimal::new(5000, 2)));
+
+        script.send("/withdraw").await;
+        script.send("200").await;
+        script.send("SGD").await;
+        script.send("80").await;
+        let refusal = texts(&script.tap(CallbackAction::ConfirmCash).await);
+        assert!(refusal.contains(&"The cash withdrawal could not be completed. There are insufficient funds in the account.".to_owned()), "{:?}", refusal);
+        assert_eq!(tbank.balance("200"), Some(Decimal::new(5000, 2)));
+    }
+
+    #[tokio::test]
+    async fn cash_is_counted_in_the_currency_the_teller_gives() {
+        // TBank has no teller lookup for an account's currency, so the mock serves none either
+        let tbank = bank().account_in("bob", "201", "5.00", "USD").teller("teller", "4321");
+        let script = Script::admin(&tbank).await;
+
+        script.send("/deposit").await;
+        assert_eq!(last_text(&script.send("201").await), "Which currency is account 201 in? Key in its code, such as SGD.");
+        assert_eq!(last_text(&script.send("usd").await), "How much cash is being deposited? Key in the amount in USD.");
+        assert_eq!(last_text(&script.send("40").await), "Please confirm the cash deposit\nAccount: 201\nAmount: USD 40.00\nNarrative: Cash deposit");
+        let receipt = texts(&script.tap(CallbackAction::ConfirmCash).await);
+        assert!(receipt.iter().any(|text| text.starts_with("Cash deposit of USD 40.00 for account 201 is done.\nBalance before: USD 5.00\nBalance after: USD 45.00")), "{:?}", receipt);
+
+        script.send("/withdraw").await;
+        script.send("201").await;
+        let calls = script.send("dollars").await;
+        assert!(texts(&calls).contains(&"Sorry something went wrong. Please try again.".to_owned()), "{:?}", calls);
+        assert_eq!(tbank.balance("201"), Some(Decimal::new(4500, 2)));
+        assert_eq!(ConversationState::Idle, TelegramService::load_state(&script.ctx.state_store, CHAT).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn cash_commands_are_hidden_from_customers() {
+        let tbank = bank().teller("teller", "4321");
+        let script = Script::new(&tbank).await;
+        assert_eq!(last_text(&script.send("/deposit").await), "Command not found!");
+        assert_eq!(last_text(&script.send("/withdraw").await), "Command not found!");
+        assert_eq!(ConversationState::Idle, TelegramService::load_state(&script.ctx.state_store, CHAT).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn new_customers_register_and_log_in() {
+        let tbank = bank();
+        let script = Script::new(&tbank).await;
+        script.send("/start").await;
+        let first = last_text(&script.tap(CallbackAction::Register).await);
+        assert!(first.ends_with(RegistrationField::Name.prompt()), "{}", first);
+
+        assert_eq!(last_text(&script.send("Lim Carol").await), RegistrationField::IcNumber.prompt());
+        let calls = script.send("S12345").await;
+        assert_eq!(last_text(&calls), format!("That is not a valid NRIC or FIN number.\n{}", RegistrationField::IcNumber.prompt()));
+        assert!(calls.iter().filter(|call| call.method == "deleteMessage").count() == 2);
+        for answer in ["S7654321Z", "1985-02-28", "10 Anson Road", "079903", "8765 4321"] {
+            script.send(answer).await;
+        }
+        let review = last_text(&script.send("carol_lim").await);
+        assert_eq!(review, "Please check your details\nName: Lim Carol\nNRIC/FIN: S7654321Z\nDate of birth: 1985-02-28\nAddress: 10 Anson Road, Singapore 079903\nMobile: +65 87654321\nUser ID: carol_lim");
+
+        let calls = script.tap(CallbackAction::ConfirmRegistration).await;
+        let welcome = texts(&calls).into_iter().find(|text| text.starts_with("Welcome to TBank!")).unwrap();
+        let account_id = &tbank.accounts_of("carol_lim")[0].account_id;
+        assert!(welcome.contains(&format!("Account ID: {}\nUser ID: carol_lim", account_id)), "{}", welcome);
+        assert!(welcome.ends_with("Please collect it from TBank before you log in."), "{}", welcome);
+        let pin = tbank.pin_of("carol_lim").unwrap();
+        assert!(!texts(&calls).iter().any(|text| text.contains(&pin)), "{:?}", calls);
+
+        assert_eq!(last_text(&script.login("carol_lim", &pin).await), MENU);
+    }
+
+    #[tokio::test]
+    async fn registration_reports_a_taken_user_id() {
+        let script = Script::new(&bank()).await;
+        script.send("/start").await;
+        script.tap(CallbackAction::Register).await;
+        for answer in ["Lim Carol", "S7654321Z", "1985-02-28", "10 Anson Road", "079903", "87654321", "alice"] {
+            script.send(answer).await;
+        }
+        // "alice" is too short to be a user ID, so the form asks again
+        assert!(last_text(&script.send("alice").await).starts_with("User IDs have 6 to 20"));
+        script.send("bob_the_builder").await;
+        let calls = script.tap(CallbackAction::ConfirmRegistration).await;
+        assert!(texts(&calls).iter().any(|text| text.starts_with("Welcome to TBank!")));
+
+        script.tap(CallbackAction::Register).await;
+        for answer in ["Lim Dave", "S7654322Z", "1985-02-28", "10 Anson Road", "079903", "87654321", "bob_the_builder"] {
+            script.send(answer).await;
+        }
+        let calls = script.tap(CallbackAction::ConfirmRegistration).await;
+        assert!(texts(&calls).contains(&"We could not register you. TBank rejected the request: Preferred user ID is already taken".to_owned()), "{:?}", texts(&calls));
+    }
+
+    #[tokio::test]
+    async fn transfers_between_own_accounts() {
+        let tbank = bank().account("alice", "101", "20.00");
+        let script = Script::new(&tbank).await;
+        script.login("alice", "123456").await;
+        script.tap(CallbackAction::Transfer).await;
+
+        let calls = script.tap(CallbackAction::TransferOwn).await;
+        assert_eq!(last_text(&calls), "Which of your accounts would you like to transfer to?");
+        let labels: Vec<String> = calls.last().unwrap().buttons.iter().map(|(label, _)| label.clone()).collect();
+        assert_eq!(labels, ["Transfer To My Account 100 (SGD 500.00)", "Transfer To My Account 101 (SGD 20.00)", "Back"]);
+
+        script.tap(CallbackAction::TransferTo { account_id: "101".to_owned() }).await;
+        let calls = script.send("5").await;
+        // The destination is not offered as the source
+        let sources: Vec<String> = calls.last().unwrap().buttons.iter().map(|(_, data)| data.clone()).collect();
+        assert_eq!(sources, ["1:tf:100", "1:bk"]);
+
+        script.tap(CallbackAction::TransferFrom { account_id: "100".to_owned() }).await;
+        let calls = script.tap(CallbackAction::Confirm).await;
+        assert!(texts(&calls).contains(&"The transfer has been done".to_owned()), "{:?}", calls);
+        assert_eq!(tbank.balance("100"), Some(Decimal::new(49500, 2)));
+        assert_eq!(tbank.balance("101"), Some(Decimal::new(2500, 2)));
+    }
+
+    #[tokio::test]
+    async fn a_single_account_has_nothing_to_transfer_to() {
+        let script = Script::new(&bank()).await;
+        script.login("alice", "123456").await;
+        script.tap(CallbackAction::Transfer).await;
+        assert_eq!(last_text(&script.tap(CallbackAction::TransferOwn).await), "You need at least two accounts to transfer between them.");
+    }
+
+    #[tokio::test]
+    async fn payees_are_listed_renamed_and_deleted() {
+        let tbank = bank().customer("carol", "111111").account("carol", "300", "0.00").beneficiary("alice", "300", "Carol");
+        let script = Script::new(&tbank).await;
+        script.login("alice", "123456").await;
+        let bob = tbank.beneficiaries_of("alice")[0].beneficiary_id.clone();
+
+        let calls = script.tap(CallbackAction::ManagePayees).await;
+        assert_eq!(last_text(&calls), "Which payee would you like to manage?");
+        let labels: Vec<String> = calls.last().unwrap().buttons.iter().map(|(label, _)| label.clone()).collect();
+        assert_eq!(labels, ["Bob 200", "Carol 300", "Add Beneficiary", "Back"]);
+
+        let view = last_text(&script.tap(CallbackAction::ViewPayee { beneficiary_id: bob.clone() }).await);
+        assert_eq!(view, "Payee: Bob\nAccount: 200\nCurrency: SGD");
+        assert_eq!(last_text(&script.tap(CallbackAction::RenamePayee { beneficiary_id: bob.clone() }).await), "New label for the payee?");
+        let calls = script.send("  Bobby  Tan ").await;
+        assert!(texts(&calls).contains(&"Payee has been renamed to Bobby Tan".to_owned()), "{:?}", calls);
+        assert_eq!(tbank.beneficiaries_of("alice")[0].description, "Bobby Tan");
+
+        script.tap(CallbackAction::ManagePayees).await;
+        script.tap(CallbackAction::ViewPayee { beneficiary_id: bob.clone() }).await;
+        let confirm = last_text(&script.tap(CallbackAction::DeletePayee { beneficiary_id: bob.clone() }).await);
+        assert_eq!(confirm, "Remove Bobby Tan (200) from your payees?");
+        // Nothing is removed until the deletion is confirmed
+        assert_eq!(tbank.beneficiaries_of("alice").len(), 2);
+        let calls = script.tap(CallbackAction::ConfirmDeletePayee { beneficiary_id: bob }).await;
+        assert!(texts(&calls).contains(&"Bobby Tan has been removed from your payees.".to_owned()), "{:?}", calls);
+        assert_eq!(tbank.beneficiaries_of("alice").len(), 1);
+        assert_eq!(tbank.beneficiaries_of("alice")[0].description, "Carol");
+    }
+
+    #[tokio::test]
+    async fn new_payees_are_checked_before_they_are_added() {
+        let tbank = bank().customer("carol", "111111").account("carol", "300", "0.00");
+        let script = Script::new(&tbank).await;
+        script.login("alice", "123456").await;
+        script.tap(CallbackAction::ManagePayees).await;
+        assert_eq!(last_text(&script.tap(CallbackAction::AddBeneficiary).await), "Key in account number to add?");
+
+        let retry = last_text(&script.send("Carol's").await);
+        assert_eq!(retry, "Account numbers only contain digits, up to 20 of them.\nKey in account number to add?");
+        assert_eq!(last_text(&script.send("300").await), "Label for the account?");
+        let calls = script.send("Carol").await;
+        assert!(texts(&calls).contains(&"Beneficiary has been added".to_owned()), "{:?}", calls);
+
+        script.tap(CallbackAction::ManagePayees).await;
+        script.tap(CallbackAction::AddBeneficiary).await;
+        script.send("200").await;
+        let calls = script.send("Bob again").await;
+        assert!(texts(&calls).contains(&"Account 200 is already one of your payees.".to_owned()), "{:?}", calls);
+        assert_eq!(tbank.beneficiaries_of("alice").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn balance_history_sends_a_chart() {
+        let script = Script::new(&bank()).await;
+        script.login("alice", "123456").await;
+        script.tap(CallbackAction::CheckBalance).await;
+
+        let calls = script.tap(CallbackAction::BalanceHistory { account_id: "100".to_owned() }).await;
+        assert_eq!(last_text(&calls), "How far back should the chart for 100 go?");
+        let labels: Vec<String> = calls.last().unwrap().buttons.iter().map(|(label, _)| label.clone()).collect();
+        assert_eq!(labels, ["3 months", "6 months", "12 months", "24 months", "Back"]);
+
+        let calls = script.tap(CallbackAction::ChartWindow { account_id: "100".to_owned(), months: 12 }).await;
+        let photo = calls.iter().find(|call| call.method == "sendPhoto").expect("no chart sent");
+        assert_eq!(photo.chat_id, Some(CHAT.0));
+        assert!(photo.files[0].content.starts_with(b"\x89PNG\r\n\x1a\n"), "chart is not a PNG");
+        assert_eq!(
+            last_text(&calls),
+            "100 balance over the past 12 months\n\nAverage: 500.00\nLowest: 500.00 (2022-10)\nHighest: 500.00 (2022-10)\nChange since 2023-09: +0.00 (+0.0%)"
+        );
+        let labels: Vec<String> = calls.last().unwrap().buttons.iter().map(|(label, _)| label.clone()).collect();
+        assert_eq!(labels, ["Compare All Accounts", "Back"]);
+    }
+
+    #[tokio::test]
+    async fn all_accounts_are_compared_on_one_chart() {
+        let tbank = bank().account("alice", "101", "0.00");
+        let script = Script::new(&tbank).await;
+        script.login("alice", "123456").await;
+        let alice = CustomerRequest {
+            service_name: "".to_owned(),
+            user_id: "alice".to_owned(),
+            pin: "123456".to_owned(),
+            otp: MOCK_OTP.to_owned(),
+        };
+        let to_savings = TransferBody {
+            account_from: "100".to_owned(),
+            account_to: "101".to_owned(),
+            transaction_amount: Money::new(Decimal::new(50, 0), HOME_CURRENCY),
+            transaction_reference_number: "".to_owned(),
+            narrative: "".to_owned(),
+        };
+        script.ctx.tbank_repository.transfer(alice, to_savings).await.unwrap();
+        script.tap(CallbackAction::CheckBalance).await;
+        script.tap(CallbackAction::BalanceHistory { account_id: "100".to_owned() }).await;
+        script.tap(CallbackAction::ChartWindow { account_id: "100".to_owned(), months: 3 }).await;
+
+        let calls = script.tap(CallbackAction::CompareAccounts { months: 3 }).await;
+        let photo = calls.iter().find(|call| call.method == "sendPhoto").expect("no chart sent");
+        assert!(photo.files[0].content.starts_with(b"\x89PNG\r\n\x1a\n"), "chart is not a PNG");
+        assert_eq!(
+            last_text(&calls),
+            "All accounts over the past 3 months\n\n\
+             Account 100\nAverage: 487.50\nLowest: 450.00 (2023-10)\nHighest: 500.00 (2023-07)\nChange since 2023-09: -50.00 (-10.0%)\n\n\
+             Account 101\nAverage: 12.50\nLowest: 0.00 (2023-07)\nHighest: 50.00 (2023-10)\nChange since 2023-09: +50.00"
+        );
+        assert_eq!(calls.last().unwrap().buttons, vec![("Back".to_owned(), "1:bk".to_owned())]);
+    }
+
+    #[tokio::test]
+    async fn remote_charts_come_from_the_chart_generator() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let generator = format!("http://{}/", listener.local_addr().unwrap());
+        let app = axum::Router::new().route("/", axum::routing::post(|| async { "drawn remotely" }));
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+        let script = Script::configured(&bank(), |config| {
+            config.chart_backend = ChartBackend::Remote;
+            config.chart_generator_url = Some(generator);
+        }).await;
+        script.login("alice", "123456").await;
+        script.tap(CallbackAction::CheckBalance).await;
+        script.tap(CallbackAction::BalanceHistory { account_id: "100".to_owned() }).await;
+
+        let calls = script.tap(CallbackAction::ChartWindow { account_id: "100".to_owned(), months: 6 }).await;
+        let photo = calls.iter().find(|call| call.method == "sendPhoto").expect("no chart sent");
+        assert_eq!(photo.files[0].content, b"drawn remotely");
+        // The chart generator only draws one account, so there is nothing to compare with
+        assert_eq!(calls.last().unwrap().buttons, vec![("Back".to_owned(), "1:bk".to_owned())]);
+    }
+
+    #[tokio::test]
+    async fn recent_transactions_page_through_the_history() {
+        let tbank = bank();
+        let script = Script::new(&tbank).await;
+        script.login("alice", "123456").await;
+        let alice = CustomerRequest {
+            service_name: "".to_owned(),
+            user_id: "alice".to_owned(),
+            pin: "123456".to_owned(),
+            otp: MOCK_OTP.to_owned(),
+        };
+        for amount in 1..=6 {
+            let transfer = TransferBody {
+                account_from: "100".to_owned(),
+                account_to: "200".to_owned(),
+                transaction_amount: Money::new(Decimal::new(amount * 10, 0), HOME_CURRENCY),
+                transaction_reference_number: format!("{}", amount),
+                narrative: format!("Lunch {}", amount),
+            };
+            script.ctx.tbank_repository.transfer(alice.clone(), transfer).await.unwrap();
+        }
+        script.tap(CallbackAction::CheckBalance).await;
+
+        let calls = script.tap(CallbackAction::RecentTransactions { account_id: "100".to_owned(), page: 1 }).await;
+        let first = last_text(&calls);
+        assert!(first.starts_with("Recent transactions for 100 (page 1)\n"), "{}", first);
+        assert!(first.contains("Lunch 6\n-60.00 SGD | Balance: 290.00 SGD"), "{}", first);
+        assert!(!first.contains("Lunch 1\n"), "{}", first);
+        let labels: Vec<_> = calls.last().unwrap().buttons.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, ["Next", "Back"]);
+
+        let calls = script.tap(CallbackAction::RecentTransactions { account_id: "100".to_owned(), page: 2 }).await;
+        let second = last_text(&calls);
+        assert!(second.contains("Lunch 1\n-10.00 SGD | Balance: 490.00 SGD"), "{}", second);
+        let labels: Vec<_> = calls.last().unwrap().buttons.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, ["Previous", "Back"]);
+    }
+
+    #[tokio::test]
+    async fn statements_are_sent_as_csv_and_pdf() {
+        let tbank = bank();
+        let script = Script::new(&tbank).await;
+        script.login("alice", "123456").await;
+        script.tap(CallbackAction::Transfer).await;
+        script.tap(CallbackAction::TransferOther).await;
+        script.tap(CallbackAction::TransferTo { account_id: "200".to_owned() }).await;
+        script.send("42").await;
+        script.tap(CallbackAction::TransferFrom { account_id: "100".to_owned() }).await;
+        script.tap(CallbackAction::Confirm).await;
+        script.tap(CallbackAction::CheckBalance).await;
+
+        let prompt = last_text(&script.tap(CallbackAction::DownloadStatement { account_id: "100".to_owned() }).await);
+        assert!(prompt.starts_with("Which period should the statement for 100 cover?"), "{}", prompt);
+
+        let (yesterday, today) = TelegramService::history_window(1);
+        let (yesterday, today) = (&yesterday[..10], &today[..10]);
+        let calls = script.send(&format!("{} to {}", yesterday, today)).await;
+        let documents: Vec<_> = calls.iter().filter(|call| call.method == "sendDocument").collect();
+        assert_eq!(documents.len(), 2, "{:?}", calls);
+
+        let stem = format!("statement-100-{}-{}", yesterday.replace('-', ""), today.replace('-', ""));
+        let csv = &documents[0].files[0];
+        assert_eq!(csv.name, format!("{}.csv", stem));
+        let csv = String::from_utf8(csv.content.clone()).unwrap();
+        assert!(csv.contains(",Simple Transfer,-42.00,458.00,SGD\r\n"), "{}", csv);
+
+        let pdf = &documents[1].files[0];
+        assert_eq!(pdf.name, format!("{}.pdf", stem));
+        assert!(pdf.content.starts_with(b"%PDF-1.4"));
+        assert_eq!(documents[1].text, Some(format!("Statement for 100 from {} to {}", yesterday, today)));
+        assert_eq!(last_text(&calls), MENU);
+    }
+
+    #[test]
+    fn an_empty_history_says_so() {
+        let (text, keyboard) = TelegramService::transactions_page("100", HOME_CURRENCY, 1, &[]);
+        assert_eq!(text, format!("Recent transactions for 100 (page 1)\n\nNo transactions in the past {} days.\n", HISTORY_WINDOW_DAYS));
+        assert_eq!(keyboard.inline_keyboard.len(), 1);
+    }
+
+    #[test]
+    fn history_rows_fall_back_to_the_account_currency_and_keep_odd_amounts() {
+        let row = |id: &str, amount: &str| TransactionDetail {
+            transaction_id: id.to_owned(),
+            account_from: "300".to_owned(),
+            account_to: "201".to_owned(),
+            transaction_amount: amount.to_owned(),
+            transaction_date: "2023-01-05T10:00:00".to_owned(),
+            narrative: None,
+            account_from_balance: None,
+            account_to_balance: Some("50".to_owned()),
+            currency: None,
+        };
+        let usd: Currency = "USD".parse().unwrap();
+        let (text, _) = TelegramService::transactions_page("201", usd, 1, &[row("1", "12"), row("2", "n/a")]);
+        assert!(text.contains("\n+12.00 USD | Balance: 50 USD\n"), "{}", text);
+        assert!(text.contains("\nn/a USD | Balance: 50 USD\n"), "{}", text);
+        assert!(!text.contains("+0.00"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn a_wrong_pin_goes_back_to_the_start() {
+        let script = Script::new(&bank()).await;
+
+        script.send("/start").await;
+        script.tap(CallbackAction::Login).await;
+        script.send("alice").await;
+        let calls = script.send("000000").await;
+        assert!(texts(&calls).iter().any(|text| text.starts_with("Sorry It seems like we could not authenticate you.")), "{:?}", calls);
+        assert_eq!(last_text(&calls), "Welcome to TBANK Bot! How can I help you today?");
+        assert!(script.ctx.session_service.session(CHAT).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn buttons_do_nothing_once_logged_out() {
+        let tbank = bank();
+        let script = Script::new(&tbank).await;
+        script.login("alice", "123456").await;
+
+        let calls = script.tap(CallbackAction::Logout).await;
+        assert_eq!(last_text(&calls), "Welcome to TBANK Bot! How can I help you today?");
+
+        let stale = CallbackAction::Transfer.encode().unwrap();
+        let q = script.telegram.callback(CHAT, MessageId(1), &stale);
+        Box::pin(TelegramService::callback_handler(script.bot.clone(), q, script.ctx.clone())).await.unwrap();
+        let calls = script.telegram.take_calls();
+        assert_eq!(last_text(&calls), "Welcome to TBANK Bot! How can I help you today?");
+        assert!(tbank.transfers().is_empty());
+    }
+}
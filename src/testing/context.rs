@@ -0,0 +1,36 @@
+use std::sync::Arc;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rust_decimal::Decimal;
+use crate::config::AppConfig;
+use crate::context::AppContext;
+use crate::enums::chart_backend::ChartBackend;
+use crate::enums::state_store::StateStoreKind;
+use crate::enums::update_mode::UpdateMode;
+
+/// Config for tests: in-memory state, a fixed credential key, local charts, and every remote service at `tbank_url`.
+pub fn test_config(tbank_url: &str) -> AppConfig {
+    AppConfig {
+        teloxide_token: "unused".to_owned(),
+        tbank_url: tbank_url.to_owned(),
+        redis_url: None,
+        state_store: StateStoreKind::Memory,
+        chart_backend: ChartBackend::Local,
+        chart_generator_url: Some(tbank_url.to_owned()),
+        chart_font_path: "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf".to_owned(),
+        credential_keys: format!("k1:{}", STANDARD.encode([7u8; 32])),
+        session_absolute_ttl_secs: 3_600,
+        session_idle_ttl_secs: 600,
+        step_up_threshold: Decimal::ONE_THOUSAND,
+        admin_chat_ids: "".to_owned(),
+        update_mode: UpdateMode::Polling,
+        webhook_url: None,
+        webhook_secret: None,
+        teller_user_id: "teller".to_owned(),
+        teller_pin: "4321".to_owned(),
+        admin_api_token: None,
+    }
+}
+
+pub async fn test_context(config: AppConfig) -> AppContext {
+    AppContext::new(Arc::new(config)).await.unwrap()
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use teloxide::types::{CallbackQuery, ChatId, Me, Message, MessageId};
+use teloxide::Bot;
+
+/// Token the fake accepts; any other token is answered with 401 like the real Bot API.
+pub const FAKE_BOT_TOKEN: &str = "123456:FAKE";
+const BOT_USER_ID: u64 = 123456;
+const BOT_USERNAME: &str = "tbank_test_bot";
+
+/// One Bot API method the bot called, with the parts scripts care about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BotCall {
+    pub method: String,
+    pub chat_id: Option<i64>,
+    pub message_id: Option<i32>,
+    pub text: Option<String>,
+    /// `(label, callback_data)` of each inline keyboard button, top to bottom.
+    pub buttons: Vec<(String, String)>,
+    /// Files uploaded with the call, such as photos and documents.
+    pub files: Vec<FakeFile>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FakeFile {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Chats {
+    calls: Vec<BotCall>,
+    last_message_id: HashMap<i64, i32>,
+    texts: HashMap<(i64, i32), String>,
+    keyboards: HashMap<(i64, i32), Vec<(String, String)>>,
+    next_update: u32,
+}
+
+impl Chats {
+    /// Telegram numbers messages per chat, shared by the user and the bot.
+    fn next_message_id(&mut self, chat_id: i64) -> i32 {
+        let id = self.last_message_id.entry(chat_id).or_default();
+        *id += 1;
+        *id
+    }
+}
+
+/// Local stand-in for the Telegram Bot API that records what the bot sends,
+/// and builds the updates a user would trigger.
+#[derive(Clone, Default)]
+pub struct FakeTelegram {
+    chats: Arc<Mutex<Chats>>,
+}
+
+impl FakeTelegram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/:token/:method", post(Self::handle))
+            .with_state(self.clone())
+    }
+
+    /// Serves the fake on a free local port and returns a bot pointed at it.
+    pub fn spawn(&self) -> Bot {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let app = self.router();
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+        let url = reqwest::Url::parse(&format!("http://{}/", addr)).unwrap();
+        Bot::new(FAKE_BOT_TOKEN).set_api_url(url)
+    }
+
+    pub fn me(&self) -> Me {
+        serde_json::from_value(json!({
+            "id": BOT_USER_ID,
+            "is_bot": true,
+            "first_name": "TBank",
+            "username": BOT_USERNAME,
+            "can_join_groups": false,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": false,
+        })).unwrap()
+    }
+
+    /// A text message typed by the user into the chat.
+    pub fn user_message(&self, chat_id: ChatId, text: &str) -> Message {
+        let id = self.chats.lock().unwrap().next_message_id(chat_id.0);
+        let mut message = Self::message_json(chat_id.0, id, Some(text), false);
+        message["from"] = Self::user_json(chat_id.0);
+        serde_json::from_value(message).unwrap()
+    }
+
+    /// A tap on an inline button of one of the bot's messages.
+    pub fn callback(&self, chat_id: ChatId, message_id: MessageId, data: &str) -> CallbackQuery {
+        let mut chats = self.chats.lock().unwrap();
+        chats.next_update += 1;
+        let text = chats.texts.get(&(chat_id.0, message_id.0)).cloned();
+        serde_json::from_value(json!({
+            "id": chats.next_update.to_string(),
+            "from": Self::user_json(chat_id.0),
+            "message": Self::message_json(chat_id.0, message_id.0, text.as_deref(), false),
+            "chat_instance": chat_id.0.to_string(),
+            "data": data,
+        })).unwrap()
+    }
+
+    pub fn calls(&self) -> Vec<BotCall> {
+        self.chats.lock().unwrap().calls.clone()
+    }
+
+    /// Calls recorded since the last time this was called.
+    pub fn take_calls(&self) -> Vec<BotCall> {
+        std::mem::take(&mut self.chats.lock().unwrap().calls)
+    }
+
+    /// Current text of a message in the chat, after any edits.
+    pub fn text_of(&self, chat_id: ChatId, message_id: MessageId) -> Option<String> {
+        self.chats.lock().unwrap().texts.get(&(chat_id.0, message_id.0)).cloned()
+    }
+
+    /// Newest message in the chat still showing a button with this callback data.
+    pub fn message_with_button(&self, chat_id: ChatId, data: &str) -> Option<MessageId> {
+        let chats = self.chats.lock().unwrap();
+        chats.keyboards
+            .iter()
+            .filter(|((chat, _), buttons)| *chat == chat_id.0 && buttons.iter().any(|(_, button)| button == data))
+            .map(|((_, id), _)| *id)
+            .max()
+            .map(MessageId)
+    }
+
+    async fn handle(
+        State(fake): State<FakeTelegram>,
+        Path((token, method)): Path<(String, String)>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> (axum::http::StatusCode, Json<Value>) {
+        if token != format!("bot{}", FAKE_BOT_TOKEN) {
+            return (
+                axum::http::StatusCode::UNAUTHORIZED,
+                Json(json!({ "ok": false, "error_code": 401, "description": "Unauthorized" })),
+            );
+        }
+        // Method names are case-insensitive; teloxide sends them capitalised
+        let mut method = method;
+        if let Some(first) = method.get_mut(..1) {
+            first.make_ascii_lowercase();
+        }
+        let content_type = headers
+            .get(axum::http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let (params, files) = match content_type.split_once("boundary=") {
+            Some((_, boundary)) => Self::multipart(&body, boundary.trim_matches('"')),
+            None => (serde_json::from_slice(&body).unwrap_or(Value::Null), vec![]),
+        };
+
+        let chat_id = match &params["chat_id"] {
+            Value::Number(id) => id.as_i64(),
+            Value::String(id) => id.parse().ok(),
+            _ => None,
+        };
+        let text = params["text"].as_str().or(params["caption"].as_str()).map(str::to_owned);
+        let mut message_id = params["message_id"].as_i64().map(|id| id as i32);
+        let buttons = Self::buttons(&params["reply_markup"]);
+
+        let mut chats = fake.chats.lock().unwrap();
+        let result = match (method.as_str(), chat_id) {
+            ("sendMessage", Some(chat_id)) | ("sendPhoto", Some(chat_id)) | ("sendDocument", Some(chat_id)) => {
+                let id = chats.next_message_id(chat_id);
+                message_id = Some(id);
+                if let Some(text) = &text {
+                    chats.texts.insert((chat_id, id), text.clone());
+                }
+                chats.keyboards.insert((chat_id, id), buttons.clone());
+                let mut message = Self::message_json(chat_id, id, text.as_deref(), method == "sendPhoto");
+                if method == "sendDocument" {
+                    let name = files.first().map(|file| file.name.clone()).unwrap_or_default();
+                    message["document"] = json!({ "file_id": name, "file_unique_id": name, "file_name": name });
+                    message["caption"] = message["text"].take();
+                    message.as_object_mut().unwrap().remove("text");
+                }
+                message
+            }
+            ("editMessageText", Some(chat_id)) => {
+                let id = message_id.unwrap_or_default();
+                chats.texts.insert((chat_id, id), text.clone().unwrap_or_default());
+                // Editing without a markup drops the old keyboard, as Telegram does
+                chats.keyboards.insert((chat_id, id), buttons.clone());
+                Self::message_json(chat_id, id, text.as_deref(), false)
+            }
+            ("deleteMessage", Some(chat_id)) => {
+                chats.texts.remove(&(chat_id, message_id.unwrap_or_default()));
+                chats.keyboards.remove(&(chat_id, message_id.unwrap_or_default()));
+                json!(true)
+            }
+            ("answerCallbackQuery", _) => json!(true),
+            _ => {
+                return (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    Json(json!({ "ok": false, "error_code": 400, "description": format!("Bad Request: {} is not faked", method) })),
+                );
+            }
+        };
+        chats.calls.push(BotCall {
+            method,
+            chat_id,
+            message_id,
+            text,
+            buttons,
+            files,
+        });
+        (axum::http::StatusCode::OK, Json(json!({ "ok": true, "result": result })))
+    }
+
+    fn buttons(reply_markup: &Value) -> Vec<(String, String)> {
+        // Multipart requests carry the markup as a JSON string
+        let reply_markup = match reply_markup {
+            Value::String(raw) => serde_json::from_str(raw).unwrap_or(Value::Null),
+            other => other.clone(),
+        };
+        reply_markup["inline_keyboard"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_array)
+            .flatten()
+            .map(|button| (
+                button["text"].as_str().unwrap_or_default().to_owned(),
+                button["callback_data"].as_str().unwrap_or_default().to_owned(),
+            ))
+            .collect()
+    }
+
+    /// Splits a `multipart/form-data` body into its text fields and its files.
+    fn multipart(body: &[u8], boundary: &str) -> (Value, Vec<FakeFile>) {
+        let delimiter = format!("--{}", boundary).into_bytes();
+        let mut fields = serde_json::Map::new();
+        let mut files = vec![];
+        for part in Self::split(body, &delimiter) {
+            let part = part.strip_prefix(b"\r\n").unwrap_or(part);
+            let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+            let Some(header_end) = part.windows(4).position(|window| window == b"\r\n\r\n") else {
+                continue;
+            };
+            let headers = String::from_utf8_lossy(&part[..header_end]);
+            let value = &part[header_end + 4..];
+            let attribute = |key: &str| {
+                headers.split(&format!("{}=\"", key)).nth(1).and_then(|rest| rest.split('"').next()).map(str::to_owned)
+            };
+            match (attribute("filename"), attribute("name")) {
+                (Some(name), _) => files.push(FakeFile { name, content: value.to_vec() }),
+                (None, Some(name)) => {
+                    fields.insert(name, Value::String(String::from_utf8_lossy(value).into_owned()));
+                }
+                (None, None) => {}
+            }
+        }
+        (Value::Object(fields), files)
+    }
+
+    fn split<'a>(body: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+        let mut parts = vec![];
+        let mut rest = body;
+        while let Some(position) = rest.windows(delimiter.len()).position(|window| window == delimiter) {
+            parts.push(&rest[..position]);
+            rest = &rest[position + delimiter.len()..];
+        }
+        parts.push(rest);
+        parts
+    }
+
+    fn user_json(chat_id: i64) -> Value {
+        json!({ "id": chat_id, "is_bot": false, "first_name": "Tester" })
+    }
+
+    fn message_json(chat_id: i64, message_id: i32, text: Option<&str>, photo: bool) -> Value {
+        let mut message = json!({
+            "message_id": message_id,
+            "date": 0,
+            "chat": { "id": chat_id, "type": "private", "first_name": "Tester" },
+            "from": { "id": BOT_USER_ID, "is_bot": true, "first_name": "TBank", "username": BOT_USERNAME },
+        });
+        if photo {
+            message["photo"] = json!([{ "file_id": "photo", "file_unique_id": "photo", "width": 1, "height": 1, "file_size": 1 }]);
+        } else {
+            message["text"] = json!(text.unwrap_or_default());
+        }
+        message
+    }
+}
@@ -0,0 +1,594 @@
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::extract::{Query, State};
+use axum::routing::post;
+use axum::{Json, Router};
+use chrono::DateTime;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+
+/// OTP the mock issues on `requestOTP`, and the placeholder the bot keeps after login.
+pub const MOCK_OTP: &str = "999999";
+/// Month the mock treats as the current one for balance trends.
+pub const MOCK_CURRENT_MONTH: (i32, u32) = (2023, 10);
+
+#[derive(Debug, Clone)]
+pub struct MockAccount {
+    pub account_id: String,
+    pub owner: String,
+    pub balance: Decimal,
+    pub product_id: String,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockBeneficiary {
+    pub beneficiary_id: String,
+    pub account_id: String,
+    pub description: String,
+    pub group: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockTransfer {
+    pub account_from: String,
+    pub account_to: String,
+    pub amount: Decimal,
+    pub reference: String,
+    pub narrative: String,
+    //yyyy-MM-dd HH:mm:ss, when the mock received it
+    pub date: String,
+    pub from_balance: Decimal,
+    pub to_balance: Decimal,
+}
+
+#[derive(Debug, Clone)]
+struct MockCustomer {
+    pin: String,
+    certificate_no: String,
+    customer_id: String,
+    otp_requested: bool,
+    beneficiaries: Vec<MockBeneficiary>,
+}
+
+#[derive(Debug, Default)]
+struct Bank {
+    customers: HashMap<String, MockCustomer>,
+    accounts: Vec<MockAccount>,
+    transfers: Vec<MockTransfer>,
+    tellers: HashMap<String, String>,
+    next_account: u64,
+    next_beneficiary: u64,
+}
+
+impl Bank {
+    fn next_beneficiary_id(&mut self) -> String {
+        self.next_beneficiary += 1;
+        format!("{}", self.next_beneficiary)
+    }
+
+    fn currency_of(&self, account_id: &str) -> String {
+        self.accounts.iter()
+            .find(|account| account.account_id == account_id)
+            .map(|account| account.currency.clone())
+            .unwrap_or_else(|| "SGD".to_owned())
+    }
+}
+
+/// In-memory TBank speaking the `Header=`/`Content=`/`ConsumerID=` protocol, for offline tests.
+#[derive(Clone, Default)]
+pub struct MockTBank {
+    bank: Arc<Mutex<Bank>>,
+}
+
+impl MockTBank {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn customer(self, user_id: &str, pin: &str) -> Self {
+        {
+            let mut bank = self.bank.lock().unwrap();
+            let number = bank.customers.len() + 1;
+            bank.customers.insert(user_id.to_owned(), MockCustomer {
+                pin: pin.to_owned(),
+                certificate_no: format!("S000000{}A", number),
+                customer_id: format!("{}", 1000 + number),
+                otp_requested: false,
+                beneficiaries: vec![],
+            });
+        }
+        self
+    }
+
+    /// `balance` is a figure the way TBank writes it, such as `"500.00"`.
+    pub fn account(self, user_id: &str, account_id: &str, balance: &str) -> Self {
+        self.account_in(user_id, account_id, balance, "SGD")
+    }
+
+    pub fn account_in(self, user_id: &str, account_id: &str, balance: &str, currency: &str) -> Self {
+        self.bank.lock().unwrap().accounts.push(MockAccount {
+            account_id: account_id.to_owned(),
+            owner: user_id.to_owned(),
+            balance: balance.parse().expect("mock balances are figures"),
+            product_id: "101".to_owned(),
+            currency: currency.to_owned(),
+        });
+        self
+    }
+
+    pub fn beneficiary(self, user_id: &str, account_id: &str, description: &str) -> Self {
+        {
+            let mut bank = self.bank.lock().unwrap();
+            let group = Self::group_for(&bank, user_id, account_id);
+            let beneficiary_id = bank.next_beneficiary_id();
+            bank.customers.get_mut(user_id).expect("unknown mock customer").beneficiaries.push(MockBeneficiary {
+                beneficiary_id,
+                account_id: account_id.to_owned(),
+                description: description.to_owned(),
+                group,
+            });
+        }
+        self
+    }
+
+    pub fn teller(self, user_id: &str, pin: &str) -> Self {
+        self.bank.lock().unwrap().tellers.insert(user_id.to_owned(), pin.to_owned());
+        self
+    }
+
+    pub fn balance(&self, account_id: &str) -> Option<Decimal> {
+        let bank = self.bank.lock().unwrap();
+        bank.accounts.iter().find(|account| account.account_id == account_id).map(|account| account.balance)
+    }
+
+    /// The PIN TBank gave the customer, as they would collect it from the bank.
+    pub fn pin_of(&self, user_id: &str) -> Option<String> {
+        let bank = self.bank.lock().unwrap();
+        bank.customers.get(user_id).map(|customer| customer.pin.clone())
+    }
+
+    pub fn accounts_of(&self, user_id: &str) -> Vec<MockAccount> {
+        let bank = self.bank.lock().unwrap();
+        bank.accounts.iter().filter(|account| account.owner == user_id).cloned().collect()
+    }
+
+    pub fn beneficiaries_of(&self, user_id: &str) -> Vec<MockBeneficiary> {
+        let bank = self.bank.lock().unwrap();
+        bank.customers.get(user_id).map(|customer| customer.beneficiaries.clone()).unwrap_or_default()
+    }
+
+    pub fn transfers(&self) -> Vec<MockTransfer> {
+        self.bank.lock().unwrap().transfers.clone()
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/", post(Self::handle))
+            .with_state(self.clone())
+    }
+
+    /// Serves the mock on a free local port and returns its address.
+    pub fn spawn(&self) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = self.router();
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+        addr
+    }
+
+    async fn handle(State(mock): State<MockTBank>, Query(query): Query<HashMap<String, String>>) -> Json<Value> {
+        let parse = |name: &str| query.get(name).and_then(|raw| serde_json::from_str::<Value>(raw).ok());
+        let Some(header) = parse("Header") else {
+            return Self::reply(Err("Header is missing"));
+        };
+        // Some services nest their content under a `Content` key and the rest send it bare
+        let content = parse("Content")
+            .map(|content| content.get("Content").cloned().unwrap_or(content))
+            .unwrap_or(Value::Null);
+        let service = header["serviceName"].as_str().unwrap_or_default().to_owned();
+        let user_id = header["userID"].as_str().unwrap_or_default().to_owned();
+        let pin = header["PIN"].as_str().unwrap_or_default().to_owned();
+        let otp = header["OTP"].as_str().unwrap_or_default().to_owned();
+
+        let mut bank = mock.bank.lock().unwrap();
+        let result = match service.as_str() {
+            "requestOTP" => Self::authenticate(&mut bank, &user_id, &pin).map(|customer| {
+                customer.otp_requested = true;
+                json!({})
+            }),
+            "loginCustomer" => Self::authenticate(&mut bank, &user_id, &pin).and_then(|customer| {
+                if !customer.otp_requested || otp != MOCK_OTP {
+                    return Err("Invalid OTP");
+                }
+                Ok(json!({
+                    "Login_OTP_Authenticate-Response": {"CustomerID": customer.customer_id, "BankID": "1"}
+                }))
+            }),
+            "getCustomerAccounts" => Self::customer_accounts(&mut bank, &user_id, &pin),
+            "getCustomerDetails" => Self::authenticate(&mut bank, &user_id, &pin).map(|customer| json!({
+                "CDMCustomer": {
+                    "address": {"country": "SG", "city": "Singapore", "postalCode": "000000", "streetAddress1": "1 Mock Street", "streetAddress2": null, "state": null},
+                    "phone": {"areaCode": null, "countryCode": "65", "localNumber": "61234567"},
+                    "profile": {"customerType": "Retail", "bankID": "1", "occupation": null, "nationality": "SG", "gender": null,
+                        "isBillingOrg": "false", "isMerchant": "false", "ethnicGroup": null, "fax": null, "email": null},
+                    "familyName": "Tan",
+                    "givenName": user_id,
+                    "certificate": {"certificateExpiryDate": null, "certificateIssuer": null, "certificateNo": customer.certificate_no, "certificateType": "NRIC"},
+                    "taxIdentifier": "",
+                    "cellphone": {"phoneNumber": "91234567", "countryCode": "65"},
+                    "maintenacehistory": {"registrationDate": "2023-01-01", "lastMaintenanceTellerID": null},
+                    "dateOfBirth": "1990-01-01",
+                    "customer": {"customerID": customer.customer_id}
+                }
+            })),
+            "getBeneficiaryList" => Self::authenticate(&mut bank, &user_id, &pin).map(|customer| customer.beneficiaries.clone()).map(|beneficiaries| {
+                let group = content["accountGroup"].as_str().unwrap_or("OTHER");
+                let beneficiaries: Vec<Value> = beneficiaries.iter()
+                    .filter(|beneficiary| beneficiary.group == group)
+                    .map(|beneficiary| json!({
+                        "AccountID": beneficiary.account_id,
+                        "Description": beneficiary.description,
+                        "Currency": bank.currency_of(&beneficiary.account_id),
+                        "BeneficiaryID": beneficiary.beneficiary_id,
+                    }))
+                    .collect();
+                if beneficiaries.is_empty() {
+                    json!({"BeneficiaryList": null})
+                } else {
+                    json!({"BeneficiaryList": {"Beneficiary": Self::one_or_many(beneficiaries)}})
+                }
+            }),
+            "addBeneficiary" => Self::add_beneficiary(&mut bank, &user_id, &pin, &content),
+            "updateBeneficiary" | "deleteBeneficiary" => Self::authenticate(&mut bank, &user_id, &pin).and_then(|customer| {
+                let beneficiary_id = content["BeneficiaryID"].as_str().unwrap_or_default();
+                let index = customer.beneficiaries.iter()
+                    .position(|beneficiary| beneficiary.beneficiary_id == beneficiary_id)
+                    .ok_or("Beneficiary not found")?;
+                if service == "deleteBeneficiary" {
+                    customer.beneficiaries.remove(index);
+                } else {
+                    customer.beneficiaries[index].description = content["Description"].as_str().unwrap_or_default().to_owned();
+                }
+                Ok(json!({}))
+            }),
+            "creditTransfer" => Self::credit_transfer(&mut bank, &user_id, &pin, &otp, &content),
+            "openDepositAccount" => Self::open_deposit_account(&mut bank, &user_id, &content),
+            "getMonthlyBalanceTrend" => Self::monthly_balance_trend(&mut bank, &user_id, &pin, &content),
+            "getTransactionHistory" => Self::transaction_history(&mut bank, &user_id, &pin, &content),
+            "onboardCustomer" => return Self::reply_flat(Self::onboard_customer(&mut bank, &content)),
+            "depositCash" | "withdrawCash" => {
+                return Self::reply_flat(Self::move_cash(&mut bank, &service, &user_id, &pin, &content));
+            }
+            _ => Err("Service not found"),
+        };
+        Self::reply(result)
+    }
+
+    fn authenticate<'a>(bank: &'a mut Bank, user_id: &str, pin: &str) -> Result<&'a mut MockCustomer, &'static str> {
+        match bank.customers.get_mut(user_id) {
+            Some(customer) if customer.pin == pin => Ok(customer),
+            Some(_) => Err("Invalid PIN"),
+            None => Err("User not found"),
+        }
+    }
+
+    fn customer_accounts(bank: &mut Bank, user_id: &str, pin: &str) -> Result<Value, &'static str> {
+        Self::authenticate(bank, user_id, pin)?;
+        let accounts: Vec<Value> = bank.accounts.iter()
+            .filter(|account| account.owner == user_id)
+            .map(Self::account_json)
+            .collect();
+        Ok(json!({"AccountList": {"account": Self::one_or_many(accounts)}}))
+    }
+
+    fn add_beneficiary(bank: &mut Bank, user_id: &str, pin: &str, content: &Value) -> Result<Value, &'static str> {
+        Self::authenticate(bank, user_id, pin)?;
+        let account_id = content["AccountID"].as_str().unwrap_or_default().to_owned();
+        if !bank.accounts.iter().any(|account| account.account_id == account_id) {
+            return Err("Account not found");
+        }
+        let group = Self::group_for(bank, user_id, &account_id);
+        let beneficiary_id = bank.next_beneficiary_id();
+        let customer = Self::authenticate(bank, user_id, pin)?;
+        if customer.beneficiaries.iter().any(|beneficiary| beneficiary.account_id == account_id) {
+            return Err("Beneficiary already exists");
+        }
+        customer.beneficiaries.push(MockBeneficiary {
+            beneficiary_id,
+            account_id,
+            description: content["Description"].as_str().unwrap_or_default().to_owned(),
+            group,
+        });
+        Ok(json!({}))
+    }
+
+    fn credit_transfer(bank: &mut Bank, user_id: &str, pin: &str, otp: &str, content: &Value) -> Result<Value, &'static str> {
+        Self::authenticate(bank, user_id, pin)?;
+        if otp != MOCK_OTP {
+            return Err("Invalid OTP");
+        }
+        let field = |name: &str| content[name].as_str().unwrap_or_default().to_owned();
+        let (account_from, account_to) = (field("accountFrom"), field("accountTo"));
+        let amount = match field("transactionAmount").parse::<Decimal>() {
+            Ok(amount) if amount > Decimal::ZERO => amount,
+            _ => return Err("Invalid transaction amount"),
+        };
+        let from = bank.accounts.iter().position(|account| account.account_id == account_from && account.owner == user_id)
+            .ok_or("Account not found")?;
+        let to = bank.accounts.iter().position(|account| account.account_id == account_to)
+            .ok_or("Account not found")?;
+        // The documented creditTransfer moves one currency only
+        if bank.accounts[from].currency != bank.accounts[to].currency {
+            return Err("Currency mismatch");
+        }
+        if bank.accounts[from].balance < amount {
+            return Err("Insufficient funds");
+        }
+        bank.accounts[from].balance -= amount;
+        bank.accounts[to].balance += amount;
+        bank.transfers.push(MockTransfer {
+            account_from,
+            account_to,
+            amount,
+            reference: field("transactionReferenceNumber"),
+            narrative: field("narrative"),
+            date: Self::now(),
+            from_balance: bank.accounts[from].balance,
+            to_balance: bank.accounts[to].balance,
+        });
+        Ok(json!({}))
+    }
+
+    /// Teller service: the bot sends the customer's certificate number as the userID.
+    fn open_deposit_account(bank: &mut Bank, certificate_no: &str, content: &Value) -> Result<Value, &'static str> {
+        let owner = bank.customers.iter()
+            .find(|(_, customer)| customer.certificate_no == certificate_no)
+            .map(|(user_id, _)| user_id.clone())
+            .ok_or("Customer not found")?;
+        bank.next_account += 1;
+        let account_id = format!("9{:09}", bank.next_account);
+        bank.accounts.push(MockAccount {
+            account_id: account_id.clone(),
+            owner,
+            balance: content["openingBalance"].as_str().and_then(|balance| balance.parse().ok()).unwrap_or_default(),
+            product_id: content["productID"].as_str().unwrap_or("101").to_owned(),
+            currency: content["currency"].as_str().unwrap_or("SGD").to_owned(),
+        });
+        Ok(json!({"accountID": {"_content_": account_id}}))
+    }
+
+    /// Replays the account's transfers backwards to find each month end balance.
+    /// All mock transfers happen in the current month, so earlier months share one balance.
+    fn monthly_balance_trend(bank: &mut Bank, user_id: &str, pin: &str, content: &Value) -> Result<Value, &'static str> {
+        Self::authenticate(bank, user_id, pin)?;
+        let account_id = content["accountID"].as_str().unwrap_or_default();
+        let months = content["numMonths"].as_str().and_then(|months| months.parse::<u32>().ok()).unwrap_or(6);
+        let account = bank.accounts.iter()
+            .find(|account| account.account_id == account_id && account.owner == user_id)
+            .ok_or("Account not found")?;
+        let net_this_month: Decimal = bank.transfers.iter()
+            .map(|transfer| {
+                let incoming = if transfer.account_to == account_id { transfer.amount } else { Decimal::ZERO };
+                let outgoing = if transfer.account_from == account_id { transfer.amount } else { Decimal::ZERO };
+                incoming - outgoing
+            })
+            .sum();
+        let before = account.balance - net_this_month;
+        let (year, month) = MOCK_CURRENT_MONTH;
+        let month_end_balance: Vec<Value> = (1..=months).rev()
+            .map(|back| {
+                let index = year * 12 + month as i32 - 1 - back as i32;
+                json!({
+                    "Year_Month": format!("{}-{:02}", index / 12, index % 12 + 1),
+                    "Balance": format!("{:.2}", before),
+                })
+            })
+            .collect();
+        Ok(json!({
+            "TrendData": {
+                "MonthEndBalance": month_end_balance,
+                "CurrentMonth": {"Year_Month": format!("{}-{:02}", year, month), "Balance": format!("{:.2}", account.balance)}
+            }
+        }))
+    }
+
+    /// Registers the customer with a fresh PIN and opens their first account.
+    fn onboard_customer(bank: &mut Bank, content: &Value) -> Result<Value, &'static str> {
+        let field = |name: &str| content[name].as_str().unwrap_or_default().to_owned();
+        let user_id = field("preferredUserID");
+        let certificate_no = field("IC_number");
+        if user_id.is_empty() || certificate_no.is_empty() {
+            return Err("Mandatory field missing");
+        }
+        if bank.customers.contains_key(&user_id) {
+            return Err("Preferred user ID is already taken");
+        }
+        if bank.customers.values().any(|customer| customer.certificate_no == certificate_no) {
+            return Err("Customer with this IC number already exists");
+        }
+        let number = bank.customers.len() + 1;
+        let customer_id = format!("{}", 1000 + number);
+        let pin = format!("{:06}", 246800 + number);
+        bank.customers.insert(user_id.clone(), MockCustomer {
+            pin: pin.clone(),
+            certificate_no,
+            customer_id: customer_id.clone(),
+            otp_requested: false,
+            beneficiaries: vec![],
+        });
+        bank.next_account += 1;
+        let account_id = format!("9{:09}", bank.next_account);
+        bank.accounts.push(MockAccount {
+            account_id: account_id.clone(),
+            owner: user_id,
+            balance: Decimal::ZERO,
+            product_id: "101".to_owned(),
+            currency: field("currency"),
+        });
+        Ok(json!({
+            "CustomerID": customer_id,
+            "AccountID": account_id,
+            "branchID": "1",
+            "PIN": pin,
+        }))
+    }
+
+    fn authenticate_teller(bank: &Bank, teller: &str, pin: &str) -> Result<(), &'static str> {
+        if bank.tellers.get(teller).map(String::as_str) != Some(pin) {
+            return Err("Invalid teller PIN");
+        }
+        Ok(())
+    }
+
+    /// Teller services. Cash is recorded as a transfer with an empty account on the counter's side.
+    fn move_cash(bank: &mut Bank, service: &str, teller: &str, pin: &str, content: &Value) -> Result<Value, &'static str> {
+        Self::authenticate_teller(bank, teller, pin)?;
+        let field = |name: &str| content[name].as_str().unwrap_or_default().to_owned();
+        let account_id = field("accountID");
+        let amount = match field("amount").parse::<Decimal>() {
+            Ok(amount) if amount > Decimal::ZERO => amount,
+            _ => return Err("Invalid amount"),
+        };
+        let index = bank.accounts.iter().position(|account| account.account_id == account_id)
+            .ok_or("Account not found")?;
+        let before = bank.accounts[index].balance;
+        let deposit = service == "depositCash";
+        if !deposit && before < amount {
+            return Err("Insufficient funds");
+        }
+        let after = if deposit { before + amount } else { before - amount };
+        bank.accounts[index].balance = after;
+        let (account_from, account_to) = if deposit { ("".to_owned(), account_id) } else { (account_id, "".to_owned()) };
+        bank.transfers.push(MockTransfer {
+            account_from,
+            account_to,
+            amount,
+            reference: "".to_owned(),
+            narrative: field("narrative"),
+            date: Self::now(),
+            from_balance: if deposit { Decimal::ZERO } else { after },
+            to_balance: if deposit { after } else { Decimal::ZERO },
+        });
+        Ok(json!({
+            "BalanceBefore": format!("{:.2}", before),
+            "BalanceAfter": format!("{:.2}", after),
+            "TransactionID": format!("{}", bank.transfers.len()),
+        }))
+    }
+
+    /// Transfers in or out of the account within the date range, newest first, one page at a time.
+    fn transaction_history(bank: &mut Bank, user_id: &str, pin: &str, content: &Value) -> Result<Value, &'static str> {
+        Self::authenticate(bank, user_id, pin)?;
+        let field = |name: &str| content[name].as_str().unwrap_or_default();
+        let account_id = field("accountID");
+        if !bank.accounts.iter().any(|account| account.account_id == account_id && account.owner == user_id) {
+            return Err("Account not found");
+        }
+        let per_page = field("numRecordsPerPage").parse::<usize>().map_err(|_| "Invalid numRecordsPerPage")?;
+        let page = field("pageNo").parse::<usize>().ok().filter(|page| *page > 0).ok_or("Invalid pageNo")?;
+        let (start, end) = (field("startDate"), field("endDate"));
+        let transactions: Vec<Value> = bank.transfers.iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, transfer)| transfer.account_from == account_id || transfer.account_to == account_id)
+            .filter(|(_, transfer)| transfer.date.as_str() >= start && transfer.date.as_str() <= end)
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .map(|(index, transfer)| json!({
+                "transactionID": format!("{}", index + 1),
+                "accountFrom": transfer.account_from,
+                "accountTo": transfer.account_to,
+                "transactionAmount": format!("{:.2}", transfer.amount),
+                "transactionDate": transfer.date.replacen(' ', "T", 1),
+                "narrative": transfer.narrative,
+                "accountFromBalance": format!("{:.2}", transfer.from_balance),
+                "accountToBalance": format!("{:.2}", transfer.to_balance),
+                "currency": "SGD",
+            }))
+            .collect();
+        if transactions.is_empty() {
+            return Ok(json!({"CDMTransactionDetail": null}));
+        }
+        Ok(json!({"CDMTransactionDetail": {"transaction_Detail": Self::one_or_many(transactions)}}))
+    }
+
+    fn now() -> String {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+        DateTime::from_timestamp(secs as i64, 0).unwrap_or_default().format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+
+    fn group_for(bank: &Bank, user_id: &str, account_id: &str) -> String {
+        let own = bank.accounts.iter().any(|account| account.account_id == account_id && account.owner == user_id);
+        if own { "OWN" } else { "OTHER" }.to_owned()
+    }
+
+    fn account_json(account: &MockAccount) -> Value {
+        json!({
+            "interestRate": "0.05",
+            "accountID": account.account_id,
+            "parentAccountFlag": "false",
+            "balance": format!("{:.2}", account.balance),
+            "productID": account.product_id,
+            "currentStatus": "Active",
+            "currency": account.currency,
+            "homeBranch": "1",
+            "accountOpenDate": "2023-01-01",
+            "maintenancehistory": {"lastTransactionBranch": "1", "lastMaintenanceOfficer": "1"},
+            "officerID": "1",
+        })
+    }
+
+    /// The GlobalErrorID for a failure; those `TBankError` knows by code, the rest share a generic one.
+    fn error_code(details: &str) -> &'static str {
+        match details {
+            "Insufficient funds" => "010011",
+            "Invalid PIN" | "Invalid teller PIN" => "010012",
+            "Invalid OTP" => "010013",
+            "Account not found" => "010014",
+            _ => "010041",
+        }
+    }
+
+    /// TBank sends a bare object for single element lists.
+    fn one_or_many(mut items: Vec<Value>) -> Value {
+        if items.len() == 1 { items.remove(0) } else { Value::Array(items) }
+    }
+
+    /// Cash services put their status next to the body instead of in a ServiceRespHeader.
+    fn reply_flat(result: Result<Value, &str>) -> Json<Value> {
+        let body = match result {
+            Ok(mut body) => {
+                body["ErrorText"] = json!("invocation successful");
+                body["ErrorDetails"] = json!("Success");
+                body["GlobalErrorID"] = json!("010000");
+                body
+            }
+            Err(details) => json!({
+                "ErrorText": "invocation failed",
+                "ErrorDetails": details,
+                "GlobalErrorID": Self::error_code(details),
+            }),
+        };
+        Json(json!({"Content": {"ServiceResponse": body}}))
+    }
+
+    fn reply(result: Result<Value, &str>) -> Json<Value> {
+        let (mut body, header) = match result {
+            Ok(body) => (body, json!({
+                "ErrorText": "invocation successful",
+                "ErrorDetails": "Success",
+                "GlobalErrorID": "010000",
+            })),
+            Err(details) => (json!({}), json!({
+                "ErrorText": "invocation failed",
+                "ErrorDetails": details,
+                "GlobalErrorID": Self::error_code(details),
+            })),
+        };
+        body["ServiceRespHeader"] = header;
+        Json(json!({"Content": {"ServiceResponse": body}}))
+    }
+}
@@ -0,0 +1,3 @@
+pub mod mock_tbank;
+pub mod fake_telegram;
+pub mod context;
\ No newline at end of file